@@ -0,0 +1,13 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    compile_protos().expect("Failed to compile proto/bitcask.proto");
+}
+
+/// Generates the gRPC messages, server and client, with a vendored protoc so none needs to be installed.
+#[cfg(feature = "grpc")]
+fn compile_protos() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path()?);
+    tonic_prost_build::configure().compile_with_config(config, &["proto/bitcask.proto"], &["proto"])?;
+    Ok(())
+}
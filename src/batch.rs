@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+/// A group of puts and deletes applied atomically by [`crate::BitcaskHandler::write_batch`].
+///
+/// The whole batch is appended to the working file as a single unit. If the process crashes while writing it,
+/// none of its operations are visible when the datastore is reopened.
+#[derive(Default)]
+pub struct WriteBatch {
+    pub(crate) operations: Vec<BatchOperation>,
+}
+
+pub(crate) enum BatchOperation {
+    Put {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    },
+    Delete {
+        key: Vec<u8>,
+    },
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> &mut Self {
+        self.operations.push(BatchOperation::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            ttl: None,
+        });
+        self
+    }
+
+    pub fn put_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) -> &mut Self {
+        self.operations.push(BatchOperation::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            ttl: Some(ttl),
+        });
+        self
+    }
+
+    /// Deletes the key when the batch is written. Unlike [`crate::BitcaskHandler::delete`],
+    /// deleting a missing key isn't an error.
+    pub fn delete(&mut self, key: &[u8]) -> &mut Self {
+        self.operations.push(BatchOperation::Delete { key: key.to_vec() });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
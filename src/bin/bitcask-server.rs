@@ -0,0 +1,148 @@
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+    thread,
+};
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use bitcask::{BitcaskHandler, IndexKind, Options, replication::Follower, server::{self, HttpOptions}};
+
+/// Serves a Bitcask datastore over the Redis protocol, usable with redis-cli and Redis client libraries,
+/// and optionally over HTTP, the memcached protocol and gRPC (with the `grpc` feature).
+#[derive(Parser)]
+#[command(name = "bitcask-server", version)]
+struct Cli {
+    /// Directory of the datastore, created if missing
+    #[arg(long, short, default_value = ".")]
+    dir: PathBuf,
+    /// Address to listen on for Redis clients
+    #[arg(long, default_value = "127.0.0.1:6379")]
+    bind: String,
+    /// Address to also listen on for HTTP requests, e.g. 127.0.0.1:8080
+    #[arg(long)]
+    http: Option<String>,
+    /// Largest value in bytes stored by an HTTP PUT, 64 MB by default
+    #[arg(long)]
+    http_max_body_size: Option<usize>,
+    /// Address to also listen on for memcached clients, e.g. 127.0.0.1:11211
+    #[arg(long)]
+    memcached: Option<String>,
+    /// Address to also listen on for gRPC clients, e.g. 127.0.0.1:50051
+    #[cfg(feature = "grpc")]
+    #[arg(long)]
+    grpc: Option<String>,
+    /// Address to also listen on for followers replicating this datastore, e.g. 127.0.0.1:7379
+    #[arg(long)]
+    replication: Option<String>,
+    /// Replication address of a leader to follow, the datastore is read-only until `REPLICAOF NO ONE`
+    #[arg(long)]
+    follow: Option<String>,
+    /// Sync every write to disk before replying
+    #[arg(long)]
+    sync_on_put: bool,
+    /// Size in bytes after which a new data file is started
+    #[arg(long)]
+    max_data_size: Option<usize>,
+    /// Secret used to sign entries and check their signature
+    #[arg(long, env = "BITCASK_MAC_SECRET", hide_env_values = true)]
+    mac_secret: Option<String>,
+    /// Hide entries written more than this many seconds ago
+    #[arg(long)]
+    expiry_secs: Option<u64>,
+    /// Keep keys sorted in memory, KEYS and SCAN return them in order
+    #[arg(long)]
+    ordered: bool,
+    /// Preallocate new data files with fallocate
+    #[arg(long)]
+    preallocate: bool,
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("bitcask-server: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let defaults = Options::default();
+    let options = Options {
+        read_write: true,
+        sync_on_put: cli.sync_on_put,
+        max_data_size: cli.max_data_size.unwrap_or(defaults.max_data_size),
+        mac_secret: cli.mac_secret.map(String::into_bytes),
+        expiry_secs: cli.expiry_secs,
+        index: if cli.ordered { IndexKind::Ordered } else { IndexKind::Hash },
+        preallocate: cli.preallocate,
+        ..defaults
+    };
+    std::fs::create_dir_all(&cli.dir).with_context(|| format!("Couldn't create {}", cli.dir.display()))?;
+    let context = || format!("Couldn't open the datastore in {}", cli.dir.display());
+    // The follower replicates until it's promoted, it's kept alive as long as the servers run
+    let (db, _follower) = match &cli.follow {
+        Some(leader) => {
+            let follower = Follower::start(leader.as_str(), &cli.dir, Some(options)).with_context(context)?;
+            (follower.db(), Some(follower))
+        }
+        None => (Arc::new(BitcaskHandler::open(&cli.dir, Some(options)).with_context(context)?), None),
+    };
+
+    if let Some(address) = cli.http {
+        let listener = bind(&address)?;
+        eprintln!("bitcask-server: serving {} over HTTP on {address}", cli.dir.display());
+        let defaults = HttpOptions::default();
+        let http_options = HttpOptions {
+            max_body_size: cli.http_max_body_size.unwrap_or(defaults.max_body_size),
+            ..defaults
+        };
+        spawn("HTTP", listener, Arc::clone(&db), move |listener, db| {
+            server::serve_http_with_options(listener, db, Some(http_options))
+        });
+    }
+    if let Some(address) = cli.memcached {
+        let listener = bind(&address)?;
+        eprintln!("bitcask-server: serving {} to memcached clients on {address}", cli.dir.display());
+        spawn("memcached", listener, Arc::clone(&db), server::serve_memcached);
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(address) = cli.grpc {
+        let listener = bind(&address)?;
+        eprintln!("bitcask-server: serving {} over gRPC on {address}", cli.dir.display());
+        spawn("gRPC", listener, Arc::clone(&db), server::serve_grpc);
+    }
+    if let Some(address) = cli.replication {
+        let listener = bind(&address)?;
+        eprintln!("bitcask-server: serving {} to followers on {address}", cli.dir.display());
+        spawn("replication", listener, Arc::clone(&db), server::serve_replication);
+    }
+    if let Some(leader) = &cli.follow {
+        eprintln!("bitcask-server: following the leader on {leader}");
+    }
+    let listener = bind(&cli.bind)?;
+    eprintln!("bitcask-server: serving {} on {}", cli.dir.display(), cli.bind);
+    server::serve_redis(listener, db)
+}
+
+/// Runs a server on its own thread, alongside the Redis one.
+fn spawn(
+    name: &'static str,
+    listener: TcpListener,
+    db: Arc<BitcaskHandler>,
+    serve: impl FnOnce(TcpListener, Arc<BitcaskHandler>) -> Result<()> + Send + 'static,
+) {
+    thread::spawn(move || {
+        if let Err(e) = serve(listener, db) {
+            eprintln!("bitcask-server: {name} server stopped: {e:#}");
+        }
+    });
+}
+
+fn bind(address: &str) -> Result<TcpListener> {
+    TcpListener::bind(address).with_context(|| format!("Couldn't listen on {address}"))
+}
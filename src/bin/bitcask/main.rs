@@ -0,0 +1,285 @@
+use std::{
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+use anyhow::{Context, Result, bail};
+use clap::{Args, Parser, Subcommand};
+
+use bitcask::{BitcaskError, BitcaskHandler, IndexKind, Options};
+
+mod shell;
+
+/// Command-line tool to inspect and edit a Bitcask datastore.
+#[derive(Parser)]
+#[command(name = "bitcask", version)]
+struct Cli {
+    /// Directory of the datastore
+    #[arg(long, short, global = true, default_value = ".")]
+    dir: PathBuf,
+    /// Read keys and values given as arguments as hex, and print them as hex
+    #[arg(long, global = true)]
+    hex: bool,
+    #[command(flatten)]
+    options: OptionsArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Flags mapped onto `Options`, `read_write` is set by the commands that write.
+#[derive(Args)]
+struct OptionsArgs {
+    /// Sync every write to disk before returning
+    #[arg(long, global = true)]
+    sync_on_put: bool,
+    /// Size in bytes after which a new data file is started
+    #[arg(long, global = true)]
+    max_data_size: Option<usize>,
+    /// Secret used to sign entries and check their signature
+    #[arg(long, global = true, env = "BITCASK_MAC_SECRET", hide_env_values = true)]
+    mac_secret: Option<String>,
+    /// Hide entries written more than this many seconds ago
+    #[arg(long, global = true)]
+    expiry_secs: Option<u64>,
+    /// Keep keys sorted in memory, keys are listed in order
+    #[arg(long, global = true)]
+    ordered: bool,
+    /// Preallocate new data files with fallocate
+    #[arg(long, global = true)]
+    preallocate: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the value of a key
+    Get { key: String },
+    /// Store a value, read from stdin when it's not given
+    Put {
+        key: String,
+        value: Option<String>,
+        /// Delete the key after this many seconds
+        #[arg(long)]
+        ttl: Option<u64>,
+    },
+    /// Delete a key
+    Delete { key: String },
+    /// List the keys, one per line
+    Keys {
+        /// Only list the keys starting with this prefix
+        #[arg(long)]
+        prefix: Option<String>,
+    },
+    /// Compact the data files, dropping deleted, overwritten and expired entries
+    Merge,
+    /// Print the number of keys, data files and their size
+    Stats,
+    /// Check the checksum, and signature with a MAC secret, of every entry
+    Verify,
+    /// Print every key and value, in on-disk order
+    Dump,
+    /// Convert a datastore written by the first version of the format, the old files are kept in `legacy`
+    Upgrade,
+    /// Open an interactive prompt with line editing and history
+    Shell {
+        /// Open the datastore read-only, e.g. while another process writes to it
+        #[arg(long)]
+        read_only: bool,
+    },
+}
+
+impl Command {
+    fn writes(&self) -> bool {
+        match self {
+            Self::Put { .. } | Self::Delete { .. } | Self::Merge | Self::Upgrade => true,
+            Self::Shell { read_only } => !read_only,
+            _ => false,
+        }
+    }
+}
+
+// Exit codes, clap exits with 2 on usage errors
+const EXIT_FAILURE: u8 = 1;
+const EXIT_NOT_FOUND: u8 = 3;
+const EXIT_CORRUPTED: u8 = 4;
+const EXIT_TAMPERED: u8 = 5;
+const EXIT_CONFLICT: u8 = 6;
+const EXIT_LOCKED: u8 = 7;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("bitcask: {e:#}");
+            exit_code(&e)
+        }
+    }
+}
+
+fn exit_code(error: &anyhow::Error) -> ExitCode {
+    let bitcask_error = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<BitcaskError>());
+    let code = match bitcask_error {
+        Some(BitcaskError::KeyNotFound) => EXIT_NOT_FOUND,
+        Some(BitcaskError::Corrupted { .. }) => EXIT_CORRUPTED,
+        Some(BitcaskError::Tampered { .. }) => EXIT_TAMPERED,
+        Some(BitcaskError::Conflict { .. }) => EXIT_CONFLICT,
+        Some(BitcaskError::Locked) => EXIT_LOCKED,
+        Some(BitcaskError::Compacted { .. }) | None => EXIT_FAILURE,
+    };
+    ExitCode::from(code)
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let options = cli.options.into_options(cli.command.writes());
+    if let Command::Upgrade = cli.command {
+        // The datastore can't be opened before it's upgraded
+        let replayed = bitcask::upgrade::upgrade(&cli.dir, Some(options))?;
+        println!("OK: {replayed} entries rewritten, the old data files are in the legacy directory");
+        return Ok(());
+    }
+    let db = open(&cli.dir, options)?;
+    let hex = cli.hex;
+    let mut stdout = io::stdout().lock();
+
+    match cli.command {
+        Command::Get { key } => {
+            let value = db.get(&parse(&key, hex)?)?;
+            if hex {
+                writeln!(stdout, "{}", to_hex(&value))?;
+            } else {
+                // Values are written as is, binary ones can be piped to a file
+                stdout.write_all(&value)?;
+                if stdout.is_terminal() {
+                    writeln!(stdout)?;
+                }
+            }
+        }
+        Command::Put { key, value, ttl } => {
+            let value = match value {
+                Some(value) => parse(&value, hex)?,
+                None => {
+                    let mut value = Vec::new();
+                    io::stdin().read_to_end(&mut value)?;
+                    value
+                }
+            };
+            let key = parse(&key, hex)?;
+            match ttl {
+                Some(secs) => db.put_with_ttl(&key, &value, Duration::from_secs(secs))?,
+                None => db.put(&key, &value)?,
+            }
+        }
+        Command::Delete { key } => db.delete(&parse(&key, hex)?)?,
+        Command::Keys { prefix } => {
+            let prefix = parse(prefix.as_deref().unwrap_or_default(), hex)?;
+            for key in db.keys().filter(|key| key.starts_with(&prefix)) {
+                writeln!(stdout, "{}", display(&key, hex))?;
+            }
+        }
+        Command::Merge => db.merge()?,
+        Command::Stats => {
+            let stats = db.stats()?;
+            writeln!(stdout, "keys: {}", stats.keys)?;
+            writeln!(stdout, "data files: {}", stats.data_files)?;
+            writeln!(stdout, "data size: {} bytes", stats.data_size)?;
+        }
+        Command::Verify => {
+            // Opening the datastore already checked every entry, reading the live values checks them again
+            let (entries, bytes) = db.fold((0, 0), |(entries, bytes), key, value| {
+                (entries + 1, bytes + key.len() + value.len())
+            })?;
+            writeln!(stdout, "OK: {entries} live entries ({bytes} bytes) verified")?;
+        }
+        Command::Dump => {
+            for pair in db.entries()? {
+                let (key, value) = pair?;
+                writeln!(stdout, "{}\t{}", display(&key, hex), display(&value, hex))?;
+            }
+        }
+        Command::Shell { .. } => shell::Shell::new(db, hex).run()?,
+        Command::Upgrade => unreachable!("Upgrades are run before opening the datastore"),
+    }
+    Ok(())
+}
+
+impl OptionsArgs {
+    fn into_options(self, read_write: bool) -> Options {
+        let defaults = Options::default();
+        Options {
+            read_write,
+            sync_on_put: self.sync_on_put,
+            max_data_size: self.max_data_size.unwrap_or(defaults.max_data_size),
+            mac_secret: self.mac_secret.map(String::into_bytes),
+            expiry_secs: self.expiry_secs,
+            index: if self.ordered { IndexKind::Ordered } else { IndexKind::Hash },
+            preallocate: self.preallocate,
+            ..defaults
+        }
+    }
+}
+
+fn open(directory: &Path, options: Options) -> Result<BitcaskHandler> {
+    if !directory.is_dir() {
+        if !options.read_write {
+            bail!("{} is not a directory", directory.display());
+        }
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("Couldn't create {}", directory.display()))?;
+    }
+    BitcaskHandler::open(directory, Some(options))
+        .with_context(|| format!("Couldn't open the datastore in {}", directory.display()))
+}
+
+/// Bytes of a key or value given as argument.
+fn parse(arg: &str, hex: bool) -> Result<Vec<u8>> {
+    if !hex {
+        return Ok(arg.as_bytes().to_vec());
+    }
+    if !arg.len().is_multiple_of(2) {
+        bail!("Odd number of hex digits in {arg:?}");
+    }
+    (0..arg.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(arg.get(i..i + 2).unwrap_or_default(), 16)
+                .with_context(|| format!("Invalid hex in {arg:?}"))
+        })
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn display(bytes: &[u8], hex: bool) -> String {
+    if hex {
+        to_hex(bytes)
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_map_to_their_exit_code() {
+        let cases = [
+            (BitcaskError::KeyNotFound, EXIT_NOT_FOUND),
+            (BitcaskError::Corrupted { file_name: "working_file_0".to_string(), offset: 5 }, EXIT_CORRUPTED),
+            (BitcaskError::Tampered { file_name: "working_file_0".to_string(), offset: 5 }, EXIT_TAMPERED),
+            (BitcaskError::Conflict { key: b"a".to_vec() }, EXIT_CONFLICT),
+            (BitcaskError::Locked, EXIT_LOCKED),
+        ];
+        for (error, code) in cases {
+            // Found behind the context added by `run`
+            let error = anyhow::Error::new(error).context("Couldn't open the datastore");
+            assert_eq!(exit_code(&error), ExitCode::from(code));
+        }
+        assert_eq!(exit_code(&anyhow::anyhow!("Odd number of hex digits")), ExitCode::from(EXIT_FAILURE));
+    }
+}
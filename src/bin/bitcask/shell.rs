@@ -0,0 +1,202 @@
+use std::{env, path::PathBuf};
+use anyhow::{Result, bail};
+use rustyline::{DefaultEditor, error::ReadlineError};
+
+use bitcask::BitcaskHandler;
+
+use crate::to_hex;
+
+const HELP: &str = "\
+get <key>              print the value of a key
+put <key> <value>      store a value
+del <key>              delete a key
+scan [prefix] [limit]  print the keys starting with prefix and their values
+stats                  print the number of keys, data files and their size
+merge                  compact the data files
+sync                   flush pending writes to disk
+mode [hex|utf8]        show or switch how keys and values are read and printed
+help                   print this help
+exit                   leave the shell
+
+Arguments can be quoted, in utf8 mode quoted arguments understand \\n, \\t, \\\\, \\\" and \\xNN escapes.";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Utf8,
+    Hex,
+}
+
+/// Interactive prompt running commands against an open datastore.
+pub struct Shell {
+    db: BitcaskHandler,
+    mode: Mode,
+}
+
+impl Shell {
+    pub fn new(db: BitcaskHandler, hex: bool) -> Self {
+        let mode = if hex { Mode::Hex } else { Mode::Utf8 };
+        Self { db, mode }
+    }
+
+    pub fn run(mut self) -> Result<()> {
+        let mut editor = DefaultEditor::new()?;
+        let history = history_path();
+        if let Some(history) = &history {
+            let _ = editor.load_history(history); // Missing on first run
+        }
+        println!("Connected to the datastore, type `help` for the list of commands.");
+
+        loop {
+            let line = match editor.readline("bitcask> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            editor.add_history_entry(line.as_str())?;
+            match self.execute(&line) {
+                Ok(true) => {}
+                Ok(false) => break,
+                // Errors are reported and the shell goes on, the datastore stays usable
+                Err(e) => println!("(error) {e:#}"),
+            }
+        }
+
+        if let Some(history) = &history {
+            editor.save_history(history)?;
+        }
+        Ok(())
+    }
+
+    /// Runs one command line, returns false when the shell should exit.
+    fn execute(&mut self, line: &str) -> Result<bool> {
+        let args = split_args(line, self.mode)?;
+        let Some((command, args)) = args.split_first() else {
+            return Ok(true);
+        };
+        match (String::from_utf8_lossy(command).to_lowercase().as_str(), args) {
+            ("get", [key]) => println!("{}", self.display(&self.db.get(&self.input(key)?)?)),
+            ("put" | "set", [key, value]) => {
+                self.db.put(&self.input(key)?, &self.input(value)?)?;
+                println!("OK");
+            }
+            ("del" | "delete", [key]) => {
+                self.db.delete(&self.input(key)?)?;
+                println!("OK");
+            }
+            ("scan", args) if args.len() <= 2 => self.scan(args)?,
+            ("stats", []) => {
+                let stats = self.db.stats()?;
+                println!("keys: {}", stats.keys);
+                println!("data files: {}", stats.data_files);
+                println!("data size: {} bytes", stats.data_size);
+            }
+            ("merge", []) => {
+                self.db.merge()?;
+                println!("OK");
+            }
+            ("sync", []) => {
+                self.db.sync()?;
+                println!("OK");
+            }
+            ("mode", []) => println!("{}", if self.mode == Mode::Hex { "hex" } else { "utf8" }),
+            ("mode", [mode]) => match mode.as_slice() {
+                b"hex" => self.mode = Mode::Hex,
+                b"utf8" | b"utf-8" => self.mode = Mode::Utf8,
+                _ => bail!("Unknown mode, expected hex or utf8"),
+            },
+            ("help", []) => println!("{HELP}"),
+            ("exit" | "quit", []) => return Ok(false),
+            (command, _) => bail!("Unknown command or wrong arguments for `{command}`, see `help`"),
+        }
+        Ok(true)
+    }
+
+    fn scan(&self, args: &[Vec<u8>]) -> Result<()> {
+        let prefix = self.input(args.first().map_or(&[], Vec::as_slice))?;
+        let limit = match args.get(1) {
+            Some(limit) => String::from_utf8_lossy(limit).parse()?,
+            None => usize::MAX,
+        };
+        let mut count = 0;
+        // Values are read from a snapshot, in key order with an ordered index
+        for pair in self.db.scan_prefix(&prefix)?.take(limit) {
+            let (key, value) = pair?;
+            println!("{} => {}", self.display(&key), self.display(&value));
+            count += 1;
+        }
+        println!("({count} entries)");
+        Ok(())
+    }
+
+    /// Bytes of a key or value argument.
+    fn input(&self, arg: &[u8]) -> Result<Vec<u8>> {
+        match self.mode {
+            Mode::Hex => crate::parse(&String::from_utf8_lossy(arg), true),
+            Mode::Utf8 => Ok(arg.to_vec()),
+        }
+    }
+
+    fn display(&self, bytes: &[u8]) -> String {
+        match self.mode {
+            Mode::Hex => to_hex(bytes),
+            Mode::Utf8 => match std::str::from_utf8(bytes) {
+                Ok(text) => format!("{text:?}"),
+                Err(_) => format!("b\"{}\"", bytes.escape_ascii()),
+            },
+        }
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".bitcask_history"))
+}
+
+/// Splits a command line on whitespace, honouring double quotes.
+fn split_args(line: &str, mode: Mode) -> Result<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+        let mut arg = Vec::new();
+        if first == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    None => bail!("Unterminated quoted argument"),
+                    Some('"') => break,
+                    Some('\\') if mode == Mode::Utf8 => arg.push(unescape(&mut chars)?),
+                    Some(c) => arg.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+        }
+        args.push(arg);
+    }
+    Ok(args)
+}
+
+fn unescape(chars: &mut impl Iterator<Item = char>) -> Result<u8> {
+    Ok(match chars.next() {
+        Some('n') => b'\n',
+        Some('t') => b'\t',
+        Some('r') => b'\r',
+        Some('0') => 0,
+        Some('\\') => b'\\',
+        Some('"') => b'"',
+        Some('x') => {
+            let digits: String = chars.take(2).collect();
+            u8::from_str_radix(&digits, 16).map_err(|_| anyhow::anyhow!("Invalid \\x escape"))?
+        }
+        _ => bail!("Invalid escape sequence"),
+    })
+}
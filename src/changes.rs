@@ -0,0 +1,313 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::{BufReader, ErrorKind, Seek, SeekFrom},
+    ops::Bound,
+    path::PathBuf,
+    time::Duration,
+};
+use anyhow::{Result, bail};
+use bincode::{config, decode_from_slice, decode_from_std_read};
+
+use crate::{
+    BitcaskError, BitcaskHandler,
+    engine::{Bitcask, Record},
+    files::{HEADER_LENGTH, WorkingFile},
+};
+
+/// Position in the log of the datastore, i.e. in its data files, where a change ends.
+///
+/// Positions are ordered like the changes they follow. The default one is the beginning of the log.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LogPosition {
+    pub file_id: u64,
+    pub offset: u64,
+}
+
+impl LogPosition {
+    pub(crate) fn new(file_id: usize, offset: usize) -> Result<Self> {
+        Ok(Self {
+            file_id: file_id.try_into()?,
+            offset: offset.try_into()?,
+        })
+    }
+}
+
+impl fmt::Display for LogPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file_id, self.offset)
+    }
+}
+
+/// A committed write, read from the log by [`Changes`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub key: Vec<u8>,
+    /// Empty for a delete.
+    pub value: Vec<u8>,
+    /// Unix time in millis of the write.
+    pub timestamp: u64,
+    /// Sequence number of the write, see [`crate::VersionedValue::version`].
+    pub version: u64,
+    /// Unix time in millis after which the value expires, if it was written with a TTL.
+    pub expires_at: Option<u64>,
+    pub is_deleted: bool,
+    /// Set for [`BitcaskHandler::delete_range`] and [`BitcaskHandler::delete_prefix`]: every key from `key` up to
+    /// this bound was deleted.
+    pub range_end: Option<Bound<Vec<u8>>>,
+    /// Where the change ends in the log, reading changes from there resumes right after it.
+    pub position: LogPosition,
+}
+
+/// Iterator over the committed writes of a datastore in commit order, see [`BitcaskHandler::changes`].
+///
+/// Changes are read from the data files, up to the last committed write. Once it's reached, `next` waits for
+/// the next commit, [`Changes::try_next`] and [`Changes::next_timeout`] don't wait or not forever.
+pub struct Changes<'a> {
+    db: &'a BitcaskHandler,
+    directory: PathBuf,
+    mac_secret: Option<Vec<u8>>,
+    // Where the last change returned ends
+    position: LogPosition,
+    // Where the next record is read, it's after `position` while a batch is being returned
+    read_position: LogPosition,
+    reader: Option<BufReader<File>>,
+    batch: VecDeque<Change>,
+    // Versions below were already returned, the copies of values rewritten by a merge are skipped
+    next_version: Option<u64>,
+}
+
+impl<'a> Changes<'a> {
+    pub(crate) fn new(db: &'a BitcaskHandler, from: LogPosition, next_version: Option<u64>) -> Self {
+        let engine = db.engine();
+        let mut changes = Self {
+            db,
+            directory: engine.directory().to_path_buf(),
+            mac_secret: engine.mac_secret().map(<[u8]>::to_vec),
+            position: from,
+            read_position: from,
+            reader: None,
+            batch: VecDeque::new(),
+            next_version,
+        };
+        // Opened right away so that a merge can't remove the file before it's read, failures show up once
+        // changes are read
+        changes.reader = changes.open(&engine).ok();
+        changes
+    }
+
+    /// Where the last change returned ends, to store along with its effects and resume from later.
+    pub fn position(&self) -> LogPosition {
+        self.position
+    }
+
+    /// Returns the next committed change, or `None` if there's none yet.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`BitcaskError::Compacted`] if changes after the current position were removed by a merge
+    /// before they were read, e.g. because the reader lagged behind.
+    pub fn try_next(&mut self) -> Result<Option<Change>> {
+        loop {
+            if let Some(change) = self.batch.pop_front() {
+                self.position = change.position;
+                return Ok(Some(change));
+            }
+            if !self.read_record(self.db.group_commit.log_end())? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Returns the next committed change, waiting up to `timeout` for it. `None` if there was none by then.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<Option<Change>> {
+        let log_end = self.db.group_commit.log_end();
+        if let Some(change) = self.try_next()? {
+            return Ok(Some(change));
+        }
+        self.db.group_commit.wait_log_end_past(log_end, Some(timeout));
+        self.try_next()
+    }
+
+    /// Reads the next record of the log before `log_end`, adding its changes to the batch.
+    /// Returns `false` once `log_end` is reached.
+    fn read_record(&mut self, log_end: LogPosition) -> Result<bool> {
+        if self.read_position >= log_end {
+            return Ok(false);
+        }
+        if self.reader.is_none() {
+            let db = self.db;
+            let engine = db.engine();
+            self.reader = Some(self.open(&engine)?);
+        }
+        let file_id = self.read_position.file_id;
+        let file_name = WorkingFile::file_name(file_id.try_into()?);
+        let mac_secret = self.mac_secret.as_deref();
+        let reader = self.reader.as_mut().unwrap();
+        let record_pos: usize = reader.stream_position()?.try_into()?;
+        let position_at = |offset: usize| -> Result<LogPosition> {
+            Ok(LogPosition {
+                file_id,
+                offset: offset.try_into()?,
+            })
+        };
+
+        let record = if record_pos == 0 {
+            if WorkingFile::read_header(&mut *reader, &file_name)? {
+                self.read_position.offset = HEADER_LENGTH.try_into()?;
+                return Ok(true);
+            }
+            None
+        } else {
+            match decode_from_std_read::<Record, _, _>(&mut *reader, config::standard()) {
+                Ok(Record::Entry(entry)) if entry.is_padding() => None,
+                Ok(record) => Some(record),
+                // End of the file, or a record torn by a crash which the startup scan ignored
+                Err(e) if e.to_string().contains("UnexpectedEof") => None,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let changes = match record {
+            None => None,
+            Some(Record::Entry(entry)) => {
+                entry.verify(mac_secret, &file_name, record_pos)?;
+                let end = reader.stream_position()?.try_into()?;
+                Some(vec![entry.into_change(position_at(end)?)])
+            }
+            Some(Record::RangeTombstone(tombstone)) => {
+                tombstone.verify(mac_secret, &file_name, record_pos)?;
+                let end = reader.stream_position()?.try_into()?;
+                Some(vec![tombstone.into_change(position_at(end)?)])
+            }
+            Some(Record::SequenceMark(mark)) => {
+                mark.verify(mac_secret, &file_name, record_pos)?;
+                Some(Vec::new())
+            }
+            Some(Record::BatchHeader {
+                entries_count,
+                length,
+                checksum,
+            }) => {
+                let body_pos: usize = reader.stream_position()?.try_into()?;
+                let body = Bitcask::read_batch_body(reader, length, checksum, &file_name, record_pos)?;
+                // A torn batch is the last thing in its file, the startup scan ignored it
+                match body {
+                    Some(body) => {
+                        let mut changes = Vec::with_capacity(entries_count.try_into()?);
+                        let mut offset = 0;
+                        for _ in 0..entries_count {
+                            let (record, read): (Record, usize) =
+                                decode_from_slice(&body[offset..], config::standard())?;
+                            let Record::Entry(entry) = record else {
+                                bail!(BitcaskError::Corrupted {
+                                    file_name,
+                                    offset: body_pos + offset
+                                });
+                            };
+                            entry.verify(mac_secret, &file_name, body_pos + offset)?;
+                            offset += read;
+                            changes.push(entry.into_change(position_at(body_pos + offset)?));
+                        }
+                        Some(changes)
+                    }
+                    None => None,
+                }
+            }
+        };
+
+        let Some(changes) = changes else {
+            if file_id < log_end.file_id {
+                self.next_file()?;
+                return Ok(true);
+            }
+            // Nothing more for now, the next read starts over from the same record
+            reader.seek(SeekFrom::Start(self.read_position.offset))?;
+            return Ok(false);
+        };
+        self.read_position.offset = reader.stream_position()?;
+        for change in changes {
+            if self.next_version.is_some_and(|next_version| change.version < next_version) {
+                // Already returned, rewritten by a merge
+                self.position = change.position;
+                continue;
+            }
+            self.next_version = Some(change.version + 1);
+            self.batch.push_back(change);
+        }
+        Ok(true)
+    }
+
+    /// Opens the data file of the read position, or the oldest one when reading from the beginning of the log.
+    /// Called under the engine lock, so that a merge can't remove the file meanwhile.
+    fn open(&mut self, engine: &Bitcask) -> Result<BufReader<File>> {
+        let file_path = self
+            .directory
+            .join(WorkingFile::file_name(self.read_position.file_id.try_into()?));
+        let file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound && self.read_position == LogPosition::default() => {
+                let Some(oldest_path) = WorkingFile::list_data_files(engine.directory())?.into_iter().next() else {
+                    return Err(e.into());
+                };
+                let oldest_id = oldest_path
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .and_then(WorkingFile::parse_file_id)
+                    .unwrap_or_default();
+                self.read_position = LogPosition::new(oldest_id, 0)?;
+                File::open(oldest_path)?
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                bail!(BitcaskError::Compacted {
+                    position: self.read_position
+                })
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut reader = BufReader::with_capacity(64 * 1024, file); // 64 KB
+        reader.seek(SeekFrom::Start(self.read_position.offset))?;
+        Ok(reader)
+    }
+
+    /// Moves the read position to the start of the data file after the current one, once it's read entirely.
+    fn next_file(&mut self) -> Result<()> {
+        let db = self.db;
+        let engine = db.engine();
+        let file_id: usize = self.read_position.file_id.try_into()?;
+        // The current file stays readable while it's open, the following ones may be gone
+        if engine.compacted_until().is_some_and(|compacted_id| compacted_id > file_id) {
+            bail!(BitcaskError::Compacted {
+                position: self.read_position
+            });
+        }
+        let next_id = WorkingFile::list_data_files(engine.directory())?
+            .iter()
+            .filter_map(|path| WorkingFile::parse_file_id(path.file_name()?.to_str()?))
+            .find(|id| *id > file_id);
+        let Some(next_id) = next_id else {
+            bail!(BitcaskError::Compacted {
+                position: self.read_position
+            });
+        };
+        self.read_position = LogPosition::new(next_id, 0)?;
+        self.reader = Some(self.open(&engine)?);
+        Ok(())
+    }
+}
+
+impl Iterator for Changes<'_> {
+    type Item = Result<Change>;
+
+    /// Returns the next committed change, waiting for it if there's none yet.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let log_end = self.db.group_commit.log_end();
+            match self.try_next() {
+                Ok(Some(change)) => return Some(Ok(change)),
+                Ok(None) => self.db.group_commit.wait_log_end_past(log_end, None),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
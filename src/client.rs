@@ -0,0 +1,409 @@
+//! Client of a datastore served by [`crate::server::serve_redis`], such as the `bitcask-server` binary.
+
+use std::{
+    fmt,
+    io::{BufReader, BufWriter, ErrorKind, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    BitcaskError, KeyValueStore, StoreIter,
+    resp::Frame,
+};
+
+// Keys listed, and values fetched, by each request of `Client::iter`
+const ITER_PAGE_SIZE: usize = 100;
+
+/// Error reply of the server, such as `ERR unknown command 'FOO'`.
+///
+/// It is returned wrapped in [`anyhow::Error`], use `err.downcast_ref::<ServerError>()` to match on it.
+/// Missing keys are reported as [`BitcaskError::KeyNotFound`] instead.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerError {
+    // First word of the reply, e.g. `ERR` or `WRONGTYPE`
+    pub code: String,
+    // Rest of the reply
+    pub message: String,
+}
+
+impl ServerError {
+    fn parse(reply: &str) -> Self {
+        let (code, message) = reply.split_once(' ').unwrap_or((reply, ""));
+        Self {
+            code: code.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message.as_str() {
+            "" => write!(f, "The server replied with an error: {}", self.code),
+            message => write!(f, "The server replied with an error: {} {message}", self.code),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+/// Settings of a [`Client`].
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    // Idle connections kept open for later calls, more are opened when calls run concurrently
+    pub max_idle_connections: usize,
+    // Limit for connecting, and for every read and write of a call, no limit when `None`
+    pub timeout: Option<Duration>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            max_idle_connections: 8,
+            timeout: None,
+        }
+    }
+}
+
+/// Connection-pooled client whose methods mirror [`crate::BitcaskHandler`], see [`KeyValueStore`].
+///
+/// It speaks the Redis protocol, so it also works against a Redis server. Like the handler, it can be shared
+/// between threads: each call borrows an idle connection, or opens a new one, and gives it back afterwards.
+pub struct Client {
+    address: SocketAddr,
+    options: ClientOptions,
+    idle_connections: Mutex<Vec<Connection>>,
+}
+
+impl Client {
+    /// Connects to a server, checking it answers before returning.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the server, e.g. `"127.0.0.1:6379"`.
+    /// * `options` - Pool and timeout settings, [`ClientOptions::default`] if `None`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bitcask::client::Client;
+    ///
+    /// let client = Client::connect("127.0.0.1:6379", None).unwrap();
+    /// client.put(b"user:1", b"Ada").unwrap();
+    /// assert_eq!(client.get(b"user:1").unwrap(), b"Ada");
+    /// ```
+    pub fn connect(address: impl ToSocketAddrs, options: Option<ClientOptions>) -> Result<Self> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .context("The server address didn't resolve to any address")?;
+        let client = Self {
+            address,
+            options: options.unwrap_or_default(),
+            idle_connections: Mutex::new(Vec::new()),
+        };
+        match client.call(&[b"PING"], true)? {
+            Frame::Simple(_) => Ok(client),
+            reply => bail!("Unexpected reply to PING: {reply:?}"),
+        }
+    }
+
+    /// Retrieves a value by key, failing with [`BitcaskError::KeyNotFound`] if it doesn't exist.
+    pub fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
+        match self.call(&[b"GET", key], true)? {
+            Frame::Bulk(value) => Ok(value),
+            Frame::Null => bail!(BitcaskError::KeyNotFound),
+            reply => bail!("Unexpected reply to GET: {reply:?}"),
+        }
+    }
+
+    /// Retrieves the values of several keys with a single request, `None` for missing keys.
+    pub fn get_many<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<Vec<Option<Vec<u8>>>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let command: Vec<&[u8]> = std::iter::once(&b"MGET"[..]).chain(keys.iter().map(AsRef::as_ref)).collect();
+        match self.call(&command, true)? {
+            Frame::Array(values) if values.len() == keys.len() => values
+                .into_iter()
+                .map(|value| match value {
+                    Frame::Bulk(value) => Ok(Some(value)),
+                    Frame::Null => Ok(None),
+                    reply => bail!("Unexpected value in reply to MGET: {reply:?}"),
+                })
+                .collect(),
+            reply => bail!("Unexpected reply to MGET: {reply:?}"),
+        }
+    }
+
+    /// Stores a key-value pair.
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.call_ok(&[b"SET", key, value], true)
+    }
+
+    /// Deletes a key, failing with [`BitcaskError::KeyNotFound`] if it doesn't exist.
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        // Sent again, a delete applied the first time would report the key as missing
+        match self.call(&[b"DEL", key], false)? {
+            Frame::Integer(1) => Ok(()),
+            Frame::Integer(0) => bail!(BitcaskError::KeyNotFound),
+            reply => bail!("Unexpected reply to DEL: {reply:?}"),
+        }
+    }
+
+    /// Lists all keys of the datastore.
+    pub fn list_keys(&self) -> Result<Vec<Vec<u8>>> {
+        match self.call(&[b"KEYS", b"*"], true)? {
+            Frame::Array(keys) => keys
+                .into_iter()
+                .map(|key| match key {
+                    Frame::Bulk(key) => Ok(key),
+                    reply => bail!("Unexpected key in reply to KEYS: {reply:?}"),
+                })
+                .collect(),
+            reply => bail!("Unexpected reply to KEYS: {reply:?}"),
+        }
+    }
+
+    /// Returns a lazy iterator over all key-value pairs.
+    ///
+    /// Keys are listed with `SCAN` and their values fetched with `MGET`, both in pages of 100 as the iterator
+    /// advances, so memory use doesn't grow with the datastore. Keys deleted in the meantime are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no connection can be opened. Each item is an error if a page can't be fetched,
+    /// the iterator ends after it.
+    pub fn iter(&self) -> Result<impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + '_> {
+        let pages = KeyPages {
+            client: self,
+            connection: Some(self.scan_connection()?),
+            cursor: Some(b"0".to_vec()),
+        };
+        Ok(pages.flat_map(move |page| match page.and_then(|page| Ok((self.get_many(&page)?, page))) {
+            Ok((values, page)) => page
+                .into_iter()
+                .zip(values)
+                .filter_map(|(key, value)| Some(Ok((key, value?))))
+                .collect(),
+            Err(e) => vec![Err(e)],
+        }))
+    }
+
+    /// Compacts the data files of the server, returning once it's done.
+    pub fn merge(&self) -> Result<()> {
+        self.call_ok(&[b"BITCASK.MERGE"], false)
+    }
+
+    /// Syncs the pending writes of the server to disk.
+    pub fn sync(&self) -> Result<()> {
+        self.call_ok(&[b"SAVE"], true)
+    }
+
+    fn call_ok(&self, command: &[&[u8]], is_repeatable: bool) -> Result<()> {
+        match self.call(command, is_repeatable)? {
+            Frame::Simple(_) => Ok(()),
+            reply => bail!("Unexpected reply: {reply:?}"),
+        }
+    }
+
+    /// Sends a command and returns its reply, error replies are returned as errors.
+    ///
+    /// A request that couldn't be written to a pooled connection is sent again on a fresh one. Once written it
+    /// may have been applied, it's only sent again if `is_repeatable`, i.e. applying it twice is harmless.
+    fn call(&self, command: &[&[u8]], is_repeatable: bool) -> Result<Frame> {
+        let request = Frame::Array(command.iter().map(|arg| Frame::bulk(*arg)).collect());
+        let pooled = self.pop_idle_connection();
+        let is_pooled = pooled.is_some();
+        let mut connection = match pooled {
+            Some(connection) => connection,
+            None => Connection::open(self.address, self.options.timeout)?,
+        };
+        let reply = match connection.send(&request) {
+            Ok(()) => match connection.receive() {
+                Ok(reply) => reply,
+                Err(_) if is_pooled && is_repeatable => {
+                    connection = Connection::open(self.address, self.options.timeout)?;
+                    connection.send(&request)?;
+                    connection.receive()?
+                }
+                Err(e) => return Err(e),
+            },
+            // The server closed the idle connection, the request never reached it
+            Err(_) if is_pooled => {
+                connection = Connection::open(self.address, self.options.timeout)?;
+                connection.send(&request)?;
+                connection.receive()?
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.release(connection);
+
+        match reply {
+            Frame::Error(message) => Err(ServerError::parse(&message).into()),
+            reply => Ok(reply),
+        }
+    }
+
+    /// Gives a connection back to the pool, unless enough are idle already.
+    fn release(&self, connection: Connection) {
+        let mut idle_connections = self.lock_idle_connections();
+        if idle_connections.len() < self.options.max_idle_connections {
+            idle_connections.push(connection);
+        }
+    }
+
+    /// Connection for the whole of a `SCAN`, the server keeps cursors per connection.
+    fn scan_connection(&self) -> Result<Connection> {
+        match self.pop_idle_connection() {
+            Some(connection) => Ok(connection),
+            None => Connection::open(self.address, self.options.timeout),
+        }
+    }
+
+    /// Takes an idle connection, dropping those the server closed meanwhile.
+    fn pop_idle_connection(&self) -> Option<Connection> {
+        let mut idle_connections = self.lock_idle_connections();
+        while let Some(connection) = idle_connections.pop() {
+            if !connection.is_closed() {
+                return Some(connection);
+            }
+        }
+        None
+    }
+
+    fn lock_idle_connections(&self) -> MutexGuard<'_, Vec<Connection>> {
+        self.idle_connections
+            .lock()
+            .expect("Connection pool lock poisoned by a panicking thread")
+    }
+}
+
+impl KeyValueStore for Client {
+    fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
+        Client::get(self, key)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        Client::put(self, key, value)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        Client::delete(self, key)
+    }
+
+    fn list_keys(&self) -> Result<Vec<Vec<u8>>> {
+        Client::list_keys(self)
+    }
+
+    fn iter(&self) -> Result<StoreIter<'_>> {
+        Ok(Box::new(Client::iter(self)?))
+    }
+
+    fn merge(&self) -> Result<()> {
+        Client::merge(self)
+    }
+
+    fn sync(&self) -> Result<()> {
+        Client::sync(self)
+    }
+}
+
+/// Pages of keys listed by `SCAN`, ends after the last page or the first error.
+struct KeyPages<'a> {
+    client: &'a Client,
+    // Given back to the pool once the scan is complete
+    connection: Option<Connection>,
+    // `None` once the scan is over
+    cursor: Option<Vec<u8>>,
+}
+
+impl KeyPages<'_> {
+    fn fetch(&mut self, cursor: Vec<u8>) -> Result<Vec<Vec<u8>>> {
+        let connection = self.connection.as_mut().context("The scan connection was closed")?;
+        let command = [b"SCAN".to_vec(), cursor, b"COUNT".to_vec(), ITER_PAGE_SIZE.to_string().into_bytes()];
+        connection.send(&Frame::Array(command.into_iter().map(Frame::Bulk).collect()))?;
+        let (next_cursor, keys) = match connection.receive()? {
+            Frame::Array(reply) => match <[Frame; 2]>::try_from(reply) {
+                Ok([Frame::Bulk(next_cursor), Frame::Array(keys)]) => (next_cursor, keys),
+                Ok(reply) => bail!("Unexpected reply to SCAN: {reply:?}"),
+                Err(reply) => bail!("Unexpected reply to SCAN: {reply:?}"),
+            },
+            Frame::Error(message) => return Err(ServerError::parse(&message).into()),
+            reply => bail!("Unexpected reply to SCAN: {reply:?}"),
+        };
+        let keys = keys
+            .into_iter()
+            .map(|key| match key {
+                Frame::Bulk(key) => Ok(key),
+                reply => bail!("Unexpected key in reply to SCAN: {reply:?}"),
+            })
+            .collect::<Result<_>>()?;
+        if next_cursor == b"0" {
+            if let Some(connection) = self.connection.take() {
+                self.client.release(connection);
+            }
+        } else {
+            self.cursor = Some(next_cursor);
+        }
+        Ok(keys)
+    }
+}
+
+impl Iterator for KeyPages<'_> {
+    type Item = Result<Vec<Vec<u8>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cursor = self.cursor.take()?;
+        Some(self.fetch(cursor))
+    }
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    fn open(address: SocketAddr, timeout: Option<Duration>) -> Result<Self> {
+        let stream = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+            None => TcpStream::connect(address),
+        }
+        .with_context(|| format!("Couldn't connect to {address}"))?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    fn send(&mut self, request: &Frame) -> Result<()> {
+        request.write_to(&mut self.writer, 2)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Frame> {
+        Frame::read_from(&mut self.reader)?.context("The server closed the connection")
+    }
+
+    /// Whether the server closed the connection, or sent something unexpected, while it was idle.
+    fn is_closed(&self) -> bool {
+        if !self.reader.buffer().is_empty() {
+            return true;
+        }
+        let stream = self.reader.get_ref();
+        if stream.set_nonblocking(true).is_err() {
+            return true;
+        }
+        let is_idle = matches!(stream.peek(&mut [0]), Err(e) if e.kind() == ErrorKind::WouldBlock);
+        stream.set_nonblocking(false).is_err() || !is_idle
+    }
+}
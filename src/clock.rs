@@ -0,0 +1,102 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Source of the timestamps written in entries and of the current time used for expiry checks.
+///
+/// Set it with [`crate::Options::clock`], it defaults to [`HybridLogicalClock`].
+pub trait Clock: Send + Sync {
+    /// Current time in Unix millis, read to timestamp new entries.
+    fn now(&self) -> u64;
+
+    /// Current time in Unix millis, read for expiry checks. Unlike [`Clock::now`] it's read on every lookup,
+    /// clocks that tick on each reading should leave it alone.
+    fn current(&self) -> u64 {
+        self.now()
+    }
+
+    /// Called with the newest timestamp found on disk when the datastore is opened, so that
+    /// clocks which must not go backwards never stamp entries older than existing ones.
+    fn observe(&self, _timestamp: u64) {}
+}
+
+/// Wall clock. It follows every adjustment of the system time, including steps backwards.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        // A clock set before 1970 reads as the epoch instead of panicking
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_millis() as u64)
+    }
+}
+
+/// Wall clock whose readings strictly increase.
+///
+/// When the system time steps back (NTP adjustment, VM migration, ...) or is behind timestamps already
+/// written to disk, it counts up from the highest time seen until the wall clock catches up. Readings
+/// within the same millisecond are told apart by this logical counter too, so every [`Clock::now`] is one
+/// millisecond past the previous one at least. Under more than one write per millisecond the clock runs
+/// ahead of the wall clock, and falls back on it once the writes slow down.
+#[derive(Default)]
+pub struct HybridLogicalClock {
+    /// Last reading of `now`, or the newest timestamp observed
+    highest: AtomicU64,
+}
+
+impl HybridLogicalClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Clock for HybridLogicalClock {
+    fn now(&self) -> u64 {
+        let physical = SystemClock.now();
+        let next = |highest: u64| physical.max(highest.saturating_add(1));
+        let previous = self
+            .highest
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |highest| Some(next(highest)))
+            .unwrap_or_else(|highest| highest);
+        next(previous)
+    }
+
+    fn current(&self) -> u64 {
+        SystemClock.now().max(self.highest.load(Ordering::SeqCst))
+    }
+
+    fn observe(&self, timestamp: u64) {
+        self.highest.fetch_max(timestamp, Ordering::SeqCst);
+    }
+}
+
+/// Clock that only moves when told to, for tests of TTL and expiry.
+#[derive(Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now),
+        }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now
+            .fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
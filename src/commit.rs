@@ -0,0 +1,126 @@
+use std::{
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::LogPosition;
+
+/// Lets concurrent writers share a single write, and fsync with `sync_on_put`, of the working file.
+///
+/// Writers append their entries to the working file buffer under the engine lock, then wait here for a flush
+/// covering their last sequence number. The first one to find no flush in progress becomes the leader and
+/// flushes everything buffered so far, writers arriving meanwhile queue up behind it for the next flush.
+///
+/// It also tracks where the committed records end in the data files, readers of [`crate::Changes`] wait
+/// here for new ones.
+pub(crate) struct GroupCommit {
+    state: Mutex<CommitState>,
+    flushed: Condvar,
+}
+
+struct CommitState {
+    durable_sequence: u64, // Entries with a smaller sequence number are written (and synced)
+    log_end: LogPosition, // Records before this position are written (and synced)
+    is_flushing: bool,
+}
+
+impl GroupCommit {
+    pub(crate) fn new(durable_sequence: u64, log_end: LogPosition) -> Self {
+        Self {
+            state: Mutex::new(CommitState {
+                durable_sequence,
+                log_end,
+                is_flushing: false,
+            }),
+            flushed: Condvar::new(),
+        }
+    }
+
+    /// Returns once the entries with a sequence number below `sequence` are durable.
+    ///
+    /// `flush` is called if this writer leads the next flush, it returns the sequence number and the log position
+    /// the working file is durable up to. A failed flush is returned to its leader only, waiting writers try again.
+    pub(crate) fn wait_durable(
+        &self,
+        sequence: u64,
+        flush: impl FnOnce() -> Result<(u64, LogPosition)>,
+    ) -> Result<()> {
+        let mut state = self.lock_state();
+        loop {
+            if state.durable_sequence >= sequence {
+                return Ok(());
+            }
+            if !state.is_flushing {
+                break;
+            }
+            state = self
+                .flushed
+                .wait(state)
+                .expect("Group commit lock poisoned by a panicking thread");
+        }
+        state.is_flushing = true;
+        drop(state);
+
+        let result = flush();
+
+        let mut state = self.lock_state();
+        state.is_flushing = false;
+        if let Ok((durable_sequence, log_end)) = result {
+            state.durable_sequence = state.durable_sequence.max(durable_sequence);
+            state.log_end = state.log_end.max(log_end);
+        }
+        self.flushed.notify_all();
+        result.map(|_| ())
+    }
+
+    /// Position where the committed records end.
+    pub(crate) fn log_end(&self) -> LogPosition {
+        self.lock_state().log_end
+    }
+
+    /// Sequence number below which entries are committed, along with the position where they end.
+    pub(crate) fn committed(&self) -> (u64, LogPosition) {
+        let state = self.lock_state();
+        (state.durable_sequence, state.log_end)
+    }
+
+    /// Moves the committed records forward for records written outside of a group commit, e.g. by a merge or
+    /// by replication, and wakes up the readers waiting for them.
+    pub(crate) fn advance(&self, sequence: u64, log_end: LogPosition) {
+        let mut state = self.lock_state();
+        state.durable_sequence = state.durable_sequence.max(sequence);
+        state.log_end = state.log_end.max(log_end);
+        self.flushed.notify_all();
+    }
+
+    /// Returns once the committed records end after `log_end`, or after `timeout` if any.
+    pub(crate) fn wait_log_end_past(&self, log_end: LogPosition, timeout: Option<Duration>) {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.lock_state();
+        while state.log_end <= log_end {
+            state = match deadline {
+                Some(deadline) => {
+                    let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                        return;
+                    };
+                    self.flushed
+                        .wait_timeout(state, timeout)
+                        .expect("Group commit lock poisoned by a panicking thread")
+                        .0
+                }
+                None => self
+                    .flushed
+                    .wait(state)
+                    .expect("Group commit lock poisoned by a panicking thread"),
+            };
+        }
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, CommitState> {
+        self.state
+            .lock()
+            .expect("Group commit lock poisoned by a panicking thread")
+    }
+}
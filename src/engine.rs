@@ -1,40 +1,63 @@
 use anyhow::{Context, Result, bail};
-use bincode::{Decode, Encode, config, decode_from_std_read};
+use bincode::{Decode, Encode, config, decode_from_slice, decode_from_std_read};
 use std::{
     collections::HashMap,
-    fs::{File, OpenOptions},
-    io::{BufReader, Seek, SeekFrom},
+    fs::{File, OpenOptions, TryLockError},
+    fs,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    ops::Bound,
     path::{Path, PathBuf},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use crate::{Options, files::WorkingFile};
+use crate::{
+    BitcaskError, Change, LogPosition, Options, WriteBatch,
+    batch::BatchOperation,
+    commit::GroupCommit,
+    files::WorkingFile,
+    iter::{Entries, Iter, Keys},
+    keydir::{self, KeyDir},
+};
 use crc32fast::Hasher;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 
 use super::BitcaskHandler;
 
+type FilesPool = HashMap<String, File>;
+type HmacSha256 = Hmac<Sha256>;
+
+fn is_expired(expires_at: Option<u64>, now: u64) -> bool {
+    expires_at.is_some_and(|expires_at| expires_at <= now)
+}
+
 pub struct Bitcask {
     directory: PathBuf,
     _lock: Option<File>,
     working_file: Option<WorkingFile>,
     working_file_id: Option<usize>, // Number of existing files in directory + 1
     // Be aware of concurrent updates to this map, aren't we have only one Process? BUT can have multiple threads?
-    key_dir: HashMap<Vec<u8>, DirEntry>,
+    key_dir: KeyDir,
     options: Options,
     // IDEA: keep files opened to avoid opening for every request in a hashmap? with
     // TODO: study the feasibility of having mmap instead. That will limit our implementation on 64-bit arch?
-    files_pool: HashMap<String, File>,
+    files_pool: FilesPool,
+    next_sequence: u64, // Sequence number of the next entry written, it's the version of the key
+    compacted_until: Option<usize>, // Id of the newest data file removed by a merge, or by replication
 }
 
 impl Bitcask {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         directory: &Path,
         lock_file: Option<File>,
         working_file: Option<WorkingFile>,
         working_file_id: Option<usize>,
-        key_dir: HashMap<Vec<u8>, DirEntry>,
+        key_dir: KeyDir,
         options: Options,
-        files_pool: HashMap<String, File>,
+        files_pool: FilesPool,
+        next_sequence: u64,
     ) -> Self {
         Self {
             directory: directory.to_path_buf(),
@@ -44,62 +67,391 @@ impl Bitcask {
             key_dir,
             options,
             files_pool,
+            next_sequence,
+            compacted_until: None,
         }
     }
 }
 
-#[derive(Clone, Encode, Decode)]
+/// A value along with the metadata of the entry holding it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionedValue {
+    pub value: Vec<u8>,
+    /// Sequence number of the write, unique and increasing across the whole datastore.
+    pub version: u64,
+    /// Unix time in millis of the write.
+    pub timestamp: u64,
+    /// Flags stored along with the value, see [`BitcaskHandler::put_with_flags`].
+    pub flags: u32,
+}
+
+/// Figures about an open datastore, see [`BitcaskHandler::stats`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// Keys in the key directory, expired keys not read or merged since are still counted.
+    pub keys: usize,
+    /// Keys written with a TTL, counted like `keys`.
+    pub expiring_keys: usize,
+    pub data_files: usize,
+    /// Size of all data files, including deleted and overwritten entries until the next `merge`.
+    pub data_size: u64,
+}
+
+#[derive(Clone, PartialEq, Encode, Decode)]
 pub struct DirEntry {
     file_name: String,
     entry_pos: usize,
     timestamp: u64,
+    expires_at: Option<u64>,
+    sequence: u64,
 }
 
 impl DirEntry {
-    pub fn new(file_name: String, entry_pos: usize, timestamp: u64) -> Self {
+    pub fn new(
+        file_name: String,
+        entry_pos: usize,
+        timestamp: u64,
+        expires_at: Option<u64>,
+        sequence: u64,
+    ) -> Self {
         Self {
             file_name,
             entry_pos,
             timestamp,
+            expires_at,
+            sequence,
         }
     }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn entry_pos(&self) -> usize {
+        self.entry_pos
+    }
+
+    /// Version handed to callers for conditional writes, it changes on every write of the key.
+    pub fn version(&self) -> u64 {
+        self.sequence
+    }
+
+    /// An entry expires at its own expiry time or, when `expiry_secs` is set, once it's older than that.
+    pub fn is_expired(&self, now: u64, expiry_secs: Option<u64>) -> bool {
+        let max_age_exceeded = expiry_secs
+            .is_some_and(|secs| self.timestamp.saturating_add(secs.saturating_mul(1000)) <= now);
+        is_expired(self.expires_at, now) || max_age_exceeded
+    }
+}
+
+/// Unit appended to the data files.
+#[derive(Encode, Decode)]
+pub enum Record {
+    Entry(Entry),
+    /// Frames an atomic batch: it's followed by `length` bytes holding `entries_count` [`Record::Entry`].
+    /// At startup a batch is applied as a whole, or ignored if it was torn by a crash: it's incomplete, or its
+    /// checksum doesn't match and only padding follows. Otherwise a mismatch is reported as corruption.
+    BatchHeader {
+        entries_count: u32,
+        length: u64,
+        checksum: u32,
+    },
+    RangeTombstone(RangeTombstone),
+    SequenceMark(SequenceMark),
 }
 
 #[derive(Encode, Decode)]
 pub struct Entry {
     crc_checksum: u32,
+    sequence: u64, // Assigned by the engine when the entry is written
     timestamp: u64,
+    expires_at: Option<u64>, // Unix time in millis after which the entry is considered deleted
+    flags: u32, // Opaque to the engine, e.g. the client flags of the memcached server
     key: Vec<u8>,
     value: Vec<u8>,
     is_deleted: bool,
+    mac: Option<[u8; 32]>, // HMAC-SHA256, only present when `Options::mac_secret` is set
 }
 
 impl Entry {
-    pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
-        let timestamp: u64 = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
-        Self {
-            crc_checksum: Self::generate_checksum(timestamp, &key, &value),
-            timestamp: timestamp,
-            key: key,
-            value: value,
+    pub fn new(key: Vec<u8>, value: Vec<u8>, expires_at: Option<u64>) -> Self {
+        // Sequence and timestamp are stamped by the engine when the entry is written
+        let mut entry = Self {
+            crc_checksum: 0,
+            sequence: 0,
+            timestamp: 0,
+            expires_at,
+            flags: 0,
+            key,
+            value,
             is_deleted: false,
-        }
+            mac: None,
+        };
+        entry.crc_checksum = entry.generate_checksum();
+        entry
     }
 
-    fn generate_checksum(timestamp: u64, key: &Vec<u8>, value: &Vec<u8>) -> u32 {
+    pub fn with_flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self.crc_checksum = self.generate_checksum();
+        self
+    }
+
+    fn generate_checksum(&self) -> u32 {
         let mut hasher = Hasher::new();
-        hasher.update(&timestamp.to_le_bytes());
-        hasher.update(&key);
-        hasher.update(&value);
+        hasher.update(&self.sequence.to_le_bytes());
+        hasher.update(&self.timestamp.to_le_bytes());
+        hasher.update(&self.expires_at.unwrap_or_default().to_le_bytes());
+        hasher.update(&self.flags.to_le_bytes());
+        hasher.update(&self.key);
+        hasher.update(&self.value);
         hasher.finalize()
     }
 
+    fn generate_mac(&self, secret: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any size");
+        // Lengths are included so that bytes can't be moved between key and value without changing the MAC
+        mac.update(&self.sequence.to_le_bytes());
+        mac.update(&self.timestamp.to_le_bytes());
+        mac.update(&self.expires_at.unwrap_or_default().to_le_bytes());
+        mac.update(&self.flags.to_le_bytes());
+        mac.update(&(self.key.len() as u64).to_le_bytes());
+        mac.update(&self.key);
+        mac.update(&(self.value.len() as u64).to_le_bytes());
+        mac.update(&self.value);
+        mac.update(&[self.is_deleted as u8]);
+        mac
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        is_expired(self.expires_at, now)
+    }
+
+    /// Zero bytes past the data of a preallocated file decode as this entry, its CRC never matches.
+    pub fn is_padding(&self) -> bool {
+        self.crc_checksum == 0
+            && self.sequence == 0
+            && self.timestamp == 0
+            && self.expires_at.is_none()
+            && self.flags == 0
+            && self.key.is_empty()
+            && self.value.is_empty()
+            && !self.is_deleted
+            && self.mac.is_none()
+    }
+
     pub fn mark_deleted(&mut self) {
         self.is_deleted = true
     }
+
+    pub fn stamp(&mut self, sequence: u64, timestamp: u64) {
+        self.sequence = sequence;
+        self.timestamp = timestamp;
+        self.crc_checksum = self.generate_checksum();
+    }
+
+    /// Signs the entry, must be called after every change to the entry's content.
+    pub fn sign(&mut self, secret: &[u8]) {
+        self.mac = Some(self.generate_mac(secret).finalize().into_bytes().into());
+    }
+
+    /// Decodes the entry `reader` is positioned at, `file_name` and `offset` locate it in error messages.
+    /// The entry still needs to be verified.
+    pub fn read_from(reader: &mut impl Read, file_name: &str, offset: usize) -> Result<Self> {
+        let record: Record = decode_from_std_read(reader, config::standard())
+            .context("Error Decoding Entry from file")?;
+        let Record::Entry(entry) = record else {
+            bail!(BitcaskError::Corrupted {
+                file_name: file_name.to_string(),
+                offset
+            });
+        };
+        Ok(entry)
+    }
+
+    /// The change this entry was written for, `position` is where the entry ends in the log.
+    pub fn into_change(self, position: LogPosition) -> Change {
+        Change {
+            value: if self.is_deleted { Vec::new() } else { self.value },
+            key: self.key,
+            timestamp: self.timestamp,
+            version: self.sequence,
+            expires_at: self.expires_at,
+            is_deleted: self.is_deleted,
+            range_end: None,
+            position,
+        }
+    }
+
+    pub fn into_value(self) -> Vec<u8> {
+        self.value
+    }
+
+    /// Checks the CRC and, when a secret is given, the MAC of an entry read from `file_name` at `offset`.
+    pub fn verify(&self, secret: Option<&[u8]>, file_name: &str, offset: usize) -> Result<()> {
+        verify_record(
+            self.crc_checksum == self.generate_checksum(),
+            self.mac,
+            secret.map(|secret| self.generate_mac(secret)),
+            file_name,
+            offset,
+        )
+    }
+}
+
+/// Deletes every key in `[start, end)` written before it, with a single record.
+#[derive(Encode, Decode)]
+pub struct RangeTombstone {
+    crc_checksum: u32,
+    sequence: u64,
+    timestamp: u64,
+    start: Vec<u8>,
+    end: Option<Vec<u8>>, // No upper bound when None
+    mac: Option<[u8; 32]>,
+}
+
+impl RangeTombstone {
+    pub fn new(start: Vec<u8>, end: Option<Vec<u8>>, sequence: u64, timestamp: u64) -> Self {
+        let mut tombstone = Self {
+            crc_checksum: 0,
+            sequence,
+            timestamp,
+            start,
+            end,
+            mac: None,
+        };
+        tombstone.crc_checksum = tombstone.generate_checksum();
+        tombstone
+    }
+
+    fn generate_checksum(&self) -> u32 {
+        let mut hasher = Hasher::new();
+        hasher.update(&self.sequence.to_le_bytes());
+        hasher.update(&self.timestamp.to_le_bytes());
+        hasher.update(&self.start);
+        hasher.update(&[self.end.is_some() as u8]);
+        hasher.update(self.end.as_deref().unwrap_or_default());
+        hasher.finalize()
+    }
+
+    fn generate_mac(&self, secret: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any size");
+        mac.update(&self.sequence.to_le_bytes());
+        mac.update(&self.timestamp.to_le_bytes());
+        mac.update(&(self.start.len() as u64).to_le_bytes());
+        mac.update(&self.start);
+        mac.update(&[self.end.is_some() as u8]);
+        mac.update(self.end.as_deref().unwrap_or_default());
+        mac
+    }
+
+    pub fn sign(&mut self, secret: &[u8]) {
+        self.mac = Some(self.generate_mac(secret).finalize().into_bytes().into());
+    }
+
+    pub fn verify(&self, secret: Option<&[u8]>, file_name: &str, offset: usize) -> Result<()> {
+        verify_record(
+            self.crc_checksum == self.generate_checksum(),
+            self.mac,
+            secret.map(|secret| self.generate_mac(secret)),
+            file_name,
+            offset,
+        )
+    }
+
+    /// The change this tombstone was written for, `position` is where the tombstone ends in the log.
+    pub fn into_change(self, position: LogPosition) -> Change {
+        Change {
+            key: self.start,
+            value: Vec::new(),
+            timestamp: self.timestamp,
+            version: self.sequence,
+            expires_at: None,
+            is_deleted: true,
+            range_end: Some(self.end.map_or(Bound::Unbounded, Bound::Excluded)),
+            position,
+        }
+    }
+}
+
+/// Written by a merge ahead of the merged entries: sequence numbers below `next_sequence` were used, also by the
+/// entries the merge dropped, so they aren't given again after a restart. Signed like entries, a forged mark
+/// could otherwise make versions be given again.
+#[derive(Encode, Decode)]
+pub struct SequenceMark {
+    crc_checksum: u32,
+    next_sequence: u64,
+    mac: Option<[u8; 32]>,
+}
+
+impl SequenceMark {
+    pub fn new(next_sequence: u64) -> Self {
+        Self {
+            crc_checksum: crc32fast::hash(&next_sequence.to_le_bytes()),
+            next_sequence,
+            mac: None,
+        }
+    }
+
+    fn generate_mac(&self, secret: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any size");
+        mac.update(&self.next_sequence.to_le_bytes());
+        mac
+    }
+
+    pub fn sign(&mut self, secret: &[u8]) {
+        self.mac = Some(self.generate_mac(secret).finalize().into_bytes().into());
+    }
+
+    pub fn verify(&self, secret: Option<&[u8]>, file_name: &str, offset: usize) -> Result<()> {
+        verify_record(
+            self.crc_checksum == crc32fast::hash(&self.next_sequence.to_le_bytes()),
+            self.mac,
+            secret.map(|secret| self.generate_mac(secret)),
+            file_name,
+            offset,
+        )
+    }
+}
+
+/// Sequence number and clock state gathered from the records loaded into a key dir.
+#[derive(Default)]
+struct LoadedRecords {
+    next_sequence: u64,
+    newest_timestamp: u64,
+}
+
+impl LoadedRecords {
+    fn observe(&mut self, sequence: u64, timestamp: u64) {
+        self.next_sequence = self.next_sequence.max(sequence + 1);
+        self.newest_timestamp = self.newest_timestamp.max(timestamp);
+    }
+}
+
+/// Corruption is checked first, a torn record must not be reported as tampered.
+fn verify_record(
+    is_crc_valid: bool,
+    mac: Option<[u8; 32]>,
+    expected_mac: Option<HmacSha256>,
+    file_name: &str,
+    offset: usize,
+) -> Result<()> {
+    if !is_crc_valid {
+        bail!(BitcaskError::Corrupted {
+            file_name: file_name.to_string(),
+            offset
+        });
+    }
+    if let Some(expected_mac) = expected_mac {
+        let is_authentic = mac.is_some_and(|tag| expected_mac.verify_slice(&tag).is_ok());
+        if !is_authentic {
+            bail!(BitcaskError::Tampered {
+                file_name: file_name.to_string(),
+                offset
+            });
+        }
+    }
+    Ok(())
 }
 
 impl Bitcask {
@@ -108,76 +460,78 @@ impl Bitcask {
          * Now we have the working file in hand and locking for only one process, What is left in this method?
          * Build the Hashmap from existing data and hint files when opening existing bitcask directory
          */
-        let options = options.unwrap_or(Options::default());
-        let (key_dir, files_pool) = Self::build_key_dir_map_and_files_pool(&directory)?;
+        let options = options.unwrap_or_default();
+        // The writer locks the directory before the scan, which may cut the padding of preallocated files
+        let lock_file = if options.read_write {
+            Some(Self::try_acquire_write_lock(directory)?)
+        } else {
+            None
+        };
+        let (key_dir, files_pool, next_sequence) =
+            Self::build_key_dir_map_and_files_pool(directory, &options)?;
 
-        let (lock_file, working_file, working_file_id) = if options.read_write {
-            let lock_file = Some(Self::try_acquire_write_lock(directory)?);
+        let (working_file, working_file_id) = if options.read_write {
             let working_file_id = WorkingFile::get_working_file_id(directory).unwrap_or_default();
             let working_file = Some(
-                WorkingFile::open(directory, working_file_id)
+                Self::open_working_file(directory, working_file_id, &options)
                     .context("Couldn't open the working file")?,
             );
-            (lock_file, working_file, Some(working_file_id))
+            (working_file, Some(working_file_id))
         } else {
-            (None, None, None)
+            (None, None)
         };
 
         // TODO: if current directory has existing bitcask store, we should fill the hashmap with the values
         // in hint files maybe or loop over all working files in reverse order to build it?
 
+        let engine = Bitcask::new(
+            directory,
+            lock_file,
+            working_file,
+            working_file_id,
+            key_dir,
+            options,
+            files_pool,
+            next_sequence,
+        );
         let bitcask_handler = BitcaskHandler {
-            bitcask_engine: Bitcask::new(
-                directory,
-                lock_file,
-                working_file,
-                working_file_id,
-                key_dir,
-                options,
-                files_pool,
-            ),
+            group_commit: GroupCommit::new(next_sequence, engine.log_end()?),
+            bitcask_engine: Mutex::new(engine),
         };
 
         Ok(bitcask_handler)
     }
 
-    fn try_acquire_write_lock(directory: &Path) -> Result<File> {
+    pub(crate) fn try_acquire_write_lock(directory: &Path) -> Result<File> {
+        let lock_file = Self::open_lock_file(directory)?;
+        match lock_file.try_lock() {
+            Ok(()) => Ok(lock_file),
+            Err(TryLockError::WouldBlock) => bail!(BitcaskError::Locked),
+            Err(TryLockError::Error(e)) => Err(e).context("Failed to lock bitcask.lock file"),
+        }
+    }
+
+    fn open_lock_file(directory: &Path) -> Result<File> {
         let lock_path = directory.join("bitcask.lock");
-        let lock_file = OpenOptions::new()
+        OpenOptions::new()
             .read(true)
             .create(true)
+            .truncate(false)
             .write(true)
             .open(&lock_path)
-            .context("Failed to open bitcask.lock file")?;
-        // PANIC if directory is already open
-        lock_file
-            .try_lock()
-            .expect("Bitcask directory is already open for writing by another process");
-        Ok(lock_file)
+            .context("Failed to open bitcask.lock file")
     }
 
     fn build_key_dir_map_and_files_pool(
         directory: &Path,
-    ) -> Result<(HashMap<Vec<u8>, DirEntry>, HashMap<String, File>)> {
+        options: &Options,
+    ) -> Result<(KeyDir, FilesPool, u64)> {
         // TODO: Handle reading from hint files(when added support) if exists, to build the map fast.
-        let mut key_dir: HashMap<Vec<u8>, DirEntry> = HashMap::new();
-        let mut files_pool: HashMap<String, File> = HashMap::new();
-        let mut data_files_paths: Vec<PathBuf> = directory.read_dir()?.filter_map(|entry| {
-            let entry = entry.ok()?;
-            if entry.file_name().to_str()?.contains("working_file") {
-                Some(entry.path())
-            } else {
-                None
-            }
-        }).collect();
-
-        data_files_paths.sort_by_key(|path| {
-            path.file_name()
-                .and_then(|s| s.to_str())
-                .and_then(|s| s.strip_prefix("working_file_"))
-                .and_then(|id| id.parse::<u64>().ok())
-                .unwrap_or(0)
-        });
+        let mut key_dir = KeyDir::new(options.index);
+        let mut files_pool: FilesPool = HashMap::new();
+        let mut loaded = LoadedRecords::default();
+        let data_files_paths = WorkingFile::list_data_files(directory)?;
+        let now = options.clock.current();
 
         for file_path in data_files_paths {
             let file = OpenOptions::new()
@@ -188,63 +542,287 @@ impl Bitcask {
                     file_path.to_str().unwrap()
                 ))?;
             let mut reader = BufReader::with_capacity(64 * 1024, file); // 64 KB
+            Self::load_data_file(&mut key_dir, &mut reader, &file_path, options, now, &mut loaded)?;
             let file_name = file_path
                 .file_name()
                 .unwrap()
                 .to_str()
                 .unwrap_or_default()
                 .to_string();
+            files_pool.insert(file_name, reader.into_inner());
+        }
+
+        options.clock.observe(loaded.newest_timestamp);
+        Ok((key_dir, files_pool, loaded.next_sequence))
+    }
+
+    /// Loads the records of a data file into the key dir, from the current position of `reader`.
+    /// Returns the position where the loaded records end, the end of the file unless its last record is torn.
+    fn load_data_file(
+        key_dir: &mut KeyDir,
+        reader: &mut BufReader<File>,
+        file_path: &Path,
+        options: &Options,
+        now: u64,
+        loaded: &mut LoadedRecords,
+    ) -> Result<usize> {
+        let mac_secret = options.mac_secret.as_deref();
+        let file_name = file_path
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap_or_default()
+            .to_string();
+
+        if reader.stream_position()? == 0 && !WorkingFile::read_header(reader, &file_name)? {
+            // Created right before a crash, its padding is given back
+            Self::cut_padding(file_path, 0, options)?;
+            return Ok(0);
+        }
+        loop {
+            // Note: stream_position, does a system call(lseek(fd, 0, SEEK_CUR)) to get the current offset, any better way?
+            let record_pos: usize = reader.stream_position()?.try_into().unwrap();
+            let record: Record =
+                match decode_from_std_read::<Record, _, _>(reader, config::standard()) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        if e.to_string().contains("UnexpectedEof") {
+                            return Ok(record_pos); // reached EOF
+                        } else {
+                            return Err(e.into()); // real error
+                        }
+                    }
+                };
 
-            loop {
-                // Note: stream_position, does a system call(lseek(fd, 0, SEEK_CUR)) to get the current offset, any better way?
-                let disk_entry_pos: usize = reader.stream_position()?.try_into().unwrap();
-                let disk_entry: Entry =
-                    match decode_from_std_read::<Entry, _, _>(&mut reader, config::standard()) {
-                        Ok(e) => e,
-                        Err(e) => {
-                            if e.to_string().contains("UnexpectedEof") {
-                                break; // reached EOF
-                            } else {
-                                return Err(e.into()); // real error
-                            }
+            match record {
+                Record::Entry(disk_entry) if disk_entry.is_padding() => {
+                    // Preallocated file that wasn't sealed, e.g. after a crash
+                    Self::cut_padding(file_path, record_pos, options)?;
+                    return Ok(record_pos);
+                }
+                Record::Entry(disk_entry) => {
+                    if let Err(e) = disk_entry.verify(mac_secret, &file_name, record_pos) {
+                        // A write torn by a crash in a preallocated file is followed by padding, not by EOF
+                        let is_torn = matches!(
+                            e.downcast_ref::<BitcaskError>(),
+                            Some(BitcaskError::Corrupted { .. })
+                        );
+                        if is_torn && Self::is_padding_until_eof(reader)? {
+                            Self::cut_padding(file_path, record_pos, options)?;
+                            return Ok(record_pos);
                         }
+                        return Err(e);
+                    }
+                    loaded.observe(disk_entry.sequence, disk_entry.timestamp);
+                    Self::load_entry(key_dir, disk_entry, &file_name, record_pos, now);
+                }
+                Record::RangeTombstone(tombstone) => {
+                    tombstone.verify(mac_secret, &file_name, record_pos)?;
+                    loaded.observe(tombstone.sequence, tombstone.timestamp);
+                    // Only keys loaded so far are older than the tombstone
+                    key_dir.remove_range(&tombstone.start, tombstone.end.as_deref());
+                }
+                Record::SequenceMark(mark) => {
+                    mark.verify(mac_secret, &file_name, record_pos)?;
+                    loaded.next_sequence = loaded.next_sequence.max(mark.next_sequence);
+                }
+                Record::BatchHeader {
+                    entries_count,
+                    length,
+                    checksum,
+                } => {
+                    let body_pos: usize = reader.stream_position()?.try_into().unwrap();
+                    let Some(body) = Self::read_batch_body(reader, length, checksum, &file_name, record_pos)? else {
+                        // Crashed while writing the batch, it may be followed by the padding of a preallocated file
+                        Self::cut_padding(file_path, record_pos, options)?;
+                        return Ok(record_pos);
                     };
 
-                if disk_entry.is_deleted {
-                    key_dir.remove(&disk_entry.key);
-                } else {
-                    // We don't need to check the timestamp as we sorted the files by id(time) already
-                    key_dir.insert(
-                        disk_entry.key,
-                        DirEntry::new(file_name.clone(), disk_entry_pos, disk_entry.timestamp),
-                    );
+                    let mut offset = 0;
+                    for _ in 0..entries_count {
+                        let (record, read): (Record, usize) =
+                            decode_from_slice(&body[offset..], config::standard())?;
+                        let Record::Entry(disk_entry) = record else {
+                            bail!(BitcaskError::Corrupted {
+                                file_name: file_name.clone(),
+                                offset: body_pos + offset
+                            });
+                        };
+                        disk_entry.verify(mac_secret, &file_name, body_pos + offset)?;
+                        loaded.observe(disk_entry.sequence, disk_entry.timestamp);
+                        Self::load_entry(key_dir, disk_entry, &file_name, body_pos + offset, now);
+                        offset += read;
+                    }
                 }
             }
+        }
+    }
 
-            files_pool.insert(file_name, reader.into_inner());
+    /// Reads the body of a batch whose header was just read from `reader`, `record_pos` is where the header starts.
+    ///
+    /// Returns `None` for a batch torn by a crash: its body runs past the end of the file, or it doesn't match its
+    /// checksum and only padding follows it. A mismatch anywhere else is corruption.
+    pub fn read_batch_body(
+        reader: &mut BufReader<File>,
+        length: u64,
+        checksum: u32,
+        file_name: &str,
+        record_pos: usize,
+    ) -> Result<Option<Vec<u8>>> {
+        // Checked before allocating, the length comes from the file
+        let remaining_b = reader
+            .get_ref()
+            .metadata()?
+            .len()
+            .saturating_sub(reader.stream_position()?);
+        if length > remaining_b {
+            return Ok(None);
+        }
+        let mut body = vec![0; length.try_into()?];
+        reader.read_exact(&mut body)?;
+        if crc32fast::hash(&body) != checksum {
+            if Self::is_padding_until_eof(reader)? {
+                return Ok(None);
+            }
+            bail!(BitcaskError::Corrupted {
+                file_name: file_name.to_string(),
+                offset: record_pos
+            });
         }
+        Ok(Some(body))
+    }
+
+    fn is_padding_until_eof(reader: &mut impl Read) -> Result<bool> {
+        let mut chunk = vec![0; 64 * 1024]; // 64 KB
+        loop {
+            let read = reader.read(&mut chunk)?;
+            if read == 0 {
+                return Ok(true);
+            }
+            if chunk[..read].iter().any(|byte| *byte != 0) {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Gives back the space preallocated after `data_end`, along with a torn record, only the writer modifies
+    /// data files.
+    fn cut_padding(file_path: &Path, data_end: usize, options: &Options) -> Result<()> {
+        if options.read_write {
+            OpenOptions::new()
+                .write(true)
+                .open(file_path)?
+                .set_len(data_end.try_into()?)
+                .context("Failed to truncate preallocated data file")?;
+        }
+        Ok(())
+    }
+
+    fn open_working_file(directory: &Path, id: usize, options: &Options) -> Result<WorkingFile> {
+        if options.preallocate {
+            WorkingFile::open_preallocated(directory, id, options.max_data_size)
+        } else {
+            WorkingFile::open(directory, id)
+        }
+    }
 
-        Ok((key_dir, files_pool))
+    fn load_entry(key_dir: &mut KeyDir, disk_entry: Entry, file_name: &str, entry_pos: usize, now: u64) {
+        if disk_entry.is_deleted || disk_entry.is_expired(now) {
+            key_dir.remove(&disk_entry.key);
+        } else {
+            // We don't need to check the timestamp as we sorted the files by id(time) already
+            key_dir.insert(
+                disk_entry.key,
+                DirEntry::new(
+                    file_name.to_string(),
+                    entry_pos,
+                    disk_entry.timestamp,
+                    disk_entry.expires_at,
+                    disk_entry.sequence,
+                ),
+            );
+        }
     }
 
     pub fn get(&mut self, key: &[u8]) -> Result<Vec<u8>> {
-        let dir_entry = self
-            .key_dir
+        self.get_with_dir_entry(key)?
+            .map(|(value, _)| value)
+            .ok_or_else(|| BitcaskError::KeyNotFound.into())
+    }
+
+    /// Returns the value of a live key along with the key dir entry it was read from.
+    pub fn get_with_dir_entry(&mut self, key: &[u8]) -> Result<Option<(Vec<u8>, DirEntry)>> {
+        let Some(dir_entry) = self.key_dir.get(key).cloned() else {
+            return Ok(None);
+        };
+        if dir_entry.is_expired(self.options.clock.current(), self.options.expiry_secs) {
+            // Expired entries are dropped lazily, startup and merge won't bring them back
+            self.key_dir.remove(key);
+            return Ok(None);
+        }
+        Ok(Some((self.read_entry(&dir_entry)?.value, dir_entry)))
+    }
+
+    pub fn get_with_meta(&mut self, key: &[u8]) -> Result<VersionedValue> {
+        let dir_entry = self.live_dir_entry(key).cloned().ok_or(BitcaskError::KeyNotFound)?;
+        let entry = self.read_entry(&dir_entry)?;
+        Ok(VersionedValue {
+            value: entry.value,
+            version: dir_entry.version(),
+            timestamp: dir_entry.timestamp,
+            flags: entry.flags,
+        })
+    }
+
+    /// Reads the values of the live keys in data file and offset order, so each file is read front to back
+    /// instead of seeking back and forth. Values are returned in the order of `keys`.
+    pub fn get_many<K: AsRef<[u8]>>(&mut self, keys: &[K]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut lookups: Vec<(usize, DirEntry)> = keys
+            .iter()
+            .enumerate()
+            .filter_map(|(index, key)| Some((index, self.live_dir_entry(key.as_ref())?.clone())))
+            .collect();
+        lookups.sort_by_key(|(_, dir_entry)| {
+            (WorkingFile::parse_file_id(&dir_entry.file_name), dir_entry.entry_pos)
+        });
+
+        let mut values = vec![None; keys.len()];
+        for (index, dir_entry) in lookups {
+            values[index] = Some(self.read_entry(&dir_entry)?.value);
+        }
+        Ok(values)
+    }
+
+    /// Returns the key dir entry of a key, unless it's missing or expired.
+    pub fn live_dir_entry(&self, key: &[u8]) -> Option<&DirEntry> {
+        let now = self.options.clock.current();
+        self.key_dir
             .get(key)
-            .cloned()
-            .ok_or_else(|| anyhow::anyhow!("Key-Value not found"))?;
-        let mut data_file: &mut File = self.get_file_containing_key(dir_entry.file_name)?;
-        data_file.seek(SeekFrom::Start(dir_entry.entry_pos.try_into()?))?;
+            .filter(|dir_entry| !dir_entry.is_expired(now, self.options.expiry_secs))
+    }
 
-        let entry: Entry = decode_from_std_read(&mut data_file, config::standard())
-            .context("Error Decoding Entry from file")?;
+    fn read_entry(&mut self, dir_entry: &DirEntry) -> Result<Entry> {
+        let mut data_file: &File = self.get_file_containing_key(dir_entry.file_name.clone())?;
+        data_file.seek(SeekFrom::Start(dir_entry.entry_pos.try_into()?))?;
 
-        Ok(entry.value)
+        let entry = Entry::read_from(&mut data_file, &dir_entry.file_name, dir_entry.entry_pos)?;
+        entry.verify(
+            self.options.mac_secret.as_deref(),
+            &dir_entry.file_name,
+            dir_entry.entry_pos,
+        )?;
+        Ok(entry)
     }
 
-    fn get_file_containing_key(&mut self, file_name: String) -> Result<&mut File> {
-        if file_name == self.working_file.as_ref().unwrap().get_file_name() {
-            Ok(self.working_file.as_mut().unwrap().get_mut_file_ref())
+    fn get_file_containing_key(&mut self, file_name: String) -> Result<&File> {
+        let is_working_file = self
+            .working_file
+            .as_ref()
+            .is_some_and(|wf| wf.get_file_name() == file_name);
+        if is_working_file {
+            // The entry may still be in the buffer of a writer waiting for its group commit
+            self.flush_working_file()?;
+            Ok(self.working_file.as_ref().unwrap().get_file_ref())
         } else {
             if self.files_pool.contains_key(&file_name) {
                 Ok(self.files_pool.get_mut(&file_name).unwrap())
@@ -260,68 +838,742 @@ impl Bitcask {
     }
 
     pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
-        let entry = Entry::new(key.to_vec(), value.to_vec());
+        let entry = Entry::new(key.to_vec(), value.to_vec(), None);
+        self.put_entry(entry)?;
+        Ok(())
+    }
+
+    pub fn put_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        let expires_at = self.expires_at(ttl);
+        let entry = Entry::new(key.to_vec(), value.to_vec(), Some(expires_at));
         self.put_entry(entry)?;
         Ok(())
     }
 
+    pub fn put_with_flags(&mut self, key: &[u8], value: &[u8], flags: u32, ttl: Option<Duration>) -> Result<()> {
+        let expires_at = ttl.map(|ttl| self.expires_at(ttl));
+        let entry = Entry::new(key.to_vec(), value.to_vec(), expires_at).with_flags(flags);
+        self.put_entry(entry)
+    }
+
+    pub fn put_many<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, pairs: &[(K, V)]) -> Result<()> {
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        let entries = pairs
+            .iter()
+            .map(|(key, value)| Entry::new(key.as_ref().to_vec(), value.as_ref().to_vec(), None))
+            .collect();
+        self.put_entries(entries, false)
+    }
+
     fn put_entry(&mut self, entry: Entry) -> Result<()> {
-        let wf = self.working_file.get_or_insert_with(|| {
-            self.working_file_id = Some(0);
-            WorkingFile::open(&self.directory, 0).unwrap()
-        });
-        let wf_bytes_count = wf.bytes_count();
-        let bytes_written = wf
-            .append(&entry)
-            .context("Error Appending to the working file")?;
-
-        self.key_dir.insert(
-            entry.key,
-            DirEntry::new(
-                wf.get_file_name(),
-                wf.bytes_count() - bytes_written,
-                entry.timestamp,
-            ),
-        );
+        self.put_entries(vec![entry], false)
+    }
+
+    /// Appends the entries to the working file and applies them to the key dir.
+    /// When `atomic` is set, they're framed as a batch that the startup scan loads entirely or not at all.
+    fn put_entries(&mut self, mut entries: Vec<Entry>, atomic: bool) -> Result<()> {
+        self.ensure_writable()?;
+        self.working_file()?;
+        let timestamp = self.options.clock.now();
+        let first_sequence = self.next_sequence;
+        for entry in entries.iter_mut() {
+            entry.stamp(self.next_sequence, timestamp);
+            self.next_sequence += 1;
+            if let Some(secret) = &self.options.mac_secret {
+                entry.sign(secret);
+            }
+        }
+        let wf = self.working_file()?;
+        let records: Vec<Record> = entries.into_iter().map(Record::Entry).collect();
+        let appended = if atomic {
+            wf.append_batch(&records)
+                .context("Error Appending batch to the working file")
+        } else {
+            wf.append_all(&records)
+                .context("Error Appending to the working file")
+        };
+        let entries_pos = match appended {
+            Ok(entries_pos) => entries_pos,
+            Err(e) => {
+                // The working file dropped the records, their sequence numbers weren't used
+                self.next_sequence = first_sequence;
+                self.replace_torn_working_file()?;
+                return Err(e);
+            }
+        };
+
+        // Nothing is visible to readers before the whole batch is written
+        let file_name = self.working_file()?.get_file_name();
+        for (record, entry_pos) in records.into_iter().zip(entries_pos) {
+            let Record::Entry(entry) = record else {
+                continue;
+            };
+            if entry.is_deleted {
+                self.key_dir.remove(&entry.key);
+            } else {
+                self.key_dir.insert(
+                    entry.key,
+                    DirEntry::new(
+                        file_name.clone(),
+                        entry_pos,
+                        entry.timestamp,
+                        entry.expires_at,
+                        entry.sequence,
+                    ),
+                );
+            }
+        }
+
+        self.rotate_working_file_if_full()
+    }
+
+    fn ensure_writable(&self) -> Result<()> {
+        if !self.options.read_write {
+            bail!("Writes require the datastore to be opened with read_write");
+        }
+        Ok(())
+    }
 
+    /// The working file, a new one is opened after the newest data file if there's none, e.g. after a merge failed.
+    fn working_file(&mut self) -> Result<&mut WorkingFile> {
+        if self.working_file.is_none() {
+            let id = WorkingFile::get_working_file_id(&self.directory)?;
+            self.working_file = Some(
+                Self::open_working_file(&self.directory, id, &self.options)
+                    .context("Couldn't open the working file")?,
+            );
+            self.working_file_id = Some(id);
+        }
+        Ok(self.working_file.as_mut().unwrap())
+    }
+
+    fn rotate_working_file_if_full(&mut self) -> Result<()> {
         // TODO: when migrating from bincode, we can have the number of bytes to be written before actually write
         // Therefore, we can move the below check before writing and refactor above insertion. To avoid having files > max size.
-        let is_wf_capacity_exceeded = bytes_written + wf_bytes_count > self.options.max_data_size;
+        let is_wf_capacity_exceeded = self.working_file()?.bytes_count() > self.options.max_data_size;
         if is_wf_capacity_exceeded {
+            self.flush_working_file()?;
+            // The group commit only syncs the current working file, pending writes of the full one are synced here
+            let sync_on_put = self.options.sync_on_put;
+            let full_wf = self.working_file()?;
+            full_wf.seal()?;
+            if sync_on_put {
+                full_wf.sync()?;
+            }
             self.working_file_id = Some(self.working_file_id.unwrap_or_default() + 1);
-            self.working_file = Some(WorkingFile::open(
+            self.working_file = Some(Self::open_working_file(
                 &self.directory,
                 self.working_file_id.unwrap_or_default(),
+                &self.options,
             )?)
         }
         Ok(())
     }
 
+    /// Current time of the datastore clock, in Unix millis.
+    pub fn now(&self) -> u64 {
+        self.options.clock.current()
+    }
+
+    fn expires_at(&self, ttl: Duration) -> u64 {
+        let ttl_millis = ttl.as_millis().try_into().unwrap_or(u64::MAX);
+        self.options.clock.current().saturating_add(ttl_millis)
+    }
+
+    pub fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let entries = batch
+            .operations
+            .into_iter()
+            .map(|operation| match operation {
+                BatchOperation::Put { key, value, ttl } => {
+                    let expires_at = ttl.map(|ttl| self.expires_at(ttl));
+                    Entry::new(key, value, expires_at)
+                }
+                BatchOperation::Delete { key } => {
+                    let mut entry = Entry::new(key, vec![b' '], None); // tombstone entry
+                    entry.mark_deleted();
+                    entry
+                }
+            })
+            .collect();
+        self.put_entries(entries, true)
+    }
+
     pub fn delete(&mut self, key: &[u8]) -> Result<()> {
-        if !self.key_dir.contains_key(key) {
-            bail!("Key-Value not found");
+        if self.live_dir_entry(key).is_none() {
+            bail!(BitcaskError::KeyNotFound);
         }
-        let mut entry= Entry::new(key.to_vec(),vec![b' ']); // tombstone entry
+        let mut entry = Entry::new(key.to_vec(), vec![b' '], None); // tombstone entry
         entry.mark_deleted();
         self.put_entry(entry)?;
+        Ok(())
+    }
 
-        self.key_dir.remove(key);
+    /// Deletes the live keys within the bounds with a single range tombstone, returns how many were deleted.
+    pub fn delete_range(&mut self, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> Result<usize> {
+        self.ensure_writable()?;
+        let (start, end) = keydir::half_open(start, end);
+        let now = self.options.clock.current();
+        // Removed from a snapshot first, nothing changes if writing the tombstone fails
+        let mut key_dir = self.key_dir.clone();
+        let deleted_count = key_dir
+            .remove_range(&start, end.as_deref())
+            .iter()
+            .filter(|(_, dir_entry)| !dir_entry.is_expired(now, self.options.expiry_secs))
+            .count();
+        if deleted_count == 0 {
+            return Ok(0);
+        }
+
+        self.working_file()?;
+        let mut tombstone = RangeTombstone::new(start, end, self.next_sequence, self.options.clock.now());
+        self.next_sequence += 1;
+        if let Some(secret) = &self.options.mac_secret {
+            tombstone.sign(secret);
+        }
+        let appended = self
+            .working_file()?
+            .append(&Record::RangeTombstone(tombstone))
+            .context("Error Appending to the working file");
+        if let Err(e) = appended {
+            self.next_sequence -= 1;
+            self.replace_torn_working_file()?;
+            return Err(e);
+        }
+        self.key_dir = key_dir;
+        self.rotate_working_file_if_full()?;
+        Ok(deleted_count)
+    }
+
+    pub fn delete_prefix(&mut self, prefix: &[u8]) -> Result<usize> {
+        let end = keydir::prefix_end(prefix).map_or(Bound::Unbounded, Bound::Excluded);
+        self.delete_range(Bound::Included(prefix.to_vec()), end)
+    }
+
+    pub fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool> {
+        let current = self.get_with_dir_entry(key)?.map(|(value, _)| value);
+        if current.as_deref() != expected {
+            return Ok(false);
+        }
+        match new {
+            Some(value) => self.put(key, value)?,
+            None if current.is_some() => self.delete(key)?,
+            None => {}
+        }
+        Ok(true)
+    }
+
+    pub fn put_if_absent(&mut self, key: &[u8], value: &[u8]) -> Result<bool> {
+        if self.live_dir_entry(key).is_some() {
+            return Ok(false);
+        }
+        self.put(key, value)?;
+        Ok(true)
+    }
+
+    pub fn delete_if_version(&mut self, key: &[u8], version: u64) -> Result<bool> {
+        if self
+            .live_dir_entry(key)
+            .is_none_or(|dir_entry| dir_entry.version() != version)
+        {
+            return Ok(false);
+        }
+        self.delete(key)?;
+        Ok(true)
+    }
+
+    pub fn version(&self, key: &[u8]) -> Option<u64> {
+        self.live_dir_entry(key).map(DirEntry::version)
+    }
+
+    pub fn keys(&self) -> Keys {
+        Keys::new(
+            self.key_dir.clone().into_iter(),
+            self.options.clock.current(),
+            self.options.expiry_secs,
+        )
+    }
+
+    /// Writes the working file buffer, iterators read values straight from the data files.
+    /// If the write tears the working file, the buffer moves to a new one and is written there.
+    fn flush_working_file(&mut self) -> Result<()> {
+        let Some(wf) = &mut self.working_file else {
+            return Ok(());
+        };
+        let result = wf.flush();
+        if result.is_err() && wf.is_torn() {
+            self.replace_torn_working_file()?;
+            return self.working_file()?.flush();
+        }
+        result
+    }
+
+    /// Seals a working file torn by a failed write and appends its buffered records to a new working file,
+    /// along with the key dir entries pointing at them. Does nothing unless the working file is torn.
+    fn replace_torn_working_file(&mut self) -> Result<()> {
+        if !self.working_file.as_ref().is_some_and(WorkingFile::is_torn) {
+            return Ok(());
+        }
+        let id = WorkingFile::get_working_file_id(&self.directory)?;
+        let mut wf = Self::open_working_file(&self.directory, id, &self.options)
+            .context("Couldn't replace a working file torn by a failed write")?;
+        let torn_wf = self.working_file.take().unwrap();
+        let torn_file_name = torn_wf.get_file_name();
+        let flushed_b = torn_wf.flushed_bytes_count();
+        let moved_pos = wf.append_bytes(&torn_wf.take_torn());
+
+        let file_name = wf.get_file_name();
+        let moved: Vec<(Vec<u8>, DirEntry)> = self
+            .key_dir
+            .iter()
+            .filter(|(_, dir_entry)| dir_entry.file_name == torn_file_name && dir_entry.entry_pos >= flushed_b)
+            .map(|(key, dir_entry)| {
+                let dir_entry = DirEntry {
+                    file_name: file_name.clone(),
+                    entry_pos: dir_entry.entry_pos - flushed_b + moved_pos,
+                    ..dir_entry.clone()
+                };
+                (key.clone(), dir_entry)
+            })
+            .collect();
+        for (key, dir_entry) in moved {
+            self.key_dir.insert(key, dir_entry);
+        }
+        self.working_file = Some(wf);
+        self.working_file_id = Some(id);
         Ok(())
     }
 
+    pub fn iter(&mut self) -> Result<Iter> {
+        self.flush_working_file()?;
+        Ok(Iter::new(
+            self.keys(),
+            self.directory.clone(),
+            self.options.mac_secret.clone(),
+        ))
+    }
+
+    pub fn range(&mut self, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> Result<Iter> {
+        self.flush_working_file()?;
+        let Some(snapshot) = self.key_dir.clone().into_range(start, end) else {
+            bail!("Range scans require the datastore to be opened with an ordered index");
+        };
+        let keys = Keys::new(snapshot, self.options.clock.current(), self.options.expiry_secs);
+        Ok(Iter::new(
+            keys,
+            self.directory.clone(),
+            self.options.mac_secret.clone(),
+        ))
+    }
+
+    pub fn prefix(&mut self, prefix: &[u8]) -> Result<Iter> {
+        let end = keydir::prefix_end(prefix).map_or(Bound::Unbounded, Bound::Excluded);
+        self.range(Bound::Included(prefix.to_vec()), end)
+    }
+
+    pub fn scan_prefix(&mut self, prefix: &[u8]) -> Result<Iter> {
+        self.flush_working_file()?;
+        let snapshot = self.key_dir.clone().into_prefix(prefix);
+        let keys = Keys::new(snapshot, self.options.clock.current(), self.options.expiry_secs);
+        Ok(Iter::new(
+            keys,
+            self.directory.clone(),
+            self.options.mac_secret.clone(),
+        ))
+    }
+
+    pub fn len(&self) -> usize {
+        self.key_dir.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.key_dir.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.live_dir_entry(key).is_some()
+    }
+
     pub fn list_keys(&self) -> Result<Vec<Vec<u8>>> {
-        Ok(self.key_dir.keys().into_iter().cloned().collect())
+        Ok(self.keys().collect())
+    }
+
+    /// Snapshot of the live keys and their key dir entries, sorted by data file and offset.
+    fn live_entries_in_disk_order(&self) -> Vec<(Vec<u8>, DirEntry)> {
+        let now = self.options.clock.current();
+        let mut live_entries: Vec<(Vec<u8>, DirEntry)> = self
+            .key_dir
+            .iter()
+            .filter(|(_, dir_entry)| !dir_entry.is_expired(now, self.options.expiry_secs))
+            .map(|(key, dir_entry)| (key.clone(), dir_entry.clone()))
+            .collect();
+        live_entries.sort_by(|(_, a), (_, b)| {
+            (WorkingFile::parse_file_id(&a.file_name), a.entry_pos)
+                .cmp(&(WorkingFile::parse_file_id(&b.file_name), b.entry_pos))
+        });
+        live_entries
+    }
+
+    pub fn entries(&mut self) -> Result<Entries> {
+        self.flush_working_file()?;
+        Ok(Entries::new(
+            self.directory.clone(),
+            self.options.mac_secret.clone(),
+            self.live_entries_in_disk_order(),
+        ))
+    }
+
+    pub fn merge(&mut self) -> Result<()> {
+        /*
+         * Every existing data file (including the current working file) is sealed and its live entries are
+         * rewritten into new data files, then the old files are removed. Deleted, overwritten and expired
+         * entries are dropped on the way.
+         * Merged files get ids after the sealed ones and the new working file comes after them, so the startup
+         * scan still sees the newest value of a key last. If we crash before removing the old files, the merged
+         * files only duplicate the latest values and the startup scan ends up with the same key dir.
+         */
+        if !self.options.read_write {
+            bail!("Merge requires the datastore to be opened with read_write");
+        }
+        let result = self.merge_data_files();
+        if self.working_file.is_none() {
+            // The merge failed after sealing the working file, writes go on in a new one after the files it left
+            if let Err(e) = self.working_file() {
+                return result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    fn merge_data_files(&mut self) -> Result<()> {
+        let sealed_files = WorkingFile::list_data_files(&self.directory)?;
+        if let Some(mut wf) = self.working_file.take() {
+            wf.seal()?;
+        }
+        let mut next_id = WorkingFile::get_working_file_id(&self.directory)?;
+
+        // Read live entries in disk order, that's mostly sequential I/O
+        let live_entries = self.live_entries_in_disk_order();
+        let mut merged_key_dir = self.key_dir.empty_like();
+        // Versions must not go back once the entries holding the newest ones are dropped
+        let mut first_mf = WorkingFile::open(&self.directory, next_id)?;
+        next_id += 1;
+        let mut mark = SequenceMark::new(self.next_sequence);
+        if let Some(secret) = &self.options.mac_secret {
+            mark.sign(secret);
+        }
+        first_mf.append(&Record::SequenceMark(mark))?;
+        let mut merge_file = Some(first_mf);
+        for (key, dir_entry) in live_entries {
+            let entry = self.read_entry(&dir_entry)?;
+            let is_mf_capacity_exceeded = merge_file
+                .as_ref()
+                .is_none_or(|mf| mf.bytes_count() > self.options.max_data_size);
+            if is_mf_capacity_exceeded {
+                if let Some(mut full_mf) = merge_file.take() {
+                    full_mf.sync()?;
+                }
+                merge_file = Some(WorkingFile::open(&self.directory, next_id)?);
+                next_id += 1;
+            }
+            let mf = merge_file.as_mut().unwrap();
+            let dir_entry = DirEntry::new(
+                mf.get_file_name(),
+                mf.bytes_count(),
+                entry.timestamp,
+                entry.expires_at,
+                entry.sequence,
+            );
+            mf.append(&Record::Entry(entry))
+                .context("Error Appending to the merge file")?;
+            merged_key_dir.insert(key, dir_entry);
+        }
+        if let Some(mut mf) = merge_file {
+            // Merged files must be durable before the files they replace are removed
+            mf.sync()?;
+        }
+
+        self.key_dir = merged_key_dir;
+        for file_path in sealed_files {
+            if let Some(file_name) = file_path.file_name().and_then(|s| s.to_str()) {
+                self.files_pool.remove(file_name);
+                self.note_compacted(&file_path, file_name);
+            }
+            fs::remove_file(&file_path).context("Failed to remove merged data file")?;
+        }
+        self.working_file_id = Some(next_id);
+        self.working_file = Some(Self::open_working_file(&self.directory, next_id, &self.options)?);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Result<Stats> {
+        let data_files = WorkingFile::list_data_files(&self.directory)?;
+        let mut data_size = 0;
+        for file_path in &data_files {
+            let working_file = self.working_file.as_ref().filter(|wf| {
+                file_path.file_name().and_then(|s| s.to_str()) == Some(wf.get_file_name().as_str())
+            });
+            data_size += match working_file {
+                // Its size on disk may include buffered bytes or preallocated space
+                Some(wf) => wf.bytes_count().try_into()?,
+                None => fs::metadata(file_path)?.len(),
+            };
+        }
+        Ok(Stats {
+            keys: self.key_dir.len(),
+            expiring_keys: self
+                .key_dir
+                .iter()
+                .filter(|(_, dir_entry)| dir_entry.expires_at.is_some())
+                .count(),
+            data_files: data_files.len(),
+            data_size,
+        })
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        self.flush_working_file()?;
+        match &mut self.working_file {
+            Some(wf) => wf.sync(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn close(&mut self) -> Result<()> {
+        self.sync()
+    }
+
+    /// Sequence number of the next entry, every entry written so far has a smaller one.
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Writes the working file buffer for the group commit, returns the sequence number and the log position it's
+    /// written up to and, with `sync_on_put`, the file to sync once the engine lock is released.
+    pub fn flush_for_commit(&mut self) -> Result<(u64, LogPosition, Option<Arc<File>>)> {
+        self.flush_working_file()?;
+        let file_to_sync = self
+            .working_file
+            .as_ref()
+            .filter(|_| self.options.sync_on_put)
+            .map(WorkingFile::shared_file);
+        Ok((self.next_sequence, self.log_end()?, file_to_sync))
+    }
+
+    /// Position where the records written to the data files end, buffered ones excluded.
+    pub fn log_end(&self) -> Result<LogPosition> {
+        if let (Some(wf), Some(id)) = (&self.working_file, self.working_file_id) {
+            return LogPosition::new(id, wf.flushed_bytes_count());
+        }
+        match self.data_files()?.pop() {
+            Some((file_path, size)) => {
+                let file_name = file_path.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+                LogPosition::new(WorkingFile::parse_file_id(file_name).unwrap_or_default(), size)
+            }
+            None => Ok(LogPosition::default()),
+        }
+    }
+
+    /// Id of the newest data file removed by a merge, or by replication, since the datastore was opened.
+    pub fn compacted_until(&self) -> Option<usize> {
+        self.compacted_until
     }
 
-    pub fn merge(&self) -> Result<()> {
-        todo!()
+    pub fn directory(&self) -> &Path {
+        &self.directory
     }
 
-    pub fn sync(&self) -> Result<()> {
-        todo!()
+    pub fn mac_secret(&self) -> Option<&[u8]> {
+        self.options.mac_secret.as_deref()
     }
 
-    pub fn close(&self) -> Result<()> {
-        todo!()
+    /// Paths and sizes of the data files, from oldest to newest, for shipping them to followers.
+    /// The size of the working file only covers the records written to it so far, not the buffered ones.
+    pub fn data_files(&self) -> Result<Vec<(PathBuf, usize)>> {
+        let mut data_files = Vec::new();
+        for file_path in WorkingFile::list_data_files(&self.directory)? {
+            let working_file = self.working_file.as_ref().filter(|wf| {
+                file_path.file_name().and_then(|s| s.to_str()) == Some(wf.get_file_name().as_str())
+            });
+            let size = match working_file {
+                Some(wf) => wf.flushed_bytes_count(),
+                None => fs::metadata(&file_path)?.len().try_into()?,
+            };
+            data_files.push((file_path, size));
+        }
+        Ok(data_files)
+    }
+
+    /// Appends bytes shipped by the leader to a data file of this follower, creating it if needed, and loads
+    /// the records they complete into the key dir. Returns how many bytes were used, the remaining ones
+    /// start a record that isn't fully received yet and are left out of the file.
+    pub fn append_replicated(&mut self, file_name: &str, bytes: &[u8]) -> Result<usize> {
+        if self.options.read_write {
+            bail!("Replicated data is only applied to a follower, not to a writer");
+        }
+        let file_path = self.directory.join(file_name);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .context("Couldn't open the replicated data file")?;
+        let offset = file.metadata()?.len();
+        file.write_all(bytes)?;
+
+        let mut reader = BufReader::with_capacity(64 * 1024, file);
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut loaded = LoadedRecords {
+            next_sequence: self.next_sequence,
+            newest_timestamp: 0,
+        };
+        let now = self.options.clock.current();
+        let end = Self::load_data_file(&mut self.key_dir, &mut reader, &file_path, &self.options, now, &mut loaded)?;
+        reader.into_inner().set_len(end.try_into()?)?;
+        self.next_sequence = loaded.next_sequence;
+        self.options.clock.observe(loaded.newest_timestamp);
+        Ok(end - usize::try_from(offset)?)
+    }
+
+    /// Removes a data file of this follower that the leader doesn't have anymore, e.g. after a merge, along
+    /// with the keys still pointing to it.
+    pub fn remove_replicated(&mut self, file_name: &str) -> Result<()> {
+        if self.options.read_write {
+            bail!("Replicated data is only applied to a follower, not to a writer");
+        }
+        let stale_keys: Vec<Vec<u8>> = self
+            .key_dir
+            .iter()
+            .filter(|(_, dir_entry)| dir_entry.file_name == file_name)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in stale_keys {
+            self.key_dir.remove(&key);
+        }
+        self.files_pool.remove(file_name);
+        let file_path = self.directory.join(file_name);
+        self.note_compacted(&file_path, file_name);
+        fs::remove_file(file_path).context("Failed to remove replicated data file")
+    }
+
+    /// Keeps track of the newest data file removed, for readers of the changes which didn't get to it yet.
+    /// Empty files don't count, no change is lost with them.
+    fn note_compacted(&mut self, file_path: &Path, file_name: &str) {
+        if fs::metadata(file_path).is_ok_and(|metadata| metadata.len() > 0) {
+            self.compacted_until = self.compacted_until.max(WorkingFile::parse_file_id(file_name));
+        }
+    }
+
+    pub fn is_read_write(&self) -> bool {
+        self.options.read_write
+    }
+
+    /// Opens a read-only datastore for writing, e.g. to promote a follower once its leader is gone.
+    /// Writes go to a new working file, after the existing data files.
+    pub fn promote(&mut self) -> Result<()> {
+        if self.options.read_write {
+            return Ok(());
+        }
+        let lock_file = Self::try_acquire_write_lock(&self.directory)?;
+        let working_file_id = WorkingFile::get_working_file_id(&self.directory)?;
+        self.working_file = Some(Self::open_working_file(&self.directory, working_file_id, &self.options)?);
+        self.working_file_id = Some(working_file_id);
+        self._lock = Some(lock_file);
+        self.options.read_write = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    fn open(name: &str) -> (BitcaskHandler, PathBuf) {
+        let dir = env::temp_dir().join(format!("bitcask-engine-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let options = Options {
+            read_write: true,
+            ..Default::default()
+        };
+        (BitcaskHandler::open(&dir, Some(options)).unwrap(), dir)
+    }
+
+    fn reopen(dir: &Path) -> BitcaskHandler {
+        let options = Options {
+            read_write: true,
+            ..Default::default()
+        };
+        BitcaskHandler::open(dir, Some(options)).unwrap()
+    }
+
+    #[test]
+    fn failed_flush_persists_nothing() {
+        let (db, dir) = open("failed-flush");
+        db.put(b"a", b"1").unwrap();
+        let file_path = dir.join(WorkingFile::file_name(0));
+        let next_sequence = {
+            let mut engine = db.engine();
+            let read_only = File::open(&file_path).unwrap();
+            engine.working_file.as_mut().unwrap().replace_file(read_only);
+            engine.next_sequence()
+        };
+
+        // Bigger than the buffer, it's flushed right away
+        let value = vec![b'x'; 2 * 1024 * 1024];
+        assert!(db.put(b"big", &value).is_err());
+        assert!(db.get(b"big").is_err());
+        assert_eq!(db.engine().next_sequence(), next_sequence);
+
+        let writable = OpenOptions::new().read(true).append(true).open(&file_path).unwrap();
+        db.engine().working_file.as_mut().unwrap().replace_file(writable);
+        db.put(b"b", b"2").unwrap();
+        assert_eq!(db.get_with_meta(b"b").unwrap().version, next_sequence);
+        drop(db);
+
+        assert!(fs::metadata(&file_path).unwrap().len() < value.len() as u64);
+        let db = reopen(&dir);
+        assert!(db.get(b"big").is_err());
+        assert_eq!(db.get(b"a").unwrap(), b"1");
+        assert_eq!(db.get(b"b").unwrap(), b"2");
+    }
+
+    #[test]
+    fn torn_working_file_moves_its_records_to_a_new_one() {
+        let (db, dir) = open("torn-flush");
+        db.put(b"a", b"1").unwrap();
+        {
+            let mut engine = db.engine();
+            engine.put(b"b", b"2").unwrap();
+            engine.put(b"c", b"3").unwrap();
+            let wf = engine.working_file.as_mut().unwrap();
+            let flushed_b = wf.flushed_bytes_count();
+            wf.tear(5);
+
+            engine.flush_working_file().unwrap();
+            assert_eq!(engine.working_file_id, Some(1));
+            let torn_path = dir.join(WorkingFile::file_name(0));
+            assert_eq!(fs::metadata(torn_path).unwrap().len(), flushed_b as u64);
+        }
+        assert_eq!(db.get(b"b").unwrap(), b"2");
+        db.put(b"d", b"4").unwrap();
+        drop(db);
+
+        let db = reopen(&dir);
+        for (key, value) in [(b"a", b"1"), (b"b", b"2"), (b"c", b"3"), (b"d", b"4")] {
+            assert_eq!(db.get(key).unwrap(), value);
+        }
     }
 }
@@ -0,0 +1,46 @@
+use std::fmt;
+
+use crate::LogPosition;
+
+/// Errors raised by the engine that callers may need to tell apart from ordinary I/O failures.
+///
+/// They are returned wrapped in [`anyhow::Error`], use `err.downcast_ref::<BitcaskError>()` to match on them.
+#[derive(Debug)]
+pub enum BitcaskError {
+    /// The key doesn't exist, was deleted or expired.
+    KeyNotFound,
+    /// The record's CRC doesn't match its content (torn write, bit rot, ...).
+    Corrupted { file_name: String, offset: usize },
+    /// The record's MAC is missing or doesn't match, the data file was modified by someone
+    /// who doesn't hold the secret configured in [`crate::Options::mac_secret`].
+    Tampered { file_name: String, offset: usize },
+    /// A key read by a transaction was written or deleted before the transaction committed.
+    Conflict { key: Vec<u8> },
+    /// Another process has the directory open for writing, only one writer is allowed at a time.
+    Locked,
+    /// Changes after the position were removed by a merge before a [`crate::Changes`] reader got to them.
+    Compacted { position: LogPosition },
+}
+
+impl fmt::Display for BitcaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::KeyNotFound => write!(f, "Key-Value not found"),
+            Self::Corrupted { file_name, offset } => {
+                write!(f, "Corrupted entry in {file_name} at offset {offset}")
+            }
+            Self::Tampered { file_name, offset } => {
+                write!(f, "Tampered entry in {file_name} at offset {offset}")
+            }
+            Self::Conflict { key } => {
+                write!(f, "Transaction conflict on key {}", String::from_utf8_lossy(key))
+            }
+            Self::Locked => write!(f, "Bitcask directory is already open for writing by another process"),
+            Self::Compacted { position } => {
+                write!(f, "Changes after log position {position} were removed by a merge")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BitcaskError {}
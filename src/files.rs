@@ -1,69 +1,357 @@
 use std::{
-    fs::{self, File, OpenOptions}, path::{Path, PathBuf}
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use anyhow::{Context, Result};
-use bincode::{config, encode_into_std_write};
+use anyhow::{Context, Result, bail};
+use bincode::{config, encode_into_std_write, encode_to_vec};
 
-use crate::engine::Entry;
+use crate::engine::Record;
+
+// Appends are buffered until flushed, a full buffer is written right away to bound memory usage
+const BUFFER_CAPACITY: usize = 1024 * 1024; // 1 MB
+// Every data file starts with the magic followed by the version of the format of its records
+const MAGIC: &[u8; 4] = b"BCSK";
+const FORMAT_VERSION: u8 = 1;
+pub const HEADER_LENGTH: usize = MAGIC.len() + 1;
 
 pub struct WorkingFile {
-    file: File,
+    file: Arc<File>, // Shared with the group commit leader, to sync it without holding the engine lock
     path: PathBuf,
-    size_b: usize,
+    size_b: usize, // Including the buffered bytes
+    flushed_b: usize,
+    buffer: Vec<u8>,
+    // Preallocated files are bigger than their content, zero padded, until they're sealed
+    is_preallocated: bool,
+    // A flush failed after writing part of the buffer, the buffer can't be appended after it anymore
+    is_torn: bool,
 }
 
 impl WorkingFile {
     pub fn open(directory: &Path, id: usize) -> Result<Self> {
+        Self::create(directory, id, None)
+    }
+
+    /// Opens a new working file taking `size` bytes on disk upfront, so that it isn't fragmented by small
+    /// appends and its size doesn't change on every sync. It must be sealed to give back the unused space.
+    pub fn open_preallocated(directory: &Path, id: usize, size: usize) -> Result<Self> {
+        Self::create(directory, id, Some(size))
+    }
+
+    fn create(directory: &Path, id: usize, preallocated_size: Option<usize>) -> Result<Self> {
         // Working file is opened once and when closed, it's considered IMMUTABLE file
-        let file_path = directory.join(format!("working_file_{id}"));
-        let file = Self {
-            file: OpenOptions::new()
-                .read(true)
-                .append(true)
-                .create_new(true)
-                .open(&file_path)
-                .context("Couldn't create Working file")?,
+        let file_path = directory.join(Self::file_name(id));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .append(preallocated_size.is_none())
+            .create_new(true)
+            .open(&file_path)
+            .context("Couldn't create Working file")?;
+        (&file).write_all(MAGIC)?;
+        (&file).write_all(&[FORMAT_VERSION])?;
+        if let Some(size) = preallocated_size {
+            // Otherwise a crash could leave a file of zeros only, the header never reaching the disk
+            file.sync_data()?;
+            preallocate(&file, size).context("Couldn't preallocate Working file")?;
+        }
+        Ok(Self {
+            file: Arc::new(file),
             path: file_path,
-            size_b: 0,
+            size_b: HEADER_LENGTH,
+            flushed_b: HEADER_LENGTH,
+            buffer: Vec::new(),
+            is_preallocated: preallocated_size.is_some(),
+            is_torn: false,
+        })
+    }
+
+    pub fn append(&mut self, record: &Record) -> Result<usize> {
+        self.buffered(|wf| {
+            let bytes_written = encode_into_std_write(record, &mut wf.buffer, config::standard())?;
+            wf.size_b += bytes_written;
+            Ok(bytes_written)
+        })
+    }
+
+    /// Appends the records one after the other, unlike a batch they are loaded one by one at startup.
+    /// Returns the position of every record.
+    pub fn append_all(&mut self, records: &[Record]) -> Result<Vec<usize>> {
+        self.buffered(|wf| {
+            let mut records_pos = Vec::with_capacity(records.len());
+            for record in records {
+                records_pos.push(wf.size_b);
+                wf.size_b += encode_into_std_write(record, &mut wf.buffer, config::standard())?;
+            }
+            Ok(records_pos)
+        })
+    }
+
+    /// Appends the records as one batch, a header holding the checksum of the records followed by the records.
+    /// Returns the position of every record.
+    pub fn append_batch(&mut self, records: &[Record]) -> Result<Vec<usize>> {
+        let mut body = Vec::new();
+        let mut records_offsets = Vec::with_capacity(records.len());
+        for record in records {
+            records_offsets.push(body.len());
+            body.extend(encode_to_vec(record, config::standard())?);
+        }
+        let header = Record::BatchHeader {
+            entries_count: records.len().try_into()?,
+            length: body.len().try_into()?,
+            checksum: crc32fast::hash(&body),
         };
-        Ok(file)
+        let mut batch = encode_to_vec(&header, config::standard())?;
+        let body_pos = self.size_b + batch.len();
+        batch.extend(body);
+
+        self.buffered(|wf| {
+            wf.buffer.extend(&batch);
+            wf.size_b += batch.len();
+            Ok(records_offsets
+                .into_iter()
+                .map(|offset| body_pos + offset)
+                .collect())
+        })
+    }
+
+    /// Buffers records with `append`, flushing them if the buffer is full. If that fails the records are taken
+    /// out of the buffer, so that nothing of a failed append reaches the file later.
+    fn buffered<T>(&mut self, append: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let (buffered_b, size_b) = (self.buffer.len(), self.size_b);
+        let result = append(self).and_then(|appended| {
+            self.flush_if_full()?;
+            Ok(appended)
+        });
+        if result.is_err() {
+            // A failed flush leaves the buffer as it was
+            self.buffer.truncate(buffered_b);
+            self.size_b = size_b;
+        }
+        result
+    }
+
+    /// Writes the buffered records to the file.
+    ///
+    /// If it fails the buffer is kept. The flush can be retried unless part of the buffer was written, then the
+    /// file is torn: it must be sealed and the buffer moved to a new working file, see [`WorkingFile::take_torn`].
+    pub fn flush(&mut self) -> Result<()> {
+        if self.is_torn {
+            bail!("The working file is torn by a failed write, its records must move to a new one");
+        }
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut file = &*self.file;
+        if self.is_preallocated {
+            // Appending would write after the padding, records go at the logical end instead
+            file.seek(SeekFrom::Start(self.flushed_b.try_into()?))?;
+        }
+        let mut written = 0;
+        while written < self.buffer.len() {
+            match file.write(&self.buffer[written..]) {
+                Ok(0) => {
+                    self.is_torn = written > 0;
+                    return Err(std::io::Error::from(ErrorKind::WriteZero).into());
+                }
+                Ok(count) => written += count,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    // Writing the buffer again would repeat its first bytes in the middle of the file
+                    self.is_torn = written > 0;
+                    return Err(e.into());
+                }
+            }
+        }
+        self.flushed_b += self.buffer.len();
+        self.buffer.clear();
+        Ok(())
+    }
+
+    pub fn is_torn(&self) -> bool {
+        self.is_torn
+    }
+
+    /// Seals a torn working file where its flushed records end, and returns its buffer to append to a new one.
+    /// Cutting the file is best effort: records written before the failure are loaded again from the new file.
+    pub fn take_torn(mut self) -> Vec<u8> {
+        if let Ok(flushed_b) = self.flushed_b.try_into() {
+            let _ = self.file.set_len(flushed_b);
+        }
+        self.is_preallocated = false;
+        std::mem::take(&mut self.buffer)
+    }
+
+    /// Buffers bytes holding whole records, moved from a torn working file. Returns where they start.
+    pub fn append_bytes(&mut self, bytes: &[u8]) -> usize {
+        let bytes_pos = self.size_b;
+        self.buffer.extend(bytes);
+        self.size_b += bytes.len();
+        bytes_pos
+    }
+
+    /// Flushes the buffered records and cuts the padding of a preallocated file, for when it becomes immutable.
+    pub fn seal(&mut self) -> Result<()> {
+        self.flush()?;
+        if self.is_preallocated {
+            self.file.set_len(self.flushed_b.try_into()?)?;
+            self.is_preallocated = false;
+        }
+        Ok(())
+    }
+
+    fn flush_if_full(&mut self) -> Result<()> {
+        if self.buffer.len() >= BUFFER_CAPACITY {
+            self.flush()?;
+        }
+        Ok(())
     }
 
-    pub fn append(&mut self, entry: &Entry) -> Result<usize> {
-        let bytes_written = encode_into_std_write(entry, &mut self.file, config::standard())?;
-        self.size_b += bytes_written;
-        Ok(bytes_written)
+    /// Flushes the buffered records and waits for the file to reach the disk.
+    pub fn sync(&mut self) -> Result<()> {
+        self.flush()?;
+        self.file.sync_all()?;
+        Ok(())
     }
 
     pub fn bytes_count(&self) -> usize {
         self.size_b
     }
 
+    /// Bytes written to the file so far, excluding the buffered ones.
+    pub fn flushed_bytes_count(&self) -> usize {
+        self.flushed_b
+    }
+
     pub fn get_working_file_id(directory: &Path) -> Result<usize> {
-        Ok(
-            fs::read_dir(directory)? // TODO: better handle error. create directory if missing?
-                .filter(|entry| {
-                    let ent = entry.as_ref().expect("Directory Entry can not be opened");
-                    ent.file_name()
-                        .to_str()
-                        .unwrap_or_default()
-                        .contains("working_file")
-                })
-                .count(),
-        )
+        // Ids are not contiguous after a merge, so the next id is the biggest existing one + 1
+        Ok(Self::list_data_files(directory)?
+            .last()
+            .and_then(|path| Self::parse_file_id(path.file_name()?.to_str()?))
+            .map_or(0, |id| id + 1))
+    }
+
+    /// Returns the paths of all data files in `directory` sorted by id, i.e. from oldest to newest.
+    pub fn list_data_files(directory: &Path) -> Result<Vec<PathBuf>> {
+        // TODO: better handle error. create directory if missing?
+        let mut data_files_paths: Vec<PathBuf> = directory
+            .read_dir()?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                if entry.file_name().to_str()?.contains("working_file") {
+                    Some(entry.path())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        data_files_paths.sort_by_key(|path| {
+            path.file_name()
+                .and_then(|s| s.to_str())
+                .and_then(Self::parse_file_id)
+                .unwrap_or(0)
+        });
+        Ok(data_files_paths)
+    }
+
+    /// Checks the header of a data file read from its start, records follow it. Returns `false` if the file ends
+    /// before the header does, or it's still zeros, e.g. it was created right before a crash.
+    pub fn read_header(reader: &mut impl Read, file_name: &str) -> Result<bool> {
+        let mut header = [0; HEADER_LENGTH];
+        let mut read = 0;
+        while read < HEADER_LENGTH {
+            match reader.read(&mut header[read..]) {
+                Ok(0) => break,
+                Ok(count) => read += count,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if header.iter().all(|byte| *byte == 0) {
+            // Preallocated before the header reached the disk
+            return Ok(false);
+        }
+        let magic_read = read.min(MAGIC.len());
+        if header[..magic_read] != MAGIC[..magic_read] {
+            bail!(
+                "{file_name} isn't a data file of a known format, if it was written by an older version \
+                 `bitcask upgrade` converts it"
+            );
+        }
+        if read < HEADER_LENGTH {
+            return Ok(false);
+        }
+        let version = header[MAGIC.len()];
+        if version != FORMAT_VERSION {
+            bail!("{file_name} has format version {version}, only version {FORMAT_VERSION} is supported");
+        }
+        Ok(true)
+    }
+
+    pub fn file_name(id: usize) -> String {
+        format!("working_file_{id}")
+    }
+
+    pub fn parse_file_id(file_name: &str) -> Option<usize> {
+        file_name
+            .strip_prefix("working_file_")
+            .and_then(|id| id.parse::<usize>().ok())
     }
 
     pub fn get_file_name(&self) -> String {
-        return self
+        self
             .path
             .file_name()
             .unwrap()
             .to_string_lossy()
-            .into_owned();
+            .into_owned()
     }
 
-    pub fn get_mut_file_ref(&mut self) -> &mut File {
-        return &mut self.file;
+    /// The underlying file, records still in the buffer are not in it yet.
+    pub fn get_file_ref(&self) -> &File {
+        &self.file
+    }
+
+    pub fn shared_file(&self) -> Arc<File> {
+        Arc::clone(&self.file)
+    }
+}
+
+#[cfg(test)]
+impl WorkingFile {
+    /// Writes go to `file` from now on, e.g. a read-only handle to make them fail.
+    pub fn replace_file(&mut self, file: File) {
+        self.file = Arc::new(file);
+    }
+
+    /// Writes the first `count` buffered bytes then gives up, as a write stopped by a full disk would.
+    pub fn tear(&mut self, count: usize) {
+        (&*self.file).write_all(&self.buffer[..count]).unwrap();
+        self.is_torn = true;
+    }
+}
+
+impl Drop for WorkingFile {
+    fn drop(&mut self) {
+        // Best effort, callers that care about errors seal before dropping
+        let _ = self.seal();
+    }
+}
+
+fn preallocate(file: &File, size: usize) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+
+        // SAFETY: the file descriptor is owned by `file` and stays open during the call
+        let result = unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, size.try_into()?) };
+        if result == 0 {
+            return Ok(());
+        }
+        // Not every file system supports it, a sparse file of the same size behaves the same for us
     }
+    file.set_len(size.try_into()?)?;
+    Ok(())
 }
@@ -0,0 +1,18 @@
+//! Messages, client and service trait of the gRPC API, generated from `proto/bitcask.proto`.
+//!
+//! The service is served by [`crate::server::serve_grpc`], [`BitcaskClient`] connects to it:
+//!
+//! ```no_run
+//! use bitcask::grpc::{BitcaskClient, GetRequest, PutRequest};
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut client = BitcaskClient::connect("http://127.0.0.1:50051").await?;
+//! client.put(PutRequest { key: b"user:1".to_vec(), value: b"Ada".to_vec(), ttl_millis: None }).await?;
+//! let value = client.get(GetRequest { key: b"user:1".to_vec() }).await?.into_inner().value;
+//! # Ok(())
+//! # }
+//! ```
+
+tonic::include_proto!("bitcask.v1");
+
+pub use bitcask_client::BitcaskClient;
@@ -1,11 +1,21 @@
-use std::{path::Path, vec::Vec};
+use std::{
+    ops::{Bound, RangeBounds},
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+    vec::Vec,
+};
 use anyhow::Result;
-use crate::Options;
+use crate::{Changes, Entries, Iter, Keys, LogPosition, Options, Stats, Transaction, VersionedValue, WriteBatch};
 
-use super::engine::Bitcask;
+use super::{commit::GroupCommit, engine::Bitcask};
 
+/// Handle to an open Bitcask datastore.
+///
+/// All methods take `&self`, the handler can be shared between threads (e.g. in an `Arc`) without extra locking.
 pub struct BitcaskHandler {
-    pub(crate) bitcask_engine: Bitcask,
+    pub(crate) bitcask_engine: Mutex<Bitcask>,
+    pub(crate) group_commit: GroupCommit,
 }
 
 impl BitcaskHandler {
@@ -25,7 +35,20 @@ impl BitcaskHandler {
     ///
     /// * `"read_write"` — Grants this process write access to the datastore.  
     ///   **Note:** Only one process can have write access at a time.
-    /// * `"sync_on_put"` — Forces a disk sync after every `put` operation for stronger durability.
+    /// * `"sync_on_put"` — Every write returns only once synced to disk, for stronger durability. Writes of
+    ///   concurrent threads are grouped, they share one write and one sync of the working file.
+    /// * `"mac_secret"` — Signs every entry with HMAC-SHA256 using this secret, entries with a missing or
+    ///   wrong MAC fail with [`crate::BitcaskError::Tampered`] on `get` and while opening the datastore.
+    ///   **Note:** Only changes to the records themselves are detected. Deleting records, or rolling data
+    ///   files back to an older copy, goes unnoticed.
+    /// * `"expiry_secs"` — Hides entries written more than this many seconds ago, as if they had a TTL.
+    ///   They stay on disk until the next `merge`.
+    /// * `"clock"` — Source of entry timestamps and of the time used for expiry, see [`crate::Clock`].
+    ///   Defaults to a [`crate::HybridLogicalClock`] whose timestamps strictly increase.
+    /// * `"index"` — [`crate::IndexKind::Ordered`] keeps keys sorted, enabling [`BitcaskHandler::range`] and
+    ///   [`BitcaskHandler::prefix`] at the cost of more memory and slower lookups than the default hash index.
+    /// * `"preallocate"` — Reserves `max_data_size` bytes with `fallocate` for every new working file, which is
+    ///   cut to its data size when it's full or closed. Opening a datastore cuts the files left padded by a crash.
     ///
     /// # Returns
     ///
@@ -34,17 +57,16 @@ impl BitcaskHandler {
     ///
     /// # Example
     ///
-    /// ```
-    /// use bitcask::BitcaskHandler;
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
     ///
     /// // Open in read-only mode
-    /// let handler = BitcaskHandler::open(String::from("/tmp/bitcask"), None).unwrap();
+    /// let handler = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
     ///
     /// // Open with write access and sync on every write
-    /// let handler = BitcaskHandler::open(
-    ///     &Path::new("/tmp/bitcask"),
-    ///     Some(vec![String::from("read_write"), String::from("sync_on_put")])
-    /// ).unwrap();
+    /// let options = Options { read_write: true, sync_on_put: true, ..Default::default() };
+    /// let handler = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
     /// ```
     pub fn open(directory: &Path, options: Option<Options>) -> Result<Self> {
         Bitcask::open(directory, options)
@@ -61,9 +83,10 @@ impl BitcaskHandler {
     /// # Returns
     ///
     /// Returns the value as a `Vec<u8>` if the key exists, or an error if:
-    /// - The key does not exist.
+    /// - The key does not exist ([`crate::BitcaskError::KeyNotFound`]).
     /// - The underlying file cannot be accessed.
-    /// - Data corruption is detected.
+    /// - Data corruption is detected ([`crate::BitcaskError::Corrupted`]).
+    /// - The entry was modified without the MAC secret ([`crate::BitcaskError::Tampered`]).
     ///
     /// # Errors
     ///
@@ -72,15 +95,70 @@ impl BitcaskHandler {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
+    /// use std::path::Path;
     /// use bitcask::BitcaskHandler;
     ///
-    /// let db = BitcaskHandler::open("/tmp/bitcask", None).unwrap();
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
     /// let value = db.get(b"user:1").unwrap();
     /// println!("Value: {:?}", value);
     /// ```
-    pub fn get(&mut self, key: &[u8]) -> Result<Vec<u8>>{
-        self.bitcask_engine.get(key)
+    pub fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
+        self.engine().get(key)
+    }
+
+    /// Retrieves a value by key along with its version, write time and flags.
+    ///
+    /// The version is a sequence number assigned to every write, unique and increasing across the whole
+    /// datastore, unlike the timestamp which follows the wall clock. It can be used as an ETag with
+    /// conditional writes such as [`BitcaskHandler::delete_if_version`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`BitcaskHandler::get`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::BitcaskHandler;
+    ///
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
+    /// let meta = db.get_with_meta(b"user:1").unwrap();
+    /// println!("{:?} v{} written at {}", meta.value, meta.version, meta.timestamp);
+    /// ```
+    pub fn get_with_meta(&self, key: &[u8]) -> Result<VersionedValue> {
+        self.engine().get_with_meta(key)
+    }
+
+    /// Retrieves the values of several keys at once.
+    ///
+    /// Lookups are sorted by data file and offset before reading, so entries stored next to each other are
+    /// read with a single pass over each file instead of one seek per key in request order.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys to look up, duplicates are allowed.
+    ///
+    /// # Returns
+    ///
+    /// One item per key in the same order as `keys`: the value, or `None` if the key doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Fails as a whole if any of the entries can't be read or fails verification, see [`BitcaskHandler::get`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::BitcaskHandler;
+    ///
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
+    /// let values = db.get_many(&[b"user:1", b"user:2", b"user:3"]).unwrap();
+    /// ```
+    pub fn get_many<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.engine().get_many(keys)
     }
 
     /// Stores a key-value pair in the Bitcask datastore.
@@ -102,14 +180,148 @@ impl BitcaskHandler {
     ///
     /// # Example
     ///
-    /// ```
-    /// use bitcask::BitcaskHandler;
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
     ///
-    /// let db = BitcaskHandler::open("/tmp/bitcask", Some(vec!["read_write"])).unwrap();
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
     /// db.put(b"user:1", b"Saif").unwrap();
     /// ```
-    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
-        self.bitcask_engine.put(key, value)
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.write(|engine| engine.put(key, value))
+    }
+
+    /// Stores several key-value pairs, encoded together and appended with a single write.
+    ///
+    /// Unlike [`BitcaskHandler::write_batch`] the pairs are not atomic: a crash in the middle of the write
+    /// may keep the first ones only. Use it to cut syscalls on bulk loads.
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - The key-value pairs to insert or update, in order: the last one wins for duplicate keys.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    /// db.put_many(&[("user:1", "Saif"), ("user:2", "Ahmed")]).unwrap();
+    /// ```
+    pub fn put_many<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, pairs: &[(K, V)]) -> Result<()> {
+        self.write(|engine| engine.put_many(pairs))
+    }
+
+    /// Stores a key-value pair that expires after the given time-to-live.
+    ///
+    /// Once expired, the key behaves as if it was deleted: `get` fails, `list_keys` skips it,
+    /// it's not loaded when the datastore is reopened and `merge` drops it from disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice representing the key to insert or update.
+    /// * `value` - A byte slice representing the value associated with the key.
+    /// * `ttl` - How long the key-value pair stays visible, starting now.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::{path::Path, time::Duration};
+    /// use bitcask::{BitcaskHandler, Options};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    /// db.put_with_ttl(b"session:42", b"token", Duration::from_secs(30 * 60)).unwrap();
+    /// ```
+    pub fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        self.write(|engine| engine.put_with_ttl(key, value, ttl))
+    }
+
+    /// Stores a key-value pair along with opaque flags, and optionally a time-to-live.
+    ///
+    /// Flags are not interpreted by the datastore, they're returned by [`BitcaskHandler::get_with_meta`]. Other
+    /// writes store zero flags. The memcached server keeps its client flags there.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice representing the key to insert or update.
+    /// * `value` - A byte slice representing the value associated with the key.
+    /// * `flags` - Flags returned along with the value.
+    /// * `ttl` - How long the key-value pair stays visible, `None` to keep it until it's deleted.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    /// const COMPRESSED: u32 = 1;
+    /// db.put_with_flags(b"page:/", b"...", COMPRESSED, None).unwrap();
+    /// assert_eq!(db.get_with_meta(b"page:/").unwrap().flags, COMPRESSED);
+    /// ```
+    pub fn put_with_flags(&self, key: &[u8], value: &[u8], flags: u32, ttl: Option<Duration>) -> Result<()> {
+        self.write(|engine| engine.put_with_flags(key, value, flags, ttl))
+    }
+
+    /// Applies all puts and deletes of a [`WriteBatch`] atomically.
+    ///
+    /// The batch is written to disk as a single unit with its own checksum and becomes visible to
+    /// readers only once it's fully written. If the process crashes in the middle, the incomplete
+    /// batch is discarded when the datastore is reopened.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The operations to apply, in order.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options, WriteBatch};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    /// let mut batch = WriteBatch::new();
+    /// batch.put(b"account:1", b"90").put(b"account:2", b"110").delete(b"transfer:7");
+    /// db.write_batch(batch).unwrap();
+    /// ```
+    pub fn write_batch(&self, batch: WriteBatch) -> Result<()> {
+        self.write(|engine| engine.write_batch(batch))
+    }
+
+    /// Runs `f` as an optimistic transaction.
+    ///
+    /// Reads made through the [`Transaction`] remember the version of the entry they saw, writes are buffered
+    /// and applied atomically as a [`WriteBatch`] once `f` returns `Ok`. The commit fails with
+    /// [`crate::BitcaskError::Conflict`] if any key read by `f` was written or deleted in the meantime, in which
+    /// case nothing is written and the caller may retry. If `f` returns an error, nothing is written either.
+    ///
+    /// No lock is held while `f` runs, so concurrent threads can safely do read-modify-write cycles.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    /// db.transaction(|tx| {
+    ///     let hits = tx.get(b"hits")?.map_or(0, |v| String::from_utf8_lossy(&v).parse().unwrap_or(0));
+    ///     tx.put(b"hits", (hits + 1).to_string().as_bytes());
+    ///     Ok(())
+    /// }).unwrap();
+    /// ```
+    pub fn transaction<T>(&self, f: impl FnOnce(&mut Transaction) -> Result<T>) -> Result<T> {
+        let mut transaction = Transaction::new(self);
+        let result = f(&mut transaction)?;
+        transaction.commit()?;
+        Ok(result)
     }
 
     /// Deletes a key and its associated value from the Bitcask datastore.
@@ -122,8 +334,8 @@ impl BitcaskHandler {
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` if the key was successfully deleted or did not exist.
-    /// Returns an error if the operation fails due to I/O or file corruption.
+    /// Returns `Ok(())` if the key was successfully deleted, [`crate::BitcaskError::KeyNotFound`] if it did
+    /// not exist. Returns an error if the operation fails due to I/O or file corruption.
     ///
     /// # Errors
     ///
@@ -132,16 +344,139 @@ impl BitcaskHandler {
     ///
     /// # Example
     ///
-    /// ```
-    /// use bitcask::BitcaskHandler;
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
     ///
-    /// let db = BitcaskHandler::open("/tmp/bitcask", Some(vec!["read_write"])).unwrap();
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
     /// db.put(b"user:1", b"Saif").unwrap();
     /// db.delete(b"user:1").unwrap();
     /// assert!(db.get(b"user:1").is_err());
     /// ```
-    pub fn delete(&mut self, key: &[u8]) -> Result<()> {
-        self.bitcask_engine.delete(key)
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        self.write(|engine| engine.delete(key))
+    }
+
+    /// Deletes every key within `range` in one call.
+    ///
+    /// A single range tombstone is appended however many keys it covers, it's honoured when the datastore is
+    /// opened again and the deleted entries are dropped by the next `merge`. Keys are found through the key
+    /// directory, with the default hash index that means looking at every key.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - Bounds on the key bytes, e.g. `"tenant:7:".."tenant:8:"`.
+    ///
+    /// # Returns
+    ///
+    /// The number of live keys deleted. Nothing is written when there are none.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    /// let deleted = db.delete_range("log:2023".."log:2024").unwrap();
+    /// ```
+    pub fn delete_range<K: AsRef<[u8]>>(&self, range: impl RangeBounds<K>) -> Result<usize> {
+        let to_owned = |bound: Bound<&K>| bound.map(|key| key.as_ref().to_vec());
+        self.write(|engine| {
+            engine.delete_range(to_owned(range.start_bound()), to_owned(range.end_bound()))
+        })
+    }
+
+    /// Deletes every key starting with `prefix` in one call, see [`BitcaskHandler::delete_range`].
+    ///
+    /// # Returns
+    ///
+    /// The number of live keys deleted.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    /// // Offboard a tenant
+    /// let deleted = db.delete_prefix(b"tenant:42:").unwrap();
+    /// ```
+    pub fn delete_prefix(&self, prefix: &[u8]) -> Result<usize> {
+        self.write(|engine| engine.delete_prefix(prefix))
+    }
+
+    /// Atomically replaces the value of `key` if its current value is `expected`.
+    ///
+    /// `None` as `expected` means the key must not exist, `None` as `new` deletes the key.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` if the current value matched and `new` was written.
+    /// * `Ok(false)` if the current value didn't match, nothing is written.
+    /// * `Err` if reading the current value or writing the new one fails.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    /// // Take over leadership only if the previous leader is still the one we know about
+    /// let is_leader = db.compare_and_swap(b"leader", Some(b"node-1"), Some(b"node-2")).unwrap();
+    /// ```
+    pub fn compare_and_swap(
+        &self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool> {
+        self.write(|engine| engine.compare_and_swap(key, expected, new))
+    }
+
+    /// Stores a key-value pair only if the key doesn't exist yet.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the pair was written, `Ok(false)` if the key already exists.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    /// if db.put_if_absent(b"request:8f2c", b"done").unwrap() {
+    ///     // First time we see this request
+    /// }
+    /// ```
+    pub fn put_if_absent(&self, key: &[u8], value: &[u8]) -> Result<bool> {
+        self.write(|engine| engine.put_if_absent(key, value))
+    }
+
+    /// Deletes a key only if its current version is `version`, as returned by [`BitcaskHandler::version`].
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the key was deleted, `Ok(false)` if it's missing or was written since.
+    pub fn delete_if_version(&self, key: &[u8], version: u64) -> Result<bool> {
+        self.write(|engine| engine.delete_if_version(key, version))
+    }
+
+    /// Returns the current version of a key, or `None` if it doesn't exist.
+    ///
+    /// The version is the sequence number of the key's last write, see [`BitcaskHandler::get_with_meta`].
+    /// It's answered from memory, no disk access is needed.
+    pub fn version(&self, key: &[u8]) -> Option<u64> {
+        self.engine().version(key)
     }
 
     /// Lists all keys currently stored in the Bitcask datastore.
@@ -150,6 +485,8 @@ impl BitcaskHandler {
     /// It does **not** read from disk and therefore reflects only the
     /// keys known to the active in-memory index.
     ///
+    /// Every key is cloned into the returned vector, prefer [`BitcaskHandler::keys`] for large datastores.
+    ///
     /// # Returns
     ///
     /// Returns a vector of keys as byte arrays (`Vec<Vec<u8>>`).
@@ -162,10 +499,12 @@ impl BitcaskHandler {
     ///
     /// # Example
     ///
-    /// ```
-    /// use bitcask::BitcaskHandler;
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
     ///
-    /// let db = BitcaskHandler::open("/tmp/bitcask", Some(vec!["read_write"])).unwrap();
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
     /// db.put(b"user:1", b"Saif").unwrap();
     /// db.put(b"user:2", b"Alice").unwrap();
     ///
@@ -175,17 +514,320 @@ impl BitcaskHandler {
     /// }
     /// ```
     pub fn list_keys(&self) -> Result<Vec<Vec<u8>>> {
-        self.bitcask_engine.list_keys()
+        self.engine().list_keys()
+    }
+
+    /// Returns a lazy iterator over all live keys.
+    ///
+    /// Keys come in arbitrary order, or sorted when opened with [`crate::IndexKind::Ordered`].
+    ///
+    /// The iterator works on a snapshot of the key directory taken in O(1) when it's created: writes made
+    /// while iterating are not visited, and keys are only cloned as they are yielded. No disk I/O is needed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::BitcaskHandler;
+    ///
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
+    /// let user_keys = db.keys().filter(|key| key.starts_with(b"user:")).count();
+    /// ```
+    pub fn keys(&self) -> Keys {
+        self.engine().keys()
+    }
+
+    /// Returns a lazy iterator over all live key-value pairs.
+    ///
+    /// Like [`BitcaskHandler::keys`], it works on a snapshot of the key directory taken when it's created.
+    /// Values are read from disk as pairs are yielded, in key directory order. Use
+    /// [`BitcaskHandler::entries`] to visit them in on-disk order instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if pending writes can't be flushed to the working file. Each item is an error if its
+    /// entry can't be read or fails verification.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::BitcaskHandler;
+    ///
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
+    /// for pair in db.iter().unwrap().take(10) {
+    ///     let (key, value) = pair.unwrap();
+    ///     println!("{:?} => {:?}", key, value);
+    /// }
+    /// ```
+    pub fn iter(&self) -> Result<Iter> {
+        self.engine().iter()
+    }
+
+    /// Returns a lazy iterator over the live key-value pairs whose key is within `range`, in key order.
+    ///
+    /// Works on a snapshot of the key directory like [`BitcaskHandler::iter`]. Reverse it with `.rev()`.
+    /// To paginate, remember the last key of a page and start the next one just after it with
+    /// `(Bound::Excluded(last_key), Bound::Unbounded)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - Bounds on the key bytes, e.g. `b"a".as_slice()..b"m".as_slice()` or `"user:".."user;"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datastore wasn't opened with [`crate::IndexKind::Ordered`]. Each item is an
+    /// error if its entry can't be read or fails verification.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::{ops::Bound, path::Path};
+    /// use bitcask::{BitcaskHandler, IndexKind, Options};
+    ///
+    /// let options = Options { index: IndexKind::Ordered, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    ///
+    /// // Newest orders first
+    /// let latest = db.range("order:2024".."order:2025").unwrap().rev().take(10);
+    ///
+    /// // Pages of 100 pairs
+    /// let mut cursor = Bound::Unbounded;
+    /// loop {
+    ///     let page: Vec<_> = db.range((cursor.clone(), Bound::Unbounded)).unwrap()
+    ///         .take(100)
+    ///         .collect::<Result<_, _>>()
+    ///         .unwrap();
+    ///     let Some((last_key, _)) = page.last() else { break };
+    ///     cursor = Bound::Excluded(last_key.clone());
+    /// }
+    /// ```
+    pub fn range<K: AsRef<[u8]>>(&self, range: impl RangeBounds<K>) -> Result<Iter> {
+        let to_owned = |bound: Bound<&K>| bound.map(|key| key.as_ref().to_vec());
+        self.engine()
+            .range(to_owned(range.start_bound()), to_owned(range.end_bound()))
     }
 
-    // TODO: Fold/Reduce method to be added in later phase
+    /// Returns a lazy iterator over the live key-value pairs whose key starts with `prefix`, in key order.
+    ///
+    /// Same as [`BitcaskHandler::range`] over the keys starting with `prefix`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datastore wasn't opened with [`crate::IndexKind::Ordered`]. Each item is an
+    /// error if its entry can't be read or fails verification.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, IndexKind, Options};
+    ///
+    /// let options = Options { index: IndexKind::Ordered, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    /// for pair in db.prefix(b"user:").unwrap() {
+    ///     let (key, value) = pair.unwrap();
+    ///     println!("{:?} => {:?}", key, value);
+    /// }
+    /// ```
+    pub fn prefix(&self, prefix: &[u8]) -> Result<Iter> {
+        self.engine().prefix(prefix)
+    }
+
+    /// Returns a lazy iterator over the live key-value pairs whose key starts with `prefix`, with any index.
+    ///
+    /// With an ordered index it is the same as [`BitcaskHandler::prefix`]. With a hash index pairs come in
+    /// arbitrary order and every key of the snapshot is looked at, but only the values of matching keys are
+    /// read from disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if pending writes can't be flushed to the working file. Each item is an error if its
+    /// entry can't be read or fails verification.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::BitcaskHandler;
+    ///
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
+    /// for pair in db.scan_prefix(b"user:").unwrap().take(10) {
+    ///     let (key, value) = pair.unwrap();
+    ///     println!("{:?} => {:?}", key, value);
+    /// }
+    /// ```
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<Iter> {
+        self.engine().scan_prefix(prefix)
+    }
+
+    /// Returns figures about the datastore: number of keys, data files and their size.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::BitcaskHandler;
+    ///
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
+    /// let stats = db.stats().unwrap();
+    /// println!("{} keys in {} files ({} bytes)", stats.keys, stats.data_files, stats.data_size);
+    /// ```
+    pub fn stats(&self) -> Result<Stats> {
+        self.engine().stats()
+    }
+
+    /// Returns the number of keys in the key directory, without disk I/O.
+    ///
+    /// Keys that expired but haven't been read or merged since are still counted.
+    pub fn len(&self) -> usize {
+        self.engine().len()
+    }
+
+    /// Returns `true` if the key directory holds no key, without disk I/O.
+    pub fn is_empty(&self) -> bool {
+        self.engine().is_empty()
+    }
+
+    /// Returns `true` if the key exists and isn't expired, without disk I/O.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.engine().contains_key(key)
+    }
+
+    /// Returns an iterator over all live key-value pairs.
+    ///
+    /// Pairs are visited in on-disk order (data file by data file, by increasing offset) rather than in
+    /// key dir order, so values are read with buffered, mostly sequential I/O.
+    /// The set of keys is captured when the iterator is created, later writes are not visited.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if pending writes can't be flushed to the working file. Each item is an error if its
+    /// entry can't be read or fails verification. A `merge` running while iterating removes the old data files
+    /// and makes the remaining items fail.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::BitcaskHandler;
+    ///
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
+    /// for pair in db.entries().unwrap() {
+    ///     let (key, value) = pair.unwrap();
+    ///     println!("{:?} => {:?}", key, value);
+    /// }
+    /// ```
+    pub fn entries(&self) -> Result<Entries> {
+        self.engine().entries()
+    }
+
+    /// Folds over all live key-value pairs, visited in the same order as [`BitcaskHandler::entries`].
+    ///
+    /// # Arguments
+    ///
+    /// * `init` - The initial accumulator.
+    /// * `f` - Called with the accumulator, a key and its value, returns the next accumulator.
+    ///
+    /// # Returns
+    ///
+    /// The final accumulator, or the first error met while reading entries.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::BitcaskHandler;
+    ///
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
+    /// let total_bytes = db.fold(0, |acc, key, value| acc + key.len() + value.len()).unwrap();
+    /// ```
+    pub fn fold<T>(&self, init: T, mut f: impl FnMut(T, &[u8], &[u8]) -> T) -> Result<T> {
+        let mut acc = init;
+        for pair in self.entries()? {
+            let (key, value) = pair?;
+            acc = f(acc, &key, &value);
+        }
+        Ok(acc)
+    }
+
+    /// Returns an iterator over the committed writes from `from` on, in commit order, to feed search indexes,
+    /// caches or other stores from the datastore's own log.
+    ///
+    /// Every `put` and `delete` is a [`crate::Change`], including those of batches and transactions, and
+    /// [`BitcaskHandler::delete_range`] is one change with a `range_end`. Changes are read from the data files, so
+    /// a reader can store [`crate::Change::position`] along with their effects and resume from it after a restart.
+    /// Iterating waits for the next commit once the reader is up to date, see [`Changes::try_next`] to poll.
+    ///
+    /// A merge removes the data files it rewrites. A reader that didn't get to their changes before fails with
+    /// [`crate::BitcaskError::Compacted`], otherwise it goes on with the merged files. The values they hold
+    /// may be returned again by a reader resumed from a position, unless it had read a change since.
+    /// On a [`crate::replication::Follower`] the changes are those of its copy of the data files, writes the
+    /// leader merged before shipping them only show up as the merged values.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Position the reader resumes after, [`LogPosition::default`] to read the whole log.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, LogPosition, Options};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    ///
+    /// // Loaded from wherever the index stored it
+    /// let position = LogPosition { file_id: 3, offset: 4096 };
+    /// for change in db.changes(position) {
+    ///     let change = change.unwrap();
+    ///     if change.is_deleted {
+    ///         println!("unindex {:?}", change.key);
+    ///     } else {
+    ///         println!("index {:?} => {:?}", change.key, change.value);
+    ///     }
+    ///     // Store change.position with the index
+    /// }
+    /// ```
+    pub fn changes(&self, from: LogPosition) -> Changes<'_> {
+        Changes::new(self, from, None)
+    }
+
+    /// Returns an iterator over the writes committed from now on, like [`BitcaskHandler::changes`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::{path::Path, thread};
+    /// use bitcask::{BitcaskHandler, Options};
+    ///
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+    ///
+    /// thread::scope(|s| {
+    ///     let changes = db.subscribe();
+    ///     s.spawn(move || {
+    ///         for change in changes {
+    ///             let change = change.unwrap();
+    ///             println!("{:?} changed at {}", change.key, change.timestamp);
+    ///         }
+    ///     });
+    ///     db.put(b"user:1", b"Ada").unwrap();
+    /// });
+    /// ```
+    pub fn subscribe(&self) -> Changes<'_> {
+        let (next_version, log_end) = self.group_commit.committed();
+        Changes::new(self, log_end, Some(next_version))
+    }
 
-    
     /// Merge multiple data files within the Bitcask datastore into a more compact form.
     ///
-    /// This operation reclaims disk space by combining data files, removing deleted or outdated entries,
+    /// This operation reclaims disk space by combining data files, removing deleted, expired or outdated entries,
     /// and producing **hint files** to speed up datastore startup.
     ///
+    /// The datastore must be opened with `read_write`. Hint files are not produced yet.
+    ///
     /// # Returns
     ///
     /// * `Ok(())` if the merge completes successfully.
@@ -198,14 +840,20 @@ impl BitcaskHandler {
     ///
     /// # Example
     ///
-    /// ```
-    /// use bitcask::BitcaskHandler;
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::{BitcaskHandler, Options};
     ///
-    /// let handler = BitcaskHandler::open("data").unwrap();
+    /// let options = Options { read_write: true, ..Default::default() };
+    /// let handler = BitcaskHandler::open(Path::new("data"), Some(options)).unwrap();
     /// handler.merge().unwrap();
     /// ```
     pub fn merge(&self) -> Result<()> {
-        self.bitcask_engine.merge()
+        let mut engine = self.engine();
+        engine.merge()?;
+        // Readers of the changes go on with the merged files
+        self.group_commit.advance(engine.next_sequence(), engine.log_end()?);
+        Ok(())
     }
 
     /// Force any pending writes in the Bitcask datastore to be synced to disk.
@@ -220,14 +868,15 @@ impl BitcaskHandler {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
+    /// use std::path::Path;
     /// use bitcask::BitcaskHandler;
     ///
-    /// let handler = BitcaskHandler::open("data", None).unwrap();
+    /// let handler = BitcaskHandler::open(Path::new("data"), None).unwrap();
     /// handler.sync().unwrap();
     /// ```
     pub fn sync(&self) -> Result<()> {
-        self.bitcask_engine.sync()
+        self.engine().sync()
     }
 
     /// Close the Bitcask datastore, flushing any pending writes to disk.
@@ -242,13 +891,67 @@ impl BitcaskHandler {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
+    /// use std::path::Path;
     /// use bitcask::BitcaskHandler;
     ///
-    /// let handler = BitcaskHandler::open("data", None).unwrap();
+    /// let handler = BitcaskHandler::open(Path::new("data"), None).unwrap();
     /// handler.close().unwrap();
     /// ```
     pub fn close(&self) -> Result<()> {
-        self.bitcask_engine.close()
+        self.engine().close()
+    }
+
+    /// Opens a read-only datastore for writing, as if it had been opened with `read_write`.
+    ///
+    /// It's mostly meant for a [`crate::replication::Follower`] taking over from its leader, replication into
+    /// the datastore stops once it's promoted. New writes go to a new data file. Does nothing if the datastore
+    /// is already open for writing.
+    ///
+    /// # Errors
+    ///
+    /// Fails if another process has the directory open for writing.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::BitcaskHandler;
+    ///
+    /// let handler = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
+    /// handler.promote().unwrap();
+    /// handler.put(b"user:1", b"Ada").unwrap();
+    /// ```
+    pub fn promote(&self) -> Result<()> {
+        self.engine().promote()
+    }
+
+    pub(crate) fn engine(&self) -> MutexGuard<'_, Bitcask> {
+        self.bitcask_engine
+            .lock()
+            .expect("Bitcask engine lock poisoned by a panicking thread")
+    }
+
+    /// Runs a write on the engine then waits for it to be written to the working file, and synced with
+    /// `sync_on_put`, along with the writes of other threads, see [`GroupCommit`].
+    pub(crate) fn write<T>(&self, f: impl FnOnce(&mut Bitcask) -> Result<T>) -> Result<T> {
+        let (result, sequence) = {
+            let mut engine = self.engine();
+            let result = f(&mut engine);
+            (result, engine.next_sequence())
+        };
+        // Even a failed write may have buffered some entries
+        self.group_commit
+            .wait_durable(sequence, || self.flush_for_commit())?;
+        result
+    }
+
+    fn flush_for_commit(&self) -> Result<(u64, LogPosition)> {
+        let (sequence, log_end, file_to_sync) = self.engine().flush_for_commit()?;
+        // Writers keep appending to the buffer while the leader waits for the disk
+        if let Some(file) = file_to_sync {
+            file.sync_data()?;
+        }
+        Ok((sequence, log_end))
     }
 }
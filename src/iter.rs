@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufReader, Seek},
+    path::PathBuf,
+    vec,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    engine::{DirEntry, Entry},
+    keydir,
+};
+
+/// Reads values from the data files for iterators, without going through the engine and its lock.
+struct ValueReader {
+    directory: PathBuf,
+    mac_secret: Option<Vec<u8>>,
+    // Entries of the same data file are read through the same buffer
+    readers: HashMap<String, BufReader<File>>,
+}
+
+impl ValueReader {
+    fn new(directory: PathBuf, mac_secret: Option<Vec<u8>>) -> Self {
+        Self {
+            directory,
+            mac_secret,
+            readers: HashMap::new(),
+        }
+    }
+
+    fn read_value(&mut self, dir_entry: &DirEntry) -> Result<Vec<u8>> {
+        let file_name = dir_entry.file_name();
+        if !self.readers.contains_key(file_name) {
+            let file = OpenOptions::new()
+                .read(true)
+                .open(self.directory.join(file_name))
+                .context("Failed to open data file containing this Key-Value")?;
+            let reader = BufReader::with_capacity(64 * 1024, file); // 64 KB
+            self.readers.insert(file_name.to_string(), reader);
+        }
+        let reader = self.readers.get_mut(file_name).unwrap();
+
+        // Relative seeks keep the buffer when the entry is already in it, which is the common case
+        let current_pos: i64 = reader.stream_position()?.try_into()?;
+        let entry_pos: i64 = dir_entry.entry_pos().try_into()?;
+        reader.seek_relative(entry_pos - current_pos)?;
+
+        let entry = Entry::read_from(reader, file_name, dir_entry.entry_pos())?;
+        entry.verify(self.mac_secret.as_deref(), file_name, dir_entry.entry_pos())?;
+        Ok(entry.into_value())
+    }
+}
+
+/// Iterator over live key-value pairs in on-disk order, see [`crate::BitcaskHandler::entries`].
+pub struct Entries {
+    dir_entries: vec::IntoIter<(Vec<u8>, DirEntry)>,
+    values: ValueReader,
+}
+
+impl Entries {
+    pub(crate) fn new(
+        directory: PathBuf,
+        mac_secret: Option<Vec<u8>>,
+        dir_entries: Vec<(Vec<u8>, DirEntry)>,
+    ) -> Self {
+        Self {
+            dir_entries: dir_entries.into_iter(),
+            values: ValueReader::new(directory, mac_secret),
+        }
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, dir_entry) = self.dir_entries.next()?;
+        Some(self.values.read_value(&dir_entry).map(|value| (key, value)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.dir_entries.size_hint()
+    }
+}
+
+/// Lazy iterator over the live keys of a key dir snapshot, see [`crate::BitcaskHandler::keys`].
+///
+/// With an ordered index keys come in ascending order, and `.rev()` gives them in descending order.
+pub struct Keys {
+    snapshot: keydir::IntoIter,
+    now: u64,
+    expiry_secs: Option<u64>,
+}
+
+impl Keys {
+    pub(crate) fn new(snapshot: keydir::IntoIter, now: u64, expiry_secs: Option<u64>) -> Self {
+        Self {
+            snapshot,
+            now,
+            expiry_secs,
+        }
+    }
+
+    fn next_live(&mut self) -> Option<(Vec<u8>, DirEntry)> {
+        self.snapshot
+            .find(|(_, dir_entry)| !dir_entry.is_expired(self.now, self.expiry_secs))
+    }
+
+    fn next_back_live(&mut self) -> Option<(Vec<u8>, DirEntry)> {
+        self.snapshot
+            .rfind(|(_, dir_entry)| !dir_entry.is_expired(self.now, self.expiry_secs))
+    }
+}
+
+impl Iterator for Keys {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_live().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.snapshot.size_hint().1)
+    }
+}
+
+impl DoubleEndedIterator for Keys {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.next_back_live().map(|(key, _)| key)
+    }
+}
+
+/// Lazy iterator over the live key-value pairs of a key dir snapshot, see [`crate::BitcaskHandler::iter`].
+///
+/// Visits keys in the same order as [`Keys`], so it can be reversed too.
+pub struct Iter {
+    keys: Keys,
+    values: ValueReader,
+}
+
+impl Iter {
+    pub(crate) fn new(keys: Keys, directory: PathBuf, mac_secret: Option<Vec<u8>>) -> Self {
+        Self {
+            keys,
+            values: ValueReader::new(directory, mac_secret),
+        }
+    }
+}
+
+impl Iterator for Iter {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, dir_entry) = self.keys.next_live()?;
+        Some(self.values.read_value(&dir_entry).map(|value| (key, value)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Iter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, dir_entry) = self.keys.next_back_live()?;
+        Some(self.values.read_value(&dir_entry).map(|value| (key, value)))
+    }
+}
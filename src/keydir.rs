@@ -0,0 +1,244 @@
+use std::ops::Bound;
+
+use crate::{IndexKind, engine::DirEntry};
+
+type SharedPtr = imbl::shared_ptr::DefaultSharedPtr;
+
+/// In-memory index from keys to the position of their latest entry.
+///
+/// Both variants are persistent maps: cloning is O(1), which gives iterators a consistent snapshot without
+/// copying the keys. The ordered one costs more memory and CPU per operation but supports range scans.
+#[derive(Clone)]
+pub enum KeyDir {
+    Hash(imbl::HashMap<Vec<u8>, DirEntry>),
+    Ordered(imbl::OrdMap<Vec<u8>, DirEntry>),
+}
+
+impl KeyDir {
+    pub fn new(index: IndexKind) -> Self {
+        match index {
+            IndexKind::Hash => Self::Hash(imbl::HashMap::new()),
+            IndexKind::Ordered => Self::Ordered(imbl::OrdMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&DirEntry> {
+        match self {
+            Self::Hash(map) => map.get(key),
+            Self::Ordered(map) => map.get(key),
+        }
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, dir_entry: DirEntry) {
+        match self {
+            Self::Hash(map) => map.insert(key, dir_entry),
+            Self::Ordered(map) => map.insert(key, dir_entry),
+        };
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        match self {
+            Self::Hash(map) => map.remove(key),
+            Self::Ordered(map) => map.remove(key),
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Hash(map) => map.len(),
+            Self::Ordered(map) => map.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&Vec<u8>, &DirEntry)> + '_> {
+        match self {
+            Self::Hash(map) => Box::new(map.iter()),
+            Self::Ordered(map) => Box::new(map.iter()),
+        }
+    }
+
+    /// Removes the keys in `[start, end)`, `end` being `None` for no upper bound, and returns them.
+    /// A hash index has to look at every key.
+    pub fn remove_range(&mut self, start: &[u8], end: Option<&[u8]>) -> Vec<(Vec<u8>, DirEntry)> {
+        let in_range = |key: &Vec<u8>| key.as_slice() >= start && end.is_none_or(|end| key.as_slice() < end);
+        let removed: Vec<(Vec<u8>, DirEntry)> = match self {
+            Self::Hash(map) => map
+                .iter()
+                .filter(|(key, _)| in_range(key))
+                .map(|(key, dir_entry)| (key.clone(), dir_entry.clone()))
+                .collect(),
+            Self::Ordered(map) => {
+                let end = end.map_or(Bound::Unbounded, Bound::Excluded);
+                map.range::<_, [u8]>((Bound::Included(start), end))
+                    .map(|(key, dir_entry)| (key.clone(), dir_entry.clone()))
+                    .collect()
+            }
+        };
+        for (key, _) in &removed {
+            self.remove(key);
+        }
+        removed
+    }
+
+    /// An empty key dir using the same kind of index.
+    pub fn empty_like(&self) -> Self {
+        match self {
+            Self::Hash(_) => Self::Hash(imbl::HashMap::new()),
+            Self::Ordered(_) => Self::Ordered(imbl::OrdMap::new()),
+        }
+    }
+
+    /// Consumes the key dir into an iterator over the keys within the bounds, `None` for a hash index.
+    pub fn into_range(self, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> Option<IntoIter> {
+        match self {
+            Self::Hash(_) => None,
+            Self::Ordered(map) => Some(IntoIter::Ordered(OrderedCursor { map, start, end })),
+        }
+    }
+
+    /// Consumes the key dir into an iterator over the keys starting with `prefix`. A hash index has to look
+    /// at every key.
+    pub fn into_prefix(self, prefix: &[u8]) -> IntoIter {
+        match self {
+            Self::Hash(map) => IntoIter::HashPrefix(map.into_iter(), prefix.to_vec()),
+            Self::Ordered(map) => IntoIter::Ordered(OrderedCursor {
+                map,
+                start: Bound::Included(prefix.to_vec()),
+                end: prefix_end(prefix).map_or(Bound::Unbounded, Bound::Excluded),
+            }),
+        }
+    }
+}
+
+impl IntoIterator for KeyDir {
+    type Item = (Vec<u8>, DirEntry);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        match self {
+            Self::Hash(map) => IntoIter::Hash(map.into_iter()),
+            Self::Ordered(map) => IntoIter::Ordered(OrderedCursor {
+                map,
+                start: Bound::Unbounded,
+                end: Bound::Unbounded,
+            }),
+        }
+    }
+}
+
+/// Owned iterator over a key dir. Hash indexes are visited in arbitrary order, from both ends alike.
+pub enum IntoIter {
+    Hash(imbl::hashmap::ConsumingIter<(Vec<u8>, DirEntry), SharedPtr>),
+    HashPrefix(imbl::hashmap::ConsumingIter<(Vec<u8>, DirEntry), SharedPtr>, Vec<u8>),
+    Ordered(OrderedCursor),
+}
+
+impl Iterator for IntoIter {
+    type Item = (Vec<u8>, DirEntry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Hash(iter) => iter.next(),
+            Self::HashPrefix(iter, prefix) => iter.find(|(key, _)| key.starts_with(prefix)),
+            Self::Ordered(cursor) => cursor.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Hash(iter) => iter.size_hint(),
+            Self::HashPrefix(iter, _) => (0, iter.size_hint().1),
+            Self::Ordered(cursor) => (0, Some(cursor.map.len())),
+        }
+    }
+}
+
+impl DoubleEndedIterator for IntoIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Hash(iter) => iter.next(),
+            Self::HashPrefix(iter, prefix) => iter.find(|(key, _)| key.starts_with(prefix)),
+            Self::Ordered(cursor) => cursor.next_back(),
+        }
+    }
+}
+
+/// Walks an ordered snapshot by narrowing its bounds past each visited key.
+///
+/// Every step is an O(log n) lookup, splitting the map upfront would cost a full traversal.
+pub struct OrderedCursor {
+    map: imbl::OrdMap<Vec<u8>, DirEntry>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+impl OrderedCursor {
+    fn is_exhausted(&self) -> bool {
+        match (&self.start, &self.end) {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (Bound::Included(start) | Bound::Excluded(start), Bound::Excluded(end))
+            | (Bound::Excluded(start), Bound::Included(end)) => start >= end,
+            _ => false,
+        }
+    }
+
+    fn bounds(&self) -> (Bound<&Vec<u8>>, Bound<&Vec<u8>>) {
+        (self.start.as_ref(), self.end.as_ref())
+    }
+
+    fn next(&mut self) -> Option<(Vec<u8>, DirEntry)> {
+        if self.is_exhausted() {
+            return None;
+        }
+        let (key, dir_entry) = self.map.range::<_, Vec<u8>>(self.bounds()).next()?;
+        let item = (key.clone(), dir_entry.clone());
+        self.start = Bound::Excluded(item.0.clone());
+        Some(item)
+    }
+
+    fn next_back(&mut self) -> Option<(Vec<u8>, DirEntry)> {
+        if self.is_exhausted() {
+            return None;
+        }
+        let (key, dir_entry) = self.map.range::<_, Vec<u8>>(self.bounds()).next_back()?;
+        let item = (key.clone(), dir_entry.clone());
+        self.end = Bound::Excluded(item.0.clone());
+        Some(item)
+    }
+}
+
+/// Turns bounds into the equivalent `[start, end)` range, `end` being `None` for no upper bound.
+pub fn half_open(start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> (Vec<u8>, Option<Vec<u8>>) {
+    // Appending a zero byte gives the smallest key greater than the original one
+    let successor = |mut key: Vec<u8>| {
+        key.push(0);
+        key
+    };
+    let start = match start {
+        Bound::Included(key) => key,
+        Bound::Excluded(key) => successor(key),
+        Bound::Unbounded => Vec::new(),
+    };
+    let end = match end {
+        Bound::Included(key) => Some(successor(key)),
+        Bound::Excluded(key) => Some(key),
+        Bound::Unbounded => None,
+    };
+    (start, end)
+}
+
+/// Smallest key greater than every key starting with `prefix`, `None` if there is none.
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
@@ -1,8 +1,32 @@
 mod handler;
+mod batch;
+mod changes;
+pub mod client;
+mod clock;
+mod commit;
 mod engine;
+mod error;
 mod files;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod iter;
+mod keydir;
 mod options;
+pub mod replication;
+mod resp;
+pub mod server;
+mod store;
+mod transaction;
+pub mod upgrade;
 
 // Public exports
 pub use handler::BitcaskHandler;
-pub use options::Options;
+pub use batch::WriteBatch;
+pub use changes::{Change, Changes, LogPosition};
+pub use clock::{Clock, HybridLogicalClock, ManualClock, SystemClock};
+pub use engine::{Stats, VersionedValue};
+pub use error::BitcaskError;
+pub use iter::{Entries, Iter, Keys};
+pub use options::{IndexKind, Options};
+pub use store::{KeyValueStore, MemoryStore, StoreIter};
+pub use transaction::Transaction;
@@ -1,18 +1,49 @@
+use std::sync::Arc;
+
+use crate::{Clock, HybridLogicalClock};
+
 pub struct Options {
     pub read_write: bool,
     pub sync_on_put: bool,
     pub enable_compression: bool, // to be supported later
     pub max_data_size: usize,
+    // Secret used to sign every record with HMAC-SHA256. When set, records with a missing or wrong MAC
+    // are rejected on `get` and while building the key dir. Each record is signed on its own: modified
+    // records are detected, but not removed ones, nor data files replaced by an older copy.
+    pub mac_secret: Option<Vec<u8>>,
+    // Entries older than this are hidden from `get` and `list_keys` and dropped by `merge`, on top of their own TTL.
+    pub expiry_secs: Option<u64>,
+    // Timestamps every entry and tells the current time for expiry checks, never goes backwards by default.
+    pub clock: Arc<dyn Clock>,
+    // Kind of in-memory index over the keys, only an ordered one supports `range` and `prefix` scans.
+    pub index: IndexKind,
+    // Allocates `max_data_size` bytes on disk when a working file is created, fewer extents and no file size
+    // update on every sync, at the cost of disk space for the working file. Files are cut to their data size once immutable.
+    pub preallocate: bool,
 }
 
-impl Options {
-    pub fn default() -> Self {
+/// In-memory index used for the key dir.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexKind {
+    /// Hash map, the fastest lookups, keys are iterated in arbitrary order.
+    #[default]
+    Hash,
+    /// B-tree sorted by key bytes, slower and bigger but iterates keys in order and supports range scans.
+    Ordered,
+}
+
+impl Default for Options {
+    fn default() -> Self {
         Self {
             read_write: false,
             sync_on_put: false,
             enable_compression: false,
             max_data_size: 2 * 1024 * 1024 * 1024, // 2 GB
+            mac_secret: None,
+            expiry_secs: None,
+            clock: Arc::new(HybridLogicalClock::new()),
+            index: IndexKind::default(),
+            preallocate: false,
         }
     }
 }
-
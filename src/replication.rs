@@ -0,0 +1,295 @@
+//! Warm standbys kept up to date by shipping the data files of a leader, served by
+//! [`crate::server::serve_replication`], to a [`Follower`].
+//!
+//! Data files are append-only, so a follower only needs to know where its copy ends: it asks for the data
+//! files from that position on, then keeps receiving the records appended to the working file of the leader.
+//! After a merge of the leader the follower receives the merged files and removes the ones they replace.
+
+use std::{
+    fs,
+    io::{BufReader, Write},
+    net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use anyhow::{Context, Result, bail};
+use bincode::{Decode, Encode, config, decode_from_std_read, encode_into_std_write};
+
+use crate::{BitcaskHandler, Options, files::WorkingFile};
+
+// Delay before connecting again to a leader that is unreachable or dropped the connection
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+// A leader sends a heartbeat every second when there is nothing to ship, it's considered gone after this
+const LEADER_TIMEOUT: Duration = Duration::from_secs(5);
+// Bytes of a data file sent in one shipment
+pub(crate) const CHUNK_SIZE: usize = 1024 * 1024; // 1 MB
+// Largest shipment a follower decodes, a chunk and its header. Bigger ones are refused before allocating, a
+// broken or hostile peer can't make the follower allocate more. Lists of data file ids take at most 10 bytes
+// per id, they fit up to 100 000 files.
+const MAX_SHIPMENT_SIZE: usize = CHUNK_SIZE + 64 * 1024;
+// A `Follow` is two integers
+pub(crate) const MAX_FOLLOW_SIZE: usize = 32;
+
+/// First message of a follower, where its copy of the data files of the leader ends.
+#[derive(Encode, Decode)]
+pub(crate) struct Follow {
+    pub file_id: u64,
+    pub offset: u64,
+}
+
+/// Messages sent by a leader to a follower.
+#[derive(Encode, Decode)]
+pub(crate) enum Shipment {
+    /// Bytes of a data file starting at `offset`, files are shipped in id order.
+    Chunk { file_id: u64, offset: u64, bytes: Vec<u8> },
+    /// Ids of every data file of the leader, sent once they're all shipped.
+    DataFiles { ids: Vec<u64> },
+    /// Sent when there's nothing to ship, so both sides notice a dead connection.
+    Heartbeat,
+}
+
+/// Read-only copy of a datastore, replicated from a leader and promotable to a writer if the leader is gone.
+///
+/// Replication runs on a background thread which reconnects whenever the connection to the leader breaks,
+/// resuming from where the local data files end. Reads through [`Follower::db`] see the records of the leader
+/// as they are applied, in the order the leader wrote them.
+pub struct Follower {
+    db: Arc<BitcaskHandler>,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    is_stopped: AtomicBool,
+    // Current connection to the leader, shut down to interrupt a blocked read when stopping
+    stream: Mutex<Option<TcpStream>>,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Follower {
+    /// Opens a datastore in `directory`, created if missing, and starts replicating a leader into it.
+    ///
+    /// # Arguments
+    ///
+    /// * `leader` - Address the leader serves replication on, see [`crate::server::serve_replication`].
+    /// * `directory` - Directory holding the copy of the data files, empty for a new follower.
+    /// * `options` - Options of the datastore, `mac_secret` must match the leader's. `read_write` is ignored,
+    ///   the datastore is read-only until it's promoted.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the datastore can't be opened. Replication errors don't stop the follower, it connects
+    /// again, see [`Follower::last_error`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use bitcask::replication::Follower;
+    ///
+    /// let follower = Follower::start("127.0.0.1:7379", Path::new("/tmp/bitcask-standby"), None).unwrap();
+    /// let value = follower.db().get(b"user:1");
+    ///
+    /// // The leader is gone, the standby takes over
+    /// let db = follower.promote().unwrap();
+    /// db.put(b"user:2", b"Grace").unwrap();
+    /// ```
+    pub fn start(leader: impl ToSocketAddrs, directory: &Path, options: Option<Options>) -> Result<Self> {
+        let leader = leader
+            .to_socket_addrs()?
+            .next()
+            .context("The leader address didn't resolve to any address")?;
+        let options = Options {
+            read_write: false,
+            ..options.unwrap_or_default()
+        };
+        fs::create_dir_all(directory)
+            .with_context(|| format!("Couldn't create {}", directory.display()))?;
+        let db = Arc::new(BitcaskHandler::open(directory, Some(options))?);
+        let shared = Arc::new(Shared {
+            is_stopped: AtomicBool::new(false),
+            stream: Mutex::new(None),
+            last_error: Mutex::new(None),
+        });
+
+        let replica = Replica {
+            leader,
+            directory: directory.to_path_buf(),
+            db: Arc::clone(&db),
+            shared: Arc::clone(&shared),
+        };
+        let thread = thread::Builder::new()
+            .name("bitcask-follower".to_string())
+            .spawn(move || replica.run())?;
+        Ok(Self {
+            db,
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// The replicated datastore, for reads. It can be served like any other, e.g. with
+    /// [`crate::server::serve_redis`], writes fail until it's promoted.
+    pub fn db(&self) -> Arc<BitcaskHandler> {
+        Arc::clone(&self.db)
+    }
+
+    /// Error that broke the last connection to the leader, if any, replication resumes once it's reachable.
+    pub fn last_error(&self) -> Option<String> {
+        self.shared.lock_last_error().clone()
+    }
+
+    /// Stops replicating and opens the datastore for writing, making this follower the new leader.
+    ///
+    /// Records of the leader that weren't shipped yet are lost, writes go to a new data file after the
+    /// replicated ones. [`BitcaskHandler::promote`] does the same from a handler returned by
+    /// [`Follower::db`], replication then stops on its own.
+    ///
+    /// # Errors
+    ///
+    /// Fails if another process has the directory open for writing.
+    pub fn promote(mut self) -> Result<Arc<BitcaskHandler>> {
+        self.stop();
+        self.db.promote()?;
+        Ok(Arc::clone(&self.db))
+    }
+
+    fn stop(&mut self) {
+        self.shared.is_stopped.store(true, Ordering::SeqCst);
+        if let Some(stream) = self.shared.lock_stream().as_ref() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Follower {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Shared {
+    fn is_stopped(&self) -> bool {
+        self.is_stopped.load(Ordering::SeqCst)
+    }
+
+    fn lock_stream(&self) -> MutexGuard<'_, Option<TcpStream>> {
+        self.stream
+            .lock()
+            .expect("Follower lock poisoned by a panicking thread")
+    }
+
+    fn lock_last_error(&self) -> MutexGuard<'_, Option<String>> {
+        self.last_error
+            .lock()
+            .expect("Follower lock poisoned by a panicking thread")
+    }
+}
+
+/// State of the replication thread of a [`Follower`].
+struct Replica {
+    leader: SocketAddr,
+    directory: PathBuf,
+    db: Arc<BitcaskHandler>,
+    shared: Arc<Shared>,
+}
+
+impl Replica {
+    fn run(self) {
+        while !self.shared.is_stopped() {
+            match self.follow() {
+                Ok(()) => break, // promoted
+                Err(e) if !self.shared.is_stopped() => {
+                    *self.shared.lock_last_error() = Some(format!("{e:#}"));
+                }
+                Err(_) => break,
+            }
+            thread::park_timeout(RECONNECT_DELAY);
+        }
+    }
+
+    /// Applies the shipments of the leader until the connection breaks or the datastore is promoted.
+    fn follow(&self) -> Result<()> {
+        let mut stream = TcpStream::connect_timeout(&self.leader, LEADER_TIMEOUT)
+            .with_context(|| format!("Couldn't connect to the leader at {}", self.leader))?;
+        stream.set_read_timeout(Some(LEADER_TIMEOUT))?;
+        *self.shared.lock_stream() = Some(stream.try_clone()?);
+        if self.shared.is_stopped() {
+            return Ok(());
+        }
+
+        let (file_id, offset) = self.position()?;
+        encode_into_std_write(Follow { file_id, offset }, &mut stream, config::standard())?;
+        stream.flush()?;
+        *self.shared.lock_last_error() = None;
+
+        let mut reader = BufReader::with_capacity(1024 * 1024, stream);
+        // Bytes of a record not fully received yet, they're added to the data file once it's complete
+        let mut pending: Vec<u8> = Vec::new();
+        let mut pending_file_id = None;
+        loop {
+            let config = config::standard().with_limit::<MAX_SHIPMENT_SIZE>();
+            let shipment: Shipment =
+                decode_from_std_read(&mut reader, config).context("Lost the connection to the leader")?;
+            let mut engine = self.db.engine();
+            if engine.is_read_write() {
+                return Ok(());
+            }
+            match shipment {
+                Shipment::Chunk { file_id, offset, bytes } => {
+                    if pending_file_id != Some(file_id) {
+                        // The previous file was removed by a merge of the leader before it was fully shipped,
+                        // its records are also in the merged files
+                        pending.clear();
+                        pending_file_id = Some(file_id);
+                    }
+                    let file_name = WorkingFile::file_name(file_id.try_into()?);
+                    let local_size = fs::metadata(self.directory.join(&file_name)).map_or(0, |m| m.len());
+                    if offset != local_size + u64::try_from(pending.len())? {
+                        bail!("The leader shipped {file_name} from {offset}, it doesn't follow the local copy");
+                    }
+                    pending.extend(bytes);
+                    let used = engine.append_replicated(&file_name, &pending)?;
+                    pending.drain(..used);
+                    self.db.group_commit.advance(engine.next_sequence(), engine.log_end()?);
+                }
+                Shipment::DataFiles { ids } => {
+                    for file_path in WorkingFile::list_data_files(&self.directory)? {
+                        let Some(file_name) = file_path.file_name().and_then(|s| s.to_str()) else {
+                            continue;
+                        };
+                        let Some(id) = WorkingFile::parse_file_id(file_name) else {
+                            continue;
+                        };
+                        if !ids.contains(&id.try_into()?) {
+                            engine.remove_replicated(file_name)?;
+                        }
+                    }
+                }
+                Shipment::Heartbeat => {}
+            }
+        }
+    }
+
+    /// Id and size of the newest local data file, the leader ships everything after it.
+    fn position(&self) -> Result<(u64, u64)> {
+        let Some(file_path) = WorkingFile::list_data_files(&self.directory)?.pop() else {
+            return Ok((0, 0));
+        };
+        let file_id = file_path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .and_then(WorkingFile::parse_file_id)
+            .context("Unexpected data file name")?;
+        Ok((file_id.try_into()?, fs::metadata(&file_path)?.len()))
+    }
+}
@@ -0,0 +1,265 @@
+//! Redis serialization protocol (RESP2 and RESP3) frames, shared by the server and the client.
+
+use std::io::{BufRead, Write};
+
+use anyhow::{Context, Result, bail};
+
+// Same limits as Redis, a peer can't make us allocate more than that for a single frame
+const MAX_BULK_LENGTH: usize = 512 * 1024 * 1024; // 512 MB
+const MAX_AGGREGATE_LENGTH: usize = 1024 * 1024;
+// Frames nest through recursive calls, deeper frames would overflow the stack
+const MAX_NESTING_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Frame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    Boolean(bool),
+    Array(Vec<Frame>),
+    /// Sent as a flat array of keys and values to RESP2 peers.
+    Map(Vec<(Frame, Frame)>),
+}
+
+impl Frame {
+    pub(crate) fn ok() -> Self {
+        Self::Simple("OK".to_string())
+    }
+
+    pub(crate) fn error(message: impl Into<String>) -> Self {
+        Self::Error(message.into())
+    }
+
+    pub(crate) fn bulk(bytes: impl Into<Vec<u8>>) -> Self {
+        Self::Bulk(bytes.into())
+    }
+
+    pub(crate) fn optional_bulk(bytes: Option<Vec<u8>>) -> Self {
+        bytes.map_or(Self::Null, Self::Bulk)
+    }
+
+    /// Writes the frame in the given protocol version, 2 or 3.
+    pub(crate) fn write_to(&self, writer: &mut impl Write, protocol: u8) -> Result<()> {
+        match self {
+            Self::Simple(text) => write!(writer, "+{text}\r\n")?,
+            Self::Error(message) => write!(writer, "-{message}\r\n")?,
+            Self::Integer(value) => write!(writer, ":{value}\r\n")?,
+            Self::Bulk(bytes) => {
+                write!(writer, "${}\r\n", bytes.len())?;
+                writer.write_all(bytes)?;
+                writer.write_all(b"\r\n")?;
+            }
+            Self::Null if protocol >= 3 => writer.write_all(b"_\r\n")?,
+            Self::Null => writer.write_all(b"$-1\r\n")?,
+            Self::Boolean(value) if protocol >= 3 => write!(writer, "#{}\r\n", if *value { 't' } else { 'f' })?,
+            Self::Boolean(value) => write!(writer, ":{}\r\n", *value as i64)?,
+            Self::Array(frames) => {
+                write!(writer, "*{}\r\n", frames.len())?;
+                for frame in frames {
+                    frame.write_to(writer, protocol)?;
+                }
+            }
+            Self::Map(pairs) => {
+                if protocol >= 3 {
+                    write!(writer, "%{}\r\n", pairs.len())?;
+                } else {
+                    write!(writer, "*{}\r\n", pairs.len() * 2)?;
+                }
+                for (key, value) in pairs {
+                    key.write_to(writer, protocol)?;
+                    value.write_to(writer, protocol)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads one frame of any protocol version, `None` if the peer closed the connection before sending one.
+    pub(crate) fn read_from(reader: &mut impl BufRead) -> Result<Option<Self>> {
+        Self::read_nested(reader, 0)
+    }
+
+    /// Reads a frame nested in `depth` arrays or maps.
+    fn read_nested(reader: &mut impl BufRead, depth: usize) -> Result<Option<Self>> {
+        let Some(line) = read_line(reader)? else {
+            return Ok(None);
+        };
+        let (kind, rest) = line.split_first().context("Protocol error: empty line")?;
+        let text = || String::from_utf8_lossy(rest).into_owned();
+        let frame = match kind {
+            b'+' => Self::Simple(text()),
+            b'-' => Self::Error(text()),
+            b':' => Self::Integer(parse_number(rest)?),
+            b'_' => Self::Null,
+            b'#' => Self::Boolean(rest == b"t"),
+            b'$' => read_bulk(reader, rest)?.map_or(Self::Null, Self::Bulk),
+            b'*' | b'%' if depth >= MAX_NESTING_DEPTH => bail!("Protocol error: nesting too deep"),
+            b'*' => match parse_number(rest)? {
+                -1 => Self::Null,
+                length => {
+                    let length = checked_length(length, MAX_AGGREGATE_LENGTH)?;
+                    let mut frames = Vec::with_capacity(length);
+                    for _ in 0..length {
+                        frames.push(Self::read_nested(reader, depth + 1)?.context("Connection closed in an array")?);
+                    }
+                    Self::Array(frames)
+                }
+            },
+            b'%' => {
+                let length = checked_length(parse_number(rest)?, MAX_AGGREGATE_LENGTH)?;
+                let mut pairs = Vec::with_capacity(length);
+                for _ in 0..length {
+                    let key = Self::read_nested(reader, depth + 1)?.context("Connection closed in a map")?;
+                    let value = Self::read_nested(reader, depth + 1)?.context("Connection closed in a map")?;
+                    pairs.push((key, value));
+                }
+                Self::Map(pairs)
+            }
+            _ => bail!("Protocol error: unexpected frame type {:?}", *kind as char),
+        };
+        Ok(Some(frame))
+    }
+}
+
+/// Reads a command sent by a client: an array of bulk strings, or an inline command as typed in a terminal.
+pub(crate) fn read_command(reader: &mut impl BufRead) -> Result<Option<Vec<Vec<u8>>>> {
+    loop {
+        let Some(&first) = reader.fill_buf()?.first() else {
+            return Ok(None);
+        };
+        if first == b'*' {
+            // Read flat rather than as a frame, clients have no reason to nest anything in a command
+            let Some(line) = read_line(reader)? else {
+                return Ok(None);
+            };
+            let length = checked_length(parse_number(&line[1..])?, MAX_AGGREGATE_LENGTH)?;
+            let mut args = Vec::with_capacity(length);
+            for _ in 0..length {
+                let line = read_line(reader)?.context("Connection closed in a command")?;
+                let bulk = match line.split_first() {
+                    Some((b'$', rest)) => read_bulk(reader, rest)?,
+                    _ => None,
+                };
+                args.push(bulk.context("Protocol error: expected an array of bulk strings")?);
+            }
+            return Ok(Some(args));
+        }
+
+        let Some(line) = read_line(reader)? else {
+            return Ok(None);
+        };
+        let args: Vec<Vec<u8>> = line
+            .split(|byte| byte.is_ascii_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect();
+        // Empty lines are ignored, like Redis does
+        if !args.is_empty() {
+            return Ok(Some(args));
+        }
+    }
+}
+
+/// Reads the bytes of a bulk string whose header line ends with `length`, `None` for a null bulk string.
+fn read_bulk(reader: &mut impl BufRead, length: &[u8]) -> Result<Option<Vec<u8>>> {
+    let length = match parse_number(length)? {
+        -1 => return Ok(None),
+        length => checked_length(length, MAX_BULK_LENGTH)?,
+    };
+    let mut bytes = vec![0; length + 2];
+    reader.read_exact(&mut bytes)?;
+    if !bytes.ends_with(b"\r\n") {
+        bail!("Protocol error: bulk string not terminated by CRLF");
+    }
+    bytes.truncate(length);
+    Ok(Some(bytes))
+}
+
+/// Reads a line without its line ending, `None` at the end of the stream.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    // A line is only metadata, a peer sending megabytes without a line ending is broken or malicious
+    let read = std::io::Read::take(&mut *reader, 64 * 1024).read_until(b'\n', &mut line)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        bail!("Protocol error: line too long or not terminated");
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_number(bytes: &[u8]) -> Result<i64> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .context("Protocol error: invalid number")
+}
+
+fn checked_length(length: i64, max: usize) -> Result<usize> {
+    match usize::try_from(length) {
+        Ok(length) if length <= max => Ok(length),
+        _ => bail!("Protocol error: invalid length {length}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_frame(bytes: &[u8]) -> Result<Option<Frame>> {
+        Frame::read_from(&mut &bytes[..])
+    }
+
+    fn nested_arrays(depth: usize) -> Vec<u8> {
+        let mut bytes = b"*1\r\n".repeat(depth);
+        bytes.extend_from_slice(b":1\r\n");
+        bytes
+    }
+
+    #[test]
+    fn frames_nest_up_to_the_limit() {
+        assert!(read_frame(&nested_arrays(MAX_NESTING_DEPTH)).unwrap().is_some());
+        let error = read_frame(&nested_arrays(MAX_NESTING_DEPTH + 1)).unwrap_err();
+        assert!(error.to_string().contains("nesting too deep"), "{error}");
+        // Deep enough to overflow the stack without the limit
+        assert!(read_frame(&nested_arrays(200_000)).is_err());
+    }
+
+    #[test]
+    fn lengths_over_the_limits_are_rejected_before_allocating() {
+        let aggregate = format!("*{}\r\n", MAX_AGGREGATE_LENGTH + 1);
+        assert!(read_frame(aggregate.as_bytes()).is_err());
+        assert!(read_command(&mut aggregate.as_bytes()).is_err());
+        let bulk = format!("${}\r\n", MAX_BULK_LENGTH + 1);
+        assert!(read_frame(bulk.as_bytes()).is_err());
+        assert!(read_frame(b"$-2\r\n").is_err());
+        assert_eq!(read_frame(b"$-1\r\n").unwrap(), Some(Frame::Null));
+    }
+
+    #[test]
+    fn lines_without_an_ending_are_rejected() {
+        let mut line = vec![b'+'; 128 * 1024];
+        assert!(read_frame(&line).is_err());
+        line.extend_from_slice(b"\r\n");
+        assert!(read_frame(&line).is_err());
+        assert!(read_frame(b"$3\r\nabcd\r\n").is_err());
+    }
+
+    #[test]
+    fn commands_are_flat_arrays_of_bulk_strings() {
+        let command = read_command(&mut &b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n"[..]).unwrap();
+        assert_eq!(command, Some(vec![b"GET".to_vec(), b"a".to_vec()]));
+        let inline = read_command(&mut &b"\r\nPING  hello\r\n"[..]).unwrap();
+        assert_eq!(inline, Some(vec![b"PING".to_vec(), b"hello".to_vec()]));
+        assert!(read_command(&mut &b"*1\r\n*1\r\n$1\r\na\r\n"[..]).is_err());
+        assert!(read_command(&mut &b"*1\r\n:1\r\n"[..]).is_err());
+        assert_eq!(read_command(&mut &b""[..]).unwrap(), None);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{net::TcpListener, sync::Arc, time::Duration};
+use anyhow::Result;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    BitcaskError, BitcaskHandler, WriteBatch,
+    grpc::{
+        BatchWriteRequest, BatchWriteResponse, DeleteRequest, DeleteResponse, GetRequest, GetResponse, KeyValue,
+        MergeRequest, MergeResponse, PutRequest, PutResponse, ScanRequest, StatsRequest, StatsResponse,
+        bitcask_server::{Bitcask, BitcaskServer},
+        write_operation::Operation,
+    },
+};
+
+// Pairs a scan reads ahead of a slow client
+const SCAN_BUFFER: usize = 64;
+
+/// Serves the datastore over gRPC, see `proto/bitcask.proto` for the service definition and
+/// [`crate::grpc::BitcaskClient`] for the generated client. Requires the `grpc` feature.
+///
+/// Requests are run on a Tokio runtime created for the server, datastore calls on its blocking threads.
+/// The function only returns if the listener fails.
+///
+/// # Arguments
+///
+/// * `listener` - Bound listener to accept connections from.
+/// * `db` - Datastore to serve, it must be opened with `read_write` for writes to succeed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{net::TcpListener, path::Path, sync::Arc};
+/// use bitcask::{BitcaskHandler, Options, server};
+///
+/// let options = Options { read_write: true, ..Default::default() };
+/// let db = Arc::new(BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap());
+/// server::serve_grpc(TcpListener::bind("127.0.0.1:50051").unwrap(), db).unwrap();
+/// ```
+pub fn serve_grpc(listener: TcpListener, db: Arc<BitcaskHandler>) -> Result<()> {
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(async {
+        let incoming = TcpListenerStream::new(tokio::net::TcpListener::from_std(listener)?);
+        tonic::transport::Server::builder()
+            .add_service(BitcaskServer::new(Service { db }))
+            .serve_with_incoming(incoming)
+            .await?;
+        Ok(())
+    })
+}
+
+struct Service {
+    db: Arc<BitcaskHandler>,
+}
+
+impl Service {
+    /// Runs a datastore call on a blocking thread, the datastore does disk IO and takes locks.
+    async fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&BitcaskHandler) -> Result<T> + Send + 'static,
+    ) -> Result<T, Status> {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || f(&db))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(|e| status(&e))
+    }
+}
+
+#[tonic::async_trait]
+impl Bitcask for Service {
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        let GetRequest { key } = request.into_inner();
+        let versioned = self.run(move |db| db.get_with_meta(&key)).await?;
+        Ok(Response::new(GetResponse {
+            value: versioned.value,
+            version: versioned.version,
+            timestamp: versioned.timestamp,
+            flags: versioned.flags,
+        }))
+    }
+
+    async fn put(&self, request: Request<PutRequest>) -> Result<Response<PutResponse>, Status> {
+        let PutRequest { key, value, ttl_millis } = request.into_inner();
+        self.run(move |db| match ttl_millis {
+            Some(millis) => db.put_with_ttl(&key, &value, Duration::from_millis(millis)),
+            None => db.put(&key, &value),
+        })
+        .await?;
+        Ok(Response::new(PutResponse {}))
+    }
+
+    async fn delete(&self, request: Request<DeleteRequest>) -> Result<Response<DeleteResponse>, Status> {
+        let DeleteRequest { key } = request.into_inner();
+        self.run(move |db| db.delete(&key)).await?;
+        Ok(Response::new(DeleteResponse {}))
+    }
+
+    async fn batch_write(
+        &self,
+        request: Request<BatchWriteRequest>,
+    ) -> Result<Response<BatchWriteResponse>, Status> {
+        let mut batch = WriteBatch::new();
+        for operation in request.into_inner().operations {
+            match operation.operation {
+                Some(Operation::Put(PutRequest { key, value, ttl_millis: Some(millis) })) => {
+                    batch.put_with_ttl(&key, &value, Duration::from_millis(millis))
+                }
+                Some(Operation::Put(PutRequest { key, value, ttl_millis: None })) => batch.put(&key, &value),
+                Some(Operation::Delete(DeleteRequest { key })) => batch.delete(&key),
+                None => return Err(Status::invalid_argument("Empty write operation")),
+            };
+        }
+        self.run(move |db| db.write_batch(batch)).await?;
+        Ok(Response::new(BatchWriteResponse {}))
+    }
+
+    type ScanStream = ReceiverStream<Result<KeyValue, Status>>;
+
+    async fn scan(&self, request: Request<ScanRequest>) -> Result<Response<Self::ScanStream>, Status> {
+        let ScanRequest { prefix, limit } = request.into_inner();
+        let limit = if limit == 0 { usize::MAX } else { limit.try_into().unwrap_or(usize::MAX) };
+        let (sender, receiver) = mpsc::channel(SCAN_BUFFER);
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || {
+            // Values are read from a snapshot taken when the scan starts, later writes aren't visited
+            let pairs = match db.scan_prefix(&prefix) {
+                Ok(pairs) => pairs,
+                Err(e) => {
+                    let _ = sender.blocking_send(Err(status(&e)));
+                    return;
+                }
+            };
+            for pair in pairs.take(limit) {
+                let pair = pair.map(|(key, value)| KeyValue { key, value }).map_err(|e| status(&e));
+                let failed = pair.is_err();
+                // Stops once the client is gone
+                if sender.blocking_send(pair).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn merge(&self, _: Request<MergeRequest>) -> Result<Response<MergeResponse>, Status> {
+        self.run(|db| db.merge()).await?;
+        Ok(Response::new(MergeResponse {}))
+    }
+
+    async fn stats(&self, _: Request<StatsRequest>) -> Result<Response<StatsResponse>, Status> {
+        let stats = self.run(|db| db.stats()).await?;
+        Ok(Response::new(StatsResponse {
+            keys: stats.keys as u64,
+            data_files: stats.data_files as u64,
+            data_size: stats.data_size,
+        }))
+    }
+}
+
+fn status(error: &anyhow::Error) -> Status {
+    let message = format!("{error:#}");
+    match error.chain().find_map(|cause| cause.downcast_ref::<BitcaskError>()) {
+        Some(BitcaskError::KeyNotFound) => Status::not_found(message),
+        Some(BitcaskError::Conflict { .. }) => Status::aborted(message),
+        Some(BitcaskError::Corrupted { .. } | BitcaskError::Tampered { .. }) => Status::data_loss(message),
+        Some(BitcaskError::Compacted { .. }) => Status::out_of_range(message),
+        Some(BitcaskError::Locked) => Status::failed_precondition(message),
+        None => Status::internal(message),
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    io::{Cursor, Read},
+    net::TcpListener,
+    sync::Arc,
+    thread,
+};
+use anyhow::{Result, anyhow};
+use percent_encoding::{NON_ALPHANUMERIC, percent_decode_str, percent_encode};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{BitcaskError, BitcaskHandler, Transaction, VersionedValue};
+
+type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+/// Settings of [`serve_http_with_options`].
+#[derive(Clone, Debug)]
+pub struct HttpOptions {
+    // Largest value a `PUT` can store, bigger bodies are refused with `413 Content Too Large`
+    pub max_body_size: usize,
+    // Threads handling requests, requests wait in line while they are all busy
+    pub workers: usize,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            max_body_size: 64 * 1024 * 1024, // 64 MB
+            workers: 16,
+        }
+    }
+}
+
+/// Serves the datastore over HTTP.
+///
+/// Keys are the percent-decoded rest of the path after `/keys/`, slashes included, values are raw request and
+/// response bodies, so both can hold any bytes.
+///
+/// * `GET /keys/{key}` returns the value with an `ETag` made from the entry's timestamp and version,
+///   `If-None-Match` gives `304 Not Modified` while the value is unchanged. `HEAD` is supported as well.
+/// * `PUT /keys/{key}` stores the body. With `If-Match` the key is only written if its `ETag` is still one of
+///   the given ones, or if it exists for `If-Match: *`. With `If-None-Match: *` it's only written if it doesn't
+///   exist. Otherwise it fails with `412`.
+/// * `DELETE /keys/{key}` deletes the key, `404` if it doesn't exist. `If-Match` is honoured as for `PUT`.
+/// * `GET /keys?prefix={prefix}` lists the keys starting with the prefix, one percent-encoded key per line.
+/// * `POST /admin/merge` and `POST /admin/sync` run [`BitcaskHandler::merge`] and [`BitcaskHandler::sync`].
+/// * `GET /stats` returns the [`crate::Stats`] as JSON.
+///
+/// Requests are handled by a fixed pool of threads, see [`HttpOptions::default`] for its size and the largest
+/// body accepted. The function only returns if the listener fails.
+///
+/// # Arguments
+///
+/// * `listener` - Bound listener to accept connections from.
+/// * `db` - Datastore to serve, it must be opened with `read_write` for writes to succeed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{net::TcpListener, path::Path, sync::Arc};
+/// use bitcask::{BitcaskHandler, Options, server};
+///
+/// let options = Options { read_write: true, ..Default::default() };
+/// let db = Arc::new(BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap());
+/// server::serve_http(TcpListener::bind("127.0.0.1:8080").unwrap(), db).unwrap();
+/// ```
+pub fn serve_http(listener: TcpListener, db: Arc<BitcaskHandler>) -> Result<()> {
+    serve_http_with_options(listener, db, None)
+}
+
+/// Serves the datastore over HTTP like [`serve_http`], with the given body limit and number of threads.
+///
+/// # Arguments
+///
+/// * `listener` - Bound listener to accept connections from.
+/// * `db` - Datastore to serve, it must be opened with `read_write` for writes to succeed.
+/// * `options` - Body limit and number of threads, [`HttpOptions::default`] if `None`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{net::TcpListener, path::Path, sync::Arc};
+/// use bitcask::{BitcaskHandler, Options, server::{self, HttpOptions}};
+///
+/// let options = Options { read_write: true, ..Default::default() };
+/// let db = Arc::new(BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap());
+/// let http_options = HttpOptions { max_body_size: 1024 * 1024, ..Default::default() };
+/// server::serve_http_with_options(TcpListener::bind("127.0.0.1:8080").unwrap(), db, Some(http_options)).unwrap();
+/// ```
+pub fn serve_http_with_options(
+    listener: TcpListener,
+    db: Arc<BitcaskHandler>,
+    options: Option<HttpOptions>,
+) -> Result<()> {
+    let options = options.unwrap_or_default();
+    let server = Arc::new(Server::from_listener(listener, None).map_err(|e| anyhow!(e))?);
+    let serve = move || -> Result<()> {
+        loop {
+            let request = server.recv()?;
+            // A client gone before the response only concerns itself
+            let _ = handle(&db, request, options.max_body_size);
+        }
+    };
+    // The calling thread is one of the workers
+    for _ in 1..options.workers {
+        let serve = serve.clone();
+        thread::spawn(serve);
+    }
+    serve()
+}
+
+fn handle(db: &BitcaskHandler, mut request: Request, max_body_size: usize) -> std::io::Result<()> {
+    let response = route(db, &mut request, max_body_size).unwrap_or_else(|e| {
+        let status = match e.downcast_ref() {
+            Some(BitcaskError::KeyNotFound) => 404,
+            _ => 500,
+        };
+        text(status, format!("{e:#}\n"))
+    });
+    request.respond(response)
+}
+
+fn route(db: &BitcaskHandler, request: &mut Request, max_body_size: usize) -> Result<HttpResponse> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let method = request.method().clone();
+
+    if let Some(key) = path.strip_prefix("/keys/") {
+        let key: Vec<u8> = percent_decode_str(key).collect();
+        return match method {
+            Method::Get | Method::Head => get(db, request, &key),
+            Method::Put => put(db, request, &key, max_body_size),
+            Method::Delete => delete(db, request, &key),
+            _ => Ok(text(405, "Method not allowed\n")),
+        };
+    }
+    match (method, path) {
+        (Method::Get | Method::Head, "/keys") => {
+            let prefix = query_param(query, "prefix").unwrap_or_default();
+            let mut body = Vec::new();
+            for key in db.keys().filter(|key| key.starts_with(&prefix)) {
+                body.extend(percent_encode(&key, NON_ALPHANUMERIC).flat_map(str::bytes));
+                body.push(b'\n');
+            }
+            Ok(Response::from_data(body).with_header(header("Content-Type", "text/plain")))
+        }
+        (Method::Get | Method::Head, "/stats") => {
+            let stats = db.stats()?;
+            let json = format!(
+                "{{\"keys\":{},\"data_files\":{},\"data_size\":{}}}\n",
+                stats.keys, stats.data_files, stats.data_size
+            );
+            Ok(Response::from_string(json).with_header(header("Content-Type", "application/json")))
+        }
+        (Method::Post, "/admin/merge") => {
+            db.merge()?;
+            Ok(text(200, "OK\n"))
+        }
+        (Method::Post, "/admin/sync") => {
+            db.sync()?;
+            Ok(text(200, "OK\n"))
+        }
+        (_, "/keys" | "/stats" | "/admin/merge" | "/admin/sync") => Ok(text(405, "Method not allowed\n")),
+        _ => Ok(text(404, "Not found\n")),
+    }
+}
+
+fn get(db: &BitcaskHandler, request: &Request, key: &[u8]) -> Result<HttpResponse> {
+    let versioned = db.get_with_meta(key)?;
+    let etag = etag(&versioned);
+    if request_header(request, "If-None-Match").is_some_and(|tags| etag_matches(tags, &etag)) {
+        return Ok(Response::from_data(Vec::new())
+            .with_status_code(304)
+            .with_header(header("ETag", &etag)));
+    }
+    Ok(Response::from_data(versioned.value)
+        .with_header(header("Content-Type", "application/octet-stream"))
+        .with_header(header("ETag", &etag)))
+}
+
+fn put(db: &BitcaskHandler, request: &mut Request, key: &[u8], max_body_size: usize) -> Result<HttpResponse> {
+    let too_large = || Ok(text(413, format!("The value is larger than {max_body_size} bytes\n")));
+    if request.body_length().is_some_and(|length| length > max_body_size) {
+        return too_large();
+    }
+    // Without a Content-Length the body is read up to the limit
+    let mut value = Vec::new();
+    request
+        .as_reader()
+        .take(max_body_size as u64 + 1)
+        .read_to_end(&mut value)?;
+    if value.len() > max_body_size {
+        return too_large();
+    }
+
+    let precondition = match precondition(request) {
+        Ok(precondition) => precondition,
+        Err(message) => return Ok(text(400, message)),
+    };
+    let written = match precondition {
+        Precondition::None => {
+            db.put(key, &value)?;
+            true
+        }
+        Precondition::Absent => db.put_if_absent(key, &value)?,
+        Precondition::Match(if_match) => write_if_match(db, key, &if_match, |tx| tx.put(key, &value))?,
+    };
+    if !written {
+        return Ok(text(412, "Precondition failed\n"));
+    }
+    Ok(Response::from_data(Vec::new()).with_status_code(204))
+}
+
+fn delete(db: &BitcaskHandler, request: &Request, key: &[u8]) -> Result<HttpResponse> {
+    let deleted = match precondition(request) {
+        Ok(Precondition::None) => {
+            db.delete(key)?;
+            true
+        }
+        Ok(Precondition::Absent) => return Ok(text(400, "If-None-Match is not supported by DELETE\n")),
+        Ok(Precondition::Match(if_match)) => write_if_match(db, key, &if_match, |tx| tx.delete(key))?,
+        Err(message) => return Ok(text(400, message)),
+    };
+    if !deleted {
+        return Ok(text(412, "Precondition failed\n"));
+    }
+    Ok(Response::from_data(Vec::new()).with_status_code(204))
+}
+
+enum Precondition {
+    None,
+    Absent,
+    Match(IfMatch),
+}
+
+/// Condition of an `If-Match` header, see RFC 9110.
+enum IfMatch {
+    /// `*`, any current value.
+    Any,
+    /// Versions of the listed ETags, weak ones are left out as they never match.
+    Versions(Vec<u64>),
+}
+
+impl IfMatch {
+    fn parse(tags: &str) -> Self {
+        if tags.trim() == "*" {
+            return Self::Any;
+        }
+        // ETags this server didn't make can't match any entry, they're left out too
+        Self::Versions(
+            tags.split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.starts_with("W/"))
+                .filter_map(parse_etag_version)
+                .collect(),
+        )
+    }
+
+    /// Whether a key at `version`, `None` if it doesn't exist, satisfies the condition.
+    fn matches(&self, version: Option<u64>) -> bool {
+        match (self, version) {
+            (_, None) => false,
+            (Self::Any, Some(_)) => true,
+            (Self::Versions(versions), Some(version)) => versions.contains(&version),
+        }
+    }
+}
+
+/// Condition set on a write by `If-Match` or `If-None-Match: *`.
+fn precondition(request: &Request) -> Result<Precondition, &'static str> {
+    if let Some(tags) = request_header(request, "If-Match") {
+        return Ok(Precondition::Match(IfMatch::parse(tags)));
+    }
+    match request_header(request, "If-None-Match") {
+        Some("*") => Ok(Precondition::Absent),
+        Some(_) => Err("Only `If-None-Match: *` is supported on writes\n"),
+        None => Ok(Precondition::None),
+    }
+}
+
+/// Runs `write` in a transaction if the key satisfies `if_match`, returns whether it did.
+fn write_if_match(
+    db: &BitcaskHandler,
+    key: &[u8],
+    if_match: &IfMatch,
+    write: impl Fn(&mut Transaction),
+) -> Result<bool> {
+    retry_on_conflict(|| {
+        db.transaction(|tx| {
+            // The transaction fails to commit if the key changes after this read, so checking the
+            // version right after it is enough
+            tx.get(key)?;
+            if !if_match.matches(db.version(key)) {
+                return Ok(false);
+            }
+            write(tx);
+            Ok(true)
+        })
+    })
+}
+
+fn retry_on_conflict(mut f: impl FnMut() -> Result<bool>) -> Result<bool> {
+    loop {
+        match f() {
+            Err(e) if matches!(e.downcast_ref(), Some(BitcaskError::Conflict { .. })) => continue,
+            result => return result,
+        }
+    }
+}
+
+/// `"<timestamp>-<version>"`, in hex. The version alone is unique, the timestamp makes the tag meaningful
+/// to a reader and keeps tags distinct across datastores.
+fn etag(versioned: &VersionedValue) -> String {
+    format!("\"{:x}-{:x}\"", versioned.timestamp, versioned.version)
+}
+
+fn parse_etag_version(tag: &str) -> Option<u64> {
+    let tag = tag.trim_matches('"');
+    let (_, version) = tag.split_once('-')?;
+    u64::from_str_radix(version, 16).ok()
+}
+
+fn etag_matches(tags: &str, etag: &str) -> bool {
+    tags.split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+fn query_param(query: &str, name: &str) -> Option<Vec<u8>> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode_str(&value.replace('+', " ")).collect())
+}
+
+fn request_header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|header| header.value.as_str())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("Header names and values are ASCII")
+}
+
+fn text(status: u16, body: impl Into<String>) -> HttpResponse {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain"))
+}
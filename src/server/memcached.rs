@@ -0,0 +1,652 @@
+use std::{
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use anyhow::{Result, bail};
+
+use crate::{BitcaskError, BitcaskHandler, VersionedValue};
+
+// Same limits as memcached with its default settings
+const MAX_KEY_LENGTH: usize = 250;
+const MAX_VALUE_LENGTH: usize = 1024 * 1024;
+const MAX_LINE_LENGTH: u64 = 2048;
+// Expiration times above 30 days are absolute Unix times
+const MAX_RELATIVE_EXPTIME: i64 = 30 * 24 * 60 * 60;
+
+const REQUEST_MAGIC: u8 = 0x80;
+const RESPONSE_MAGIC: u8 = 0x81;
+const HEADER_LENGTH: usize = 24;
+
+/// Serves the datastore over the memcached text and binary protocols, detected from the first byte a client
+/// sends.
+///
+/// Supported commands are `get`, `gets`, `set`, `add`, `replace`, `delete`, `cas`, `touch`, `stats`,
+/// `version` and `quit`, plus their binary counterparts including the quiet and `GetK` variants, `GAT` and
+/// `Noop`. Client flags are stored with the value (see [`BitcaskHandler::put_with_flags`]) and expiration
+/// times become the entry's time-to-live. The CAS unique of a value is derived from its version.
+///
+/// Unlike memcached nothing is ever evicted, and `touch` rewrites the value with the new expiration time,
+/// which changes its CAS unique.
+///
+/// Each connection is served by its own thread, the function only returns if accepting a connection fails.
+///
+/// # Arguments
+///
+/// * `listener` - Bound listener to accept connections from.
+/// * `db` - Datastore to serve, it must be opened with `read_write` for writes to succeed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{net::TcpListener, path::Path, sync::Arc};
+/// use bitcask::{BitcaskHandler, Options, server};
+///
+/// let options = Options { read_write: true, ..Default::default() };
+/// let db = Arc::new(BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap());
+/// server::serve_memcached(TcpListener::bind("127.0.0.1:11211").unwrap(), db).unwrap();
+/// ```
+pub fn serve_memcached(listener: TcpListener, db: Arc<BitcaskHandler>) -> Result<()> {
+    let server = Arc::new(Server {
+        db,
+        started: Instant::now(),
+        counters: Counters::default(),
+    });
+    loop {
+        let (stream, _) = listener.accept()?;
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            server.counters.total_connections.fetch_add(1, Ordering::Relaxed);
+            server.counters.curr_connections.fetch_add(1, Ordering::Relaxed);
+            // A broken connection only concerns its client
+            let _ = server.serve(stream);
+            server.counters.curr_connections.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    curr_connections: AtomicU64,
+    total_connections: AtomicU64,
+    cmd_get: AtomicU64,
+    cmd_set: AtomicU64,
+    cmd_touch: AtomicU64,
+    get_hits: AtomicU64,
+    get_misses: AtomicU64,
+}
+
+struct Server {
+    db: Arc<BitcaskHandler>,
+    started: Instant,
+    counters: Counters,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum StoreMode {
+    Set,
+    Add,
+    Replace,
+}
+
+#[derive(PartialEq)]
+enum Outcome {
+    Done,
+    NotStored,
+    Exists,
+    NotFound,
+}
+
+impl Server {
+    fn serve(&self, stream: TcpStream) -> Result<()> {
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        let Some(&first) = reader.fill_buf()?.first() else {
+            return Ok(());
+        };
+        let result = if first == REQUEST_MAGIC {
+            self.serve_binary(&mut reader, &mut writer)
+        } else {
+            self.serve_text(&mut reader, &mut writer)
+        };
+        writer.flush()?;
+        result
+    }
+
+    // Operations shared by both protocols
+
+    fn get(&self, key: &[u8]) -> Result<Option<VersionedValue>> {
+        self.counters.cmd_get.fetch_add(1, Ordering::Relaxed);
+        let found = match self.db.get_with_meta(key) {
+            Ok(versioned) => Some(versioned),
+            Err(e) if is_not_found(&e) => None,
+            Err(e) => return Err(e),
+        };
+        let counter = if found.is_some() { &self.counters.get_hits } else { &self.counters.get_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(found)
+    }
+
+    /// Stores a value, returns the outcome and the CAS unique of the stored value.
+    fn store(
+        &self,
+        mode: StoreMode,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        exptime: i64,
+        cas: Option<u64>,
+    ) -> Result<(Outcome, u64)> {
+        self.counters.cmd_set.fetch_add(1, Ordering::Relaxed);
+        // Checked and written under the engine lock, no other write can come in between
+        self.db.write(|engine| {
+            let current = engine.version(key).map(cas_unique);
+            let outcome = match (mode, cas, current) {
+                (_, Some(_), None) => Outcome::NotFound,
+                (_, Some(cas), Some(current)) if cas != current => Outcome::Exists,
+                (StoreMode::Add, None, Some(_)) | (StoreMode::Replace, None, None) => Outcome::NotStored,
+                _ => Outcome::Done,
+            };
+            if outcome != Outcome::Done {
+                return Ok((outcome, 0));
+            }
+            let ttl = ttl(exptime, engine.now());
+            engine.put_with_flags(key, value, flags, ttl)?;
+            Ok((outcome, engine.version(key).map_or(0, cas_unique)))
+        })
+    }
+
+    fn delete(&self, key: &[u8], cas: Option<u64>) -> Result<Outcome> {
+        self.db.write(|engine| match engine.version(key).map(cas_unique) {
+            None => Ok(Outcome::NotFound),
+            Some(current) if cas.is_some_and(|cas| cas != current) => Ok(Outcome::Exists),
+            Some(_) => engine.delete(key).map(|()| Outcome::Done),
+        })
+    }
+
+    /// Sets a new expiration time, returns the value with its new version, or `None` if the key is missing.
+    fn touch(&self, key: &[u8], exptime: i64) -> Result<Option<VersionedValue>> {
+        self.counters.cmd_touch.fetch_add(1, Ordering::Relaxed);
+        self.db.write(|engine| {
+            let mut versioned = match engine.get_with_meta(key) {
+                Ok(versioned) => versioned,
+                Err(e) if is_not_found(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            let ttl = ttl(exptime, engine.now());
+            engine.put_with_flags(key, &versioned.value, versioned.flags, ttl)?;
+            versioned.version = engine.version(key).unwrap_or(versioned.version);
+            Ok(Some(versioned))
+        })
+    }
+
+    fn stats(&self) -> Result<Vec<(&'static str, String)>> {
+        let stats = self.db.stats()?;
+        let counter = |counter: &AtomicU64| counter.load(Ordering::Relaxed).to_string();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok(vec![
+            ("pid", std::process::id().to_string()),
+            ("uptime", self.started.elapsed().as_secs().to_string()),
+            ("time", now.as_secs().to_string()),
+            ("version", env!("CARGO_PKG_VERSION").to_string()),
+            ("curr_connections", counter(&self.counters.curr_connections)),
+            ("total_connections", counter(&self.counters.total_connections)),
+            ("cmd_get", counter(&self.counters.cmd_get)),
+            ("cmd_set", counter(&self.counters.cmd_set)),
+            ("cmd_touch", counter(&self.counters.cmd_touch)),
+            ("get_hits", counter(&self.counters.get_hits)),
+            ("get_misses", counter(&self.counters.get_misses)),
+            ("curr_items", stats.keys.to_string()),
+            ("bytes", stats.data_size.to_string()),
+            ("data_files", stats.data_files.to_string()),
+            ("evictions", "0".to_string()),
+        ])
+    }
+
+    // Text protocol
+
+    fn serve_text(&self, reader: &mut BufReader<TcpStream>, writer: &mut impl Write) -> Result<()> {
+        loop {
+            let mut line = Vec::new();
+            if reader.by_ref().take(MAX_LINE_LENGTH).read_until(b'\n', &mut line)? == 0 {
+                return Ok(());
+            }
+            if !line.ends_with(b"\n") {
+                writer.write_all(b"CLIENT_ERROR line too long\r\n")?;
+                return Ok(());
+            }
+            let args: Vec<&[u8]> = line.split(u8::is_ascii_whitespace).filter(|arg| !arg.is_empty()).collect();
+            let Some((command, args)) = args.split_first() else {
+                writer.write_all(b"ERROR\r\n")?;
+                if reader.buffer().is_empty() {
+                    writer.flush()?;
+                }
+                continue;
+            };
+            let (args, noreply) = match args.split_last() {
+                Some((&b"noreply", args)) => (args, true),
+                _ => (args, false),
+            };
+
+            let mut reply = Vec::new();
+            let keep_going = match self.text_command(command, args, reader, &mut reply) {
+                Ok(keep_going) => keep_going,
+                Err(e) => {
+                    reply = format!("SERVER_ERROR {}\r\n", format!("{e:#}").replace(['\r', '\n'], " ")).into_bytes();
+                    true
+                }
+            };
+            // Errors about the request itself are sent even with noreply, like memcached does
+            if !noreply || reply.starts_with(b"CLIENT_ERROR") || reply == b"ERROR\r\n" {
+                writer.write_all(&reply)?;
+            }
+            if !keep_going {
+                return Ok(());
+            }
+            if reader.buffer().is_empty() {
+                writer.flush()?;
+            }
+        }
+    }
+
+    /// Runs a text command, writes its reply to `reply` and returns false if the connection should be closed.
+    fn text_command(
+        &self,
+        command: &[u8],
+        args: &[&[u8]],
+        reader: &mut impl BufRead,
+        reply: &mut Vec<u8>,
+    ) -> Result<bool> {
+        match (command, args) {
+            (b"get" | b"gets", keys) if !keys.is_empty() => {
+                for key in keys {
+                    let Some(versioned) = self.get(key)? else {
+                        continue;
+                    };
+                    reply.extend_from_slice(b"VALUE ");
+                    reply.extend_from_slice(key);
+                    write!(reply, " {} {}", versioned.flags, versioned.value.len())?;
+                    if command == b"gets" {
+                        write!(reply, " {}", cas_unique(versioned.version))?;
+                    }
+                    reply.extend_from_slice(b"\r\n");
+                    reply.extend_from_slice(&versioned.value);
+                    reply.extend_from_slice(b"\r\n");
+                }
+                reply.extend_from_slice(b"END\r\n");
+            }
+            (b"set" | b"add" | b"replace" | b"cas", args) => {
+                let is_cas = command == b"cas";
+                let expected_args = if is_cas { 5 } else { 4 };
+                let parsed = (args.len() == expected_args)
+                    .then(|| {
+                        let flags = parse::<u32>(args[1])?;
+                        let exptime = parse::<i64>(args[2])?;
+                        let length = parse::<usize>(args[3])?;
+                        let cas = if is_cas { Some(parse::<u64>(args[4])?) } else { None };
+                        Some((flags, exptime, length, cas))
+                    })
+                    .flatten();
+                let Some((flags, exptime, length, cas)) = parsed else {
+                    reply.extend_from_slice(b"CLIENT_ERROR bad command line format\r\n");
+                    return Ok(true);
+                };
+                if length > MAX_VALUE_LENGTH {
+                    // The data block is skipped so the next command can be read
+                    io::copy(&mut reader.take(length as u64 + 2), &mut io::sink())?;
+                    reply.extend_from_slice(b"SERVER_ERROR object too large for cache\r\n");
+                    return Ok(true);
+                }
+                let mut value = vec![0; length + 2];
+                reader.read_exact(&mut value)?;
+                if !value.ends_with(b"\r\n") {
+                    reply.extend_from_slice(b"CLIENT_ERROR bad data chunk\r\n");
+                    return Ok(false);
+                }
+                value.truncate(length);
+                let key = args[0];
+                if key.len() > MAX_KEY_LENGTH {
+                    reply.extend_from_slice(b"CLIENT_ERROR key too long\r\n");
+                    return Ok(true);
+                }
+                let mode = match command {
+                    b"add" => StoreMode::Add,
+                    b"replace" => StoreMode::Replace,
+                    _ => StoreMode::Set,
+                };
+                let (outcome, _) = self.store(mode, key, &value, flags, exptime, cas)?;
+                reply.extend_from_slice(match outcome {
+                    Outcome::Done => b"STORED\r\n",
+                    Outcome::NotStored => b"NOT_STORED\r\n",
+                    Outcome::Exists => b"EXISTS\r\n",
+                    Outcome::NotFound => b"NOT_FOUND\r\n",
+                });
+            }
+            // memcached still accepts a legacy time argument of 0
+            (b"delete", [key] | [key, b"0"]) => {
+                reply.extend_from_slice(match self.delete(key, None)? {
+                    Outcome::Done => b"DELETED\r\n",
+                    _ => b"NOT_FOUND\r\n",
+                });
+            }
+            (b"touch", [key, exptime]) => {
+                let Some(exptime) = parse::<i64>(exptime) else {
+                    reply.extend_from_slice(b"CLIENT_ERROR invalid exptime argument\r\n");
+                    return Ok(true);
+                };
+                reply.extend_from_slice(match self.touch(key, exptime)? {
+                    Some(_) => b"TOUCHED\r\n",
+                    None => b"NOT_FOUND\r\n",
+                });
+            }
+            (b"stats", []) => {
+                for (name, value) in self.stats()? {
+                    write!(reply, "STAT {name} {value}\r\n")?;
+                }
+                reply.extend_from_slice(b"END\r\n");
+            }
+            (b"version", []) => write!(reply, "VERSION {}\r\n", env!("CARGO_PKG_VERSION"))?,
+            (b"quit", []) => return Ok(false),
+            _ => reply.extend_from_slice(b"ERROR\r\n"),
+        }
+        Ok(true)
+    }
+
+    // Binary protocol
+
+    fn serve_binary(&self, reader: &mut BufReader<TcpStream>, writer: &mut impl Write) -> Result<()> {
+        loop {
+            let mut header = [0; HEADER_LENGTH];
+            match reader.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+            let request = Request::parse(&header)?;
+            if request.body_length > MAX_KEY_LENGTH + 8 + MAX_VALUE_LENGTH {
+                io::copy(&mut reader.take(request.body_length as u64), &mut io::sink())?;
+                Response::new(&request, STATUS_TOO_LARGE).message("Too large").write_to(writer)?;
+                continue;
+            }
+            let mut body = vec![0; request.body_length];
+            reader.read_exact(&mut body)?;
+            let response = self.binary_command(&request, &body).unwrap_or_else(|e| {
+                Some(Response::new(&request, STATUS_INTERNAL_ERROR).message(&format!("{e:#}")))
+            });
+            if let Some(response) = response {
+                response.write_to(writer)?;
+            }
+            if matches!(request.opcode, OP_QUIT | OP_QUITQ) {
+                return Ok(());
+            }
+            if reader.buffer().is_empty() {
+                writer.flush()?;
+            }
+        }
+    }
+
+    /// Runs a binary command, `None` when a quiet command has nothing to report.
+    fn binary_command(&self, request: &Request, body: &[u8]) -> Result<Option<Response>> {
+        let Some((extras, key, value)) = request.split_body(body) else {
+            return Ok(Some(Response::new(request, STATUS_INVALID_ARGUMENTS).message("Invalid arguments")));
+        };
+        let quiet = matches!(
+            request.opcode,
+            OP_GETQ | OP_GETKQ | OP_SETQ | OP_ADDQ | OP_REPLACEQ | OP_DELETEQ | OP_QUITQ | OP_GATQ
+        );
+        let invalid = || Ok(Some(Response::new(request, STATUS_INVALID_ARGUMENTS).message("Invalid arguments")));
+        if key.len() > MAX_KEY_LENGTH {
+            return invalid();
+        }
+
+        let response = match request.opcode {
+            OP_GET | OP_GETQ | OP_GETK | OP_GETKQ => {
+                if !extras.is_empty() || !value.is_empty() {
+                    return invalid();
+                }
+                let with_key = matches!(request.opcode, OP_GETK | OP_GETKQ);
+                match self.get(key)? {
+                    Some(versioned) => found(request, versioned, with_key.then_some(key)),
+                    None if quiet => return Ok(None),
+                    None => not_found(request),
+                }
+            }
+            OP_SET | OP_SETQ | OP_ADD | OP_ADDQ | OP_REPLACE | OP_REPLACEQ => {
+                let [flags @ .., e0, e1, e2, e3] = extras else {
+                    return invalid();
+                };
+                let Ok(flags) = <[u8; 4]>::try_from(flags) else {
+                    return invalid();
+                };
+                let exptime = u32::from_be_bytes([*e0, *e1, *e2, *e3]);
+                let mode = match request.opcode {
+                    OP_ADD | OP_ADDQ => StoreMode::Add,
+                    OP_REPLACE | OP_REPLACEQ => StoreMode::Replace,
+                    _ => StoreMode::Set,
+                };
+                let cas = (request.cas != 0).then_some(request.cas);
+                let (outcome, cas) =
+                    self.store(mode, key, value, u32::from_be_bytes(flags), exptime.into(), cas)?;
+                match outcome {
+                    Outcome::Done if quiet => return Ok(None),
+                    Outcome::Done => Response::new(request, STATUS_OK).cas(cas),
+                    // Binary clients expect the reason an add or replace failed
+                    Outcome::NotStored if mode == StoreMode::Add => exists(request),
+                    Outcome::NotStored | Outcome::NotFound => not_found(request),
+                    Outcome::Exists => exists(request),
+                }
+            }
+            OP_DELETE | OP_DELETEQ => {
+                if !extras.is_empty() || !value.is_empty() {
+                    return invalid();
+                }
+                let cas = (request.cas != 0).then_some(request.cas);
+                match self.delete(key, cas)? {
+                    Outcome::Done if quiet => return Ok(None),
+                    Outcome::Done => Response::new(request, STATUS_OK),
+                    Outcome::Exists => exists(request),
+                    _ => not_found(request),
+                }
+            }
+            OP_TOUCH | OP_GAT | OP_GATQ => {
+                let Ok(exptime) = <[u8; 4]>::try_from(extras) else {
+                    return invalid();
+                };
+                let exptime = u32::from_be_bytes(exptime);
+                match self.touch(key, exptime.into())? {
+                    Some(_) if request.opcode == OP_TOUCH => Response::new(request, STATUS_OK),
+                    Some(versioned) => found(request, versioned, None),
+                    None if quiet => return Ok(None),
+                    None => not_found(request),
+                }
+            }
+            OP_NOOP => Response::new(request, STATUS_OK),
+            OP_VERSION => Response::new(request, STATUS_OK).message(env!("CARGO_PKG_VERSION")),
+            OP_QUIT if !quiet => Response::new(request, STATUS_OK),
+            OP_QUIT | OP_QUITQ => return Ok(None),
+            OP_STAT => {
+                // Every stat is a response of its own, the last one has an empty key
+                let mut responses = Vec::new();
+                for (name, value) in self.stats()? {
+                    let mut response = Response::new(request, STATUS_OK);
+                    response.key = name.as_bytes().to_vec();
+                    response.value = value.into_bytes();
+                    response.write_to(&mut responses)?;
+                }
+                Response::new(request, STATUS_OK).prefixed(responses)
+            }
+            _ => Response::new(request, STATUS_UNKNOWN_COMMAND).message("Unknown command"),
+        };
+        Ok(Some(response))
+    }
+}
+
+// Binary protocol opcodes
+const OP_GET: u8 = 0x00;
+const OP_SET: u8 = 0x01;
+const OP_ADD: u8 = 0x02;
+const OP_REPLACE: u8 = 0x03;
+const OP_DELETE: u8 = 0x04;
+const OP_QUIT: u8 = 0x07;
+const OP_GETQ: u8 = 0x09;
+const OP_NOOP: u8 = 0x0a;
+const OP_VERSION: u8 = 0x0b;
+const OP_GETK: u8 = 0x0c;
+const OP_GETKQ: u8 = 0x0d;
+const OP_STAT: u8 = 0x10;
+const OP_SETQ: u8 = 0x11;
+const OP_ADDQ: u8 = 0x12;
+const OP_REPLACEQ: u8 = 0x13;
+const OP_DELETEQ: u8 = 0x14;
+const OP_QUITQ: u8 = 0x17;
+const OP_TOUCH: u8 = 0x1c;
+const OP_GAT: u8 = 0x1d;
+const OP_GATQ: u8 = 0x1e;
+
+// Binary protocol response statuses
+const STATUS_OK: u16 = 0x0000;
+const STATUS_KEY_NOT_FOUND: u16 = 0x0001;
+const STATUS_KEY_EXISTS: u16 = 0x0002;
+const STATUS_TOO_LARGE: u16 = 0x0003;
+const STATUS_INVALID_ARGUMENTS: u16 = 0x0004;
+const STATUS_UNKNOWN_COMMAND: u16 = 0x0081;
+const STATUS_INTERNAL_ERROR: u16 = 0x0084;
+
+struct Request {
+    opcode: u8,
+    key_length: usize,
+    extras_length: usize,
+    body_length: usize,
+    opaque: u32,
+    cas: u64,
+}
+
+impl Request {
+    fn parse(header: &[u8; HEADER_LENGTH]) -> Result<Self> {
+        if header[0] != REQUEST_MAGIC {
+            bail!("Invalid magic byte {:#04x} in binary request", header[0]);
+        }
+        let u16_at = |at: usize| u16::from_be_bytes([header[at], header[at + 1]]);
+        let u32_at = |at: usize| u32::from_be_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]]);
+        Ok(Self {
+            opcode: header[1],
+            key_length: u16_at(2).into(),
+            extras_length: header[4].into(),
+            body_length: u32_at(8) as usize,
+            opaque: u32_at(12),
+            cas: u64::from_be_bytes(header[16..24].try_into()?),
+        })
+    }
+
+    /// Splits the body into extras, key and value, `None` if the lengths don't add up.
+    fn split_body<'a>(&self, body: &'a [u8]) -> Option<(&'a [u8], &'a [u8], &'a [u8])> {
+        let (extras, rest) = body.split_at_checked(self.extras_length)?;
+        let (key, value) = rest.split_at_checked(self.key_length)?;
+        Some((extras, key, value))
+    }
+}
+
+struct Response {
+    opcode: u8,
+    status: u16,
+    opaque: u32,
+    cas: u64,
+    extras: Vec<u8>,
+    key: Vec<u8>,
+    value: Vec<u8>,
+    prefix: Vec<u8>, // Responses sent before this one, for commands answered with several packets
+}
+
+impl Response {
+    fn new(request: &Request, status: u16) -> Self {
+        Self {
+            opcode: request.opcode,
+            status,
+            opaque: request.opaque,
+            cas: 0,
+            extras: Vec::new(),
+            key: Vec::new(),
+            value: Vec::new(),
+            prefix: Vec::new(),
+        }
+    }
+
+    fn message(mut self, message: &str) -> Self {
+        self.value = message.as_bytes().to_vec();
+        self
+    }
+
+    fn cas(mut self, cas: u64) -> Self {
+        self.cas = cas;
+        self
+    }
+
+    fn prefixed(mut self, prefix: Vec<u8>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_all(&self.prefix)?;
+        let body_length = self.extras.len() + self.key.len() + self.value.len();
+        writer.write_all(&[RESPONSE_MAGIC, self.opcode])?;
+        writer.write_all(&(self.key.len() as u16).to_be_bytes())?;
+        writer.write_all(&[self.extras.len() as u8, 0])?; // Extras length and raw bytes data type
+        writer.write_all(&self.status.to_be_bytes())?;
+        writer.write_all(&(body_length as u32).to_be_bytes())?;
+        writer.write_all(&self.opaque.to_be_bytes())?;
+        writer.write_all(&self.cas.to_be_bytes())?;
+        writer.write_all(&self.extras)?;
+        writer.write_all(&self.key)?;
+        writer.write_all(&self.value)?;
+        Ok(())
+    }
+}
+
+fn found(request: &Request, versioned: VersionedValue, key: Option<&[u8]>) -> Response {
+    let mut response = Response::new(request, STATUS_OK).cas(cas_unique(versioned.version));
+    response.extras = versioned.flags.to_be_bytes().to_vec();
+    response.key = key.unwrap_or_default().to_vec();
+    response.value = versioned.value;
+    response
+}
+
+fn not_found(request: &Request) -> Response {
+    Response::new(request, STATUS_KEY_NOT_FOUND).message("Not found")
+}
+
+fn exists(request: &Request) -> Response {
+    Response::new(request, STATUS_KEY_EXISTS).message("Data exists for key.")
+}
+
+/// CAS unique of a value, versions start at 0 which memcached clients take as no CAS.
+fn cas_unique(version: u64) -> u64 {
+    version + 1
+}
+
+/// Time-to-live of a memcached expiration time given the current time in Unix millis.
+///
+/// 0 never expires, up to 30 days it's a number of seconds from now, beyond it's a Unix time in seconds.
+/// A negative time or one in the past expires the value right away.
+fn ttl(exptime: i64, now: u64) -> Option<Duration> {
+    match exptime {
+        0 => None,
+        ..0 => Some(Duration::ZERO),
+        1..=MAX_RELATIVE_EXPTIME => Some(Duration::from_secs(exptime as u64)),
+        _ => Some(Duration::from_millis((exptime as u64).saturating_mul(1000).saturating_sub(now))),
+    }
+}
+
+fn parse<T: std::str::FromStr>(bytes: &[u8]) -> Option<T> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+fn is_not_found(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref(), Some(BitcaskError::KeyNotFound))
+}
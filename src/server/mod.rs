@@ -0,0 +1,18 @@
+//! Network front ends serving a datastore to other processes.
+//!
+//! Each server takes a bound listener and a shared handler, and serves clients concurrently until the
+//! listener fails. Several servers can share the same handler.
+
+#[cfg(feature = "grpc")]
+mod grpc;
+mod http;
+mod memcached;
+mod redis;
+mod replication;
+
+#[cfg(feature = "grpc")]
+pub use grpc::serve_grpc;
+pub use http::{HttpOptions, serve_http, serve_http_with_options};
+pub use memcached::serve_memcached;
+pub use redis::serve_redis;
+pub use replication::serve_replication;
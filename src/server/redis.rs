@@ -0,0 +1,458 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, BufWriter, Write},
+    iter::Peekable,
+    net::{TcpListener, TcpStream},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+use anyhow::{Result, bail};
+
+use crate::{
+    BitcaskError, BitcaskHandler, Keys, WriteBatch,
+    resp::{self, Frame},
+};
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Scans a connection keeps going at once, starting another one drops the oldest.
+const MAX_OPEN_SCANS: usize = 16;
+
+/// Serves the datastore over the Redis protocol (RESP2, and RESP3 after `HELLO 3`).
+///
+/// Supported commands are `GET`, `SET` (with `EX`, `PX`, `NX` and `XX`), `DEL`, `EXISTS`, `KEYS`, `SCAN`,
+/// `MGET`, `MSET`, `DBSIZE`, `PING`, `ECHO`, `HELLO`, `SELECT 0`, `INFO`, `QUIT`, `SAVE` which runs
+/// [`BitcaskHandler::sync`], `BGREWRITEAOF` which starts a [`BitcaskHandler::merge`] in the background and
+/// reports its outcome in `INFO`, `BITCASK.MERGE` which replies once the merge is done, and `REPLICAOF NO ONE`
+/// which promotes a follower with [`BitcaskHandler::promote`]. Pipelined commands are answered in order.
+///
+/// Each connection is served by its own thread, the function only returns if accepting a connection fails.
+///
+/// # Arguments
+///
+/// * `listener` - Bound listener to accept connections from.
+/// * `db` - Datastore to serve, it must be opened with `read_write` for writes to succeed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{net::TcpListener, path::Path, sync::Arc};
+/// use bitcask::{BitcaskHandler, Options, server};
+///
+/// let options = Options { read_write: true, ..Default::default() };
+/// let db = Arc::new(BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap());
+/// server::serve_redis(TcpListener::bind("127.0.0.1:6379").unwrap(), db).unwrap();
+/// ```
+pub fn serve_redis(listener: TcpListener, db: Arc<BitcaskHandler>) -> Result<()> {
+    let background_merge = Arc::default();
+    loop {
+        let (stream, _) = listener.accept()?;
+        let db = Arc::clone(&db);
+        let background_merge = Arc::clone(&background_merge);
+        thread::spawn(move || {
+            // A broken connection only concerns its client
+            let _ = Connection::new(db, background_merge).serve(stream);
+        });
+    }
+}
+
+/// State of the merges started by `BGREWRITEAOF`, reported by `INFO`.
+#[derive(Default)]
+struct BackgroundMerge {
+    in_progress: bool,
+    last_error: Option<String>,
+}
+
+struct Connection {
+    db: Arc<BitcaskHandler>,
+    background_merge: Arc<Mutex<BackgroundMerge>>,
+    id: u64,
+    protocol: u8,
+    /// Key snapshots of the scans in progress, by cursor
+    scans: BTreeMap<u64, Peekable<Keys>>,
+    next_cursor: u64,
+}
+
+impl Connection {
+    fn new(db: Arc<BitcaskHandler>, background_merge: Arc<Mutex<BackgroundMerge>>) -> Self {
+        let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+        Self {
+            db,
+            background_merge,
+            id,
+            protocol: 2,
+            scans: BTreeMap::new(),
+            next_cursor: 1,
+        }
+    }
+
+    fn serve(&mut self, stream: TcpStream) -> Result<()> {
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        loop {
+            let args = match resp::read_command(&mut reader) {
+                Ok(Some(args)) => args,
+                Ok(None) => break,
+                Err(e) => {
+                    // Like Redis, the connection is closed after a protocol error as it can't resync
+                    Frame::error(format!("ERR {e}")).write_to(&mut writer, self.protocol)?;
+                    break;
+                }
+            };
+            let quit = args.first().is_some_and(|command| command.eq_ignore_ascii_case(b"quit"));
+            let reply = self.execute(&args).unwrap_or_else(|e| Frame::error(error_message(&e)));
+            reply.write_to(&mut writer, self.protocol)?;
+            if quit {
+                break;
+            }
+            // Replies to pipelined commands are sent together
+            if reader.buffer().is_empty() {
+                writer.flush()?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn execute(&mut self, args: &[Vec<u8>]) -> Result<Frame> {
+        let Some((command, args)) = args.split_first() else {
+            bail!("ERR empty command");
+        };
+        let command = String::from_utf8_lossy(command).to_lowercase();
+        let db = &self.db;
+        let reply = match (command.as_str(), args) {
+            ("ping", []) => Frame::Simple("PONG".to_string()),
+            ("ping" | "echo", [message]) => Frame::bulk(message.clone()),
+            ("hello", args) => self.hello(args)?,
+            ("quit", _) => Frame::ok(),
+            ("select", [index]) if index.as_slice() == b"0" => Frame::ok(),
+            ("select", [_]) => bail!("ERR DB index is out of range"),
+            ("dbsize", []) => Frame::Integer(db.len() as i64),
+            ("get", [key]) => match db.get(key) {
+                Ok(value) => Frame::Bulk(value),
+                Err(e) if is_not_found(&e) => Frame::Null,
+                Err(e) => return Err(e),
+            },
+            ("set", [key, value, options @ ..]) => self.set(key, value, options)?,
+            ("del", keys) if !keys.is_empty() => {
+                let mut deleted = 0;
+                for key in keys {
+                    match db.delete(key) {
+                        Ok(()) => deleted += 1,
+                        Err(e) if is_not_found(&e) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Frame::Integer(deleted)
+            }
+            ("exists", keys) if !keys.is_empty() => {
+                Frame::Integer(keys.iter().filter(|key| db.contains_key(key)).count() as i64)
+            }
+            ("mget", keys) if !keys.is_empty() => {
+                Frame::Array(db.get_many(keys)?.into_iter().map(Frame::optional_bulk).collect())
+            }
+            ("mset", pairs) if !pairs.is_empty() && pairs.len().is_multiple_of(2) => {
+                // Like Redis, the keys are set atomically
+                let mut batch = WriteBatch::new();
+                for pair in pairs.chunks_exact(2) {
+                    batch.put(&pair[0], &pair[1]);
+                }
+                db.write_batch(batch)?;
+                Frame::ok()
+            }
+            ("keys", [pattern]) => Frame::Array(
+                db.keys()
+                    .filter(|key| glob_match(pattern, key))
+                    .map(Frame::Bulk)
+                    .collect(),
+            ),
+            ("scan", [cursor, options @ ..]) => self.scan(cursor, options)?,
+            ("info", sections) if sections.len() <= 1 => Frame::bulk(self.info()?),
+            ("bgrewriteaof", []) => self.background_merge()?,
+            ("save", []) => {
+                db.sync()?;
+                Frame::ok()
+            }
+            ("bitcask.merge", []) => {
+                db.merge()?;
+                Frame::ok()
+            }
+            ("replicaof", [host, port]) if host.eq_ignore_ascii_case(b"no") && port.eq_ignore_ascii_case(b"one") => {
+                db.promote()?;
+                Frame::ok()
+            }
+            ("replicaof", [_, _]) => bail!("ERR only REPLICAOF NO ONE is supported"),
+            ("command", _) => Frame::Array(Vec::new()), // Asked by redis-cli on startup
+            (
+                "ping" | "echo" | "select" | "dbsize" | "get" | "set" | "del" | "exists" | "mget" | "mset" | "keys"
+                | "scan" | "info" | "save" | "bgrewriteaof" | "bitcask.merge" | "replicaof",
+                _,
+            ) => bail!("ERR wrong number of arguments for '{command}' command"),
+            _ => bail!("ERR unknown command '{command}'"),
+        };
+        Ok(reply)
+    }
+
+    /// `HELLO [protover]`, switches the protocol version and describes the server.
+    fn hello(&mut self, args: &[Vec<u8>]) -> Result<Frame> {
+        if args.len() > 1 {
+            bail!("ERR AUTH and SETNAME are not supported");
+        }
+        if let Some(version) = args.first() {
+            self.protocol = match version.as_slice() {
+                b"2" => 2,
+                b"3" => 3,
+                _ => bail!("NOPROTO unsupported protocol version"),
+            };
+        }
+        let field = |name: &str, value: Frame| (Frame::bulk(name), value);
+        Ok(Frame::Map(vec![
+            field("server", Frame::bulk("bitcask")),
+            field("version", Frame::bulk(env!("CARGO_PKG_VERSION"))),
+            field("proto", Frame::Integer(self.protocol.into())),
+            field("id", Frame::Integer(self.id as i64)),
+            field("mode", Frame::bulk("standalone")),
+            field("role", Frame::bulk("master")),
+            field("modules", Frame::Array(Vec::new())),
+        ]))
+    }
+
+    /// `SET key value [EX seconds | PX milliseconds] [NX | XX]`
+    fn set(&self, key: &[u8], value: &[u8], options: &[Vec<u8>]) -> Result<Frame> {
+        let mut ttl = None;
+        let mut only_if = None;
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            match option.to_ascii_lowercase().as_slice() {
+                unit @ (b"ex" | b"px") if ttl.is_none() => {
+                    let amount = options.next().and_then(|amount| parse_integer(amount));
+                    let Some(amount) = amount.filter(|&amount| amount > 0) else {
+                        bail!("ERR invalid expire time in 'set' command");
+                    };
+                    ttl = Some(if unit == b"ex" {
+                        Duration::from_secs(amount)
+                    } else {
+                        Duration::from_millis(amount)
+                    });
+                }
+                condition @ (b"nx" | b"xx") if only_if.is_none() => only_if = Some(condition == b"xx"),
+                _ => bail!("ERR syntax error"),
+            }
+        }
+
+        let Some(must_exist) = only_if else {
+            match ttl {
+                Some(ttl) => self.db.put_with_ttl(key, value, ttl)?,
+                None => self.db.put(key, value)?,
+            }
+            return Ok(Frame::ok());
+        };
+        // The existence check and the write commit together, a concurrent write makes the transaction retry
+        loop {
+            let result = self.db.transaction(|tx| {
+                if tx.get(key)?.is_some() != must_exist {
+                    return Ok(false);
+                }
+                match ttl {
+                    Some(ttl) => tx.put_with_ttl(key, value, ttl),
+                    None => tx.put(key, value),
+                }
+                Ok(true)
+            });
+            match result {
+                Ok(true) => return Ok(Frame::ok()),
+                Ok(false) => return Ok(Frame::Null),
+                Err(e) if matches!(e.downcast_ref(), Some(BitcaskError::Conflict { .. })) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// `SCAN cursor [MATCH pattern] [COUNT count]`
+    ///
+    /// Cursor 0 starts a scan over a snapshot of the keys, the returned cursor resumes it where the page ended.
+    /// A full scan returns every key present when it started exactly once, keys written since then aren't
+    /// returned. Unlike Redis, cursors are only valid on the connection that started the scan, and only the
+    /// last [`MAX_OPEN_SCANS`] unfinished scans can be resumed.
+    fn scan(&mut self, cursor: &[u8], options: &[Vec<u8>]) -> Result<Frame> {
+        let Some(cursor) = parse_integer(cursor) else {
+            bail!("ERR invalid cursor");
+        };
+        let mut pattern = None;
+        let mut count = 10;
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            match (option.to_ascii_lowercase().as_slice(), options.next()) {
+                (b"match", Some(value)) => pattern = Some(value),
+                (b"count", Some(value)) => match parse_integer(value) {
+                    Some(value) if value > 0 => count = value,
+                    _ => bail!("ERR value is not an integer or out of range"),
+                },
+                _ => bail!("ERR syntax error"),
+            }
+        }
+
+        let mut keys = match cursor {
+            0 => self.db.keys().peekable(),
+            cursor => match self.scans.remove(&cursor) {
+                Some(keys) => keys,
+                None => bail!("ERR invalid cursor"),
+            },
+        };
+        let page: Vec<Vec<u8>> = keys.by_ref().take(count as usize).collect();
+        let next_cursor = match keys.peek() {
+            Some(_) => {
+                let next_cursor = self.next_cursor;
+                self.next_cursor += 1;
+                if self.scans.len() == MAX_OPEN_SCANS {
+                    self.scans.pop_first();
+                }
+                self.scans.insert(next_cursor, keys);
+                next_cursor
+            }
+            None => 0,
+        };
+        let matching = page
+            .into_iter()
+            .filter(|key| pattern.is_none_or(|pattern| glob_match(pattern, key)))
+            .map(Frame::Bulk)
+            .collect();
+        Ok(Frame::Array(vec![Frame::bulk(next_cursor.to_string()), Frame::Array(matching)]))
+    }
+
+    /// `BGREWRITEAOF`, merges in another thread, `INFO` tells when it's done and if it failed.
+    fn background_merge(&self) -> Result<Frame> {
+        let mut state = self.background_merge.lock().unwrap();
+        if state.in_progress {
+            bail!("ERR Background append only file rewriting already in progress");
+        }
+        state.in_progress = true;
+        let db = Arc::clone(&self.db);
+        let background_merge = Arc::clone(&self.background_merge);
+        thread::spawn(move || {
+            let result = db.merge();
+            let mut state = background_merge.lock().unwrap();
+            state.in_progress = false;
+            state.last_error = result.err().map(|e| format!("{e:#}").replace(['\r', '\n'], " "));
+        });
+        Ok(Frame::Simple("Background append only file rewriting started".to_string()))
+    }
+
+    fn info(&self) -> Result<String> {
+        let stats = self.db.stats()?;
+        let (merge_in_progress, merge_status) = {
+            let state = self.background_merge.lock().unwrap();
+            let status = match &state.last_error {
+                Some(error) => format!("err\r\nbitcask_last_merge_error:{error}"),
+                None => "ok".to_string(),
+            };
+            (u8::from(state.in_progress), status)
+        };
+        Ok(format!(
+            "# Server\r\nredis_version:7.0.0\r\nbitcask_version:{}\r\nredis_mode:standalone\r\n\r\n\
+             # Persistence\r\naof_rewrite_in_progress:{merge_in_progress}\r\n\
+             aof_last_bgrewrite_status:{merge_status}\r\n\r\n\
+             # Bitcask\r\ndata_files:{}\r\ndata_size:{}\r\n\r\n\
+             # Keyspace\r\ndb0:keys={},expires={}\r\n",
+            env!("CARGO_PKG_VERSION"),
+            stats.data_files,
+            stats.data_size,
+            stats.keys,
+            stats.expiring_keys,
+        ))
+    }
+}
+
+fn is_not_found(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref(), Some(BitcaskError::KeyNotFound))
+}
+
+/// Error reply for a failed command, errors raised here already start with their Redis error code.
+fn error_message(error: &anyhow::Error) -> String {
+    let message = format!("{error:#}").replace(['\r', '\n'], " ");
+    if message.starts_with("ERR ") || message.starts_with("NOPROTO ") {
+        message
+    } else {
+        format!("ERR {message}")
+    }
+}
+
+fn parse_integer(bytes: &[u8]) -> Option<u64> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Matches a key against a Redis glob-style pattern: `*`, `?`, `[abc]`, `[^a-z]` and `\` escapes.
+///
+/// On a mismatch only the last `*` is retried one byte further, which keeps matching in O(pattern * key).
+fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut p, mut k) = (0, 0);
+    // Pattern position after the last `*`, and the key position it's currently matched up to
+    let mut backtrack = None;
+    while k < key.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            backtrack = Some((p, k));
+            continue;
+        }
+        if let Some(next) = match_one(pattern, p, key[k]) {
+            p = next;
+            k += 1;
+            continue;
+        }
+        match backtrack {
+            Some((star_p, star_k)) => {
+                p = star_p;
+                k = star_k + 1;
+                backtrack = Some((star_p, k));
+            }
+            None => return false,
+        }
+    }
+    pattern[p.min(pattern.len())..].iter().all(|&byte| byte == b'*')
+}
+
+/// Matches one byte against the pattern element at `p` other than `*`, returns the position of the next one.
+fn match_one(pattern: &[u8], p: usize, byte: u8) -> Option<usize> {
+    match &pattern[p.min(pattern.len())..] {
+        [] => None,
+        [b'?', ..] => Some(p + 1),
+        [b'[', rest @ ..] => {
+            let (negate, mut class) = match rest.split_first() {
+                Some((b'^', class)) => (true, class),
+                _ => (false, rest),
+            };
+            let mut matched = false;
+            loop {
+                match class {
+                    // An unterminated class matches like Redis does, up to the end of the pattern
+                    [] => break,
+                    [b']', tail @ ..] => {
+                        class = tail;
+                        break;
+                    }
+                    [b'\\', escaped, tail @ ..] => {
+                        matched |= byte == *escaped;
+                        class = tail;
+                    }
+                    [low, b'-', high, tail @ ..] if *high != b']' => {
+                        let (low, high) = if low <= high { (*low, *high) } else { (*high, *low) };
+                        matched |= (low..=high).contains(&byte);
+                        class = tail;
+                    }
+                    [other, tail @ ..] => {
+                        matched |= byte == *other;
+                        class = tail;
+                    }
+                }
+            }
+            (matched != negate).then_some(pattern.len() - class.len())
+        }
+        [b'\\', escaped, ..] => (byte == *escaped).then_some(p + 2),
+        [literal, ..] => (byte == *literal).then_some(p + 1),
+    }
+}
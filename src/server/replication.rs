@@ -0,0 +1,151 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use anyhow::{Result, bail};
+use bincode::{config, decode_from_std_read, encode_into_std_write};
+
+use crate::{
+    BitcaskHandler,
+    files::WorkingFile,
+    replication::{CHUNK_SIZE, Follow, MAX_FOLLOW_SIZE, Shipment},
+};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Serves the data files of the datastore to [`crate::replication::Follower`]s, making this process their leader.
+///
+/// Each follower first receives the data files it's missing, then the records appended to the working file as
+/// soon as they're committed. Merged data files are shipped once the merge is done.
+///
+/// Each follower is served by its own thread, the function only returns if accepting a connection fails.
+///
+/// # Arguments
+///
+/// * `listener` - Bound listener to accept followers from.
+/// * `db` - Datastore to replicate, usually opened with `read_write`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{net::TcpListener, path::Path, sync::Arc};
+/// use bitcask::{BitcaskHandler, Options, server};
+///
+/// let options = Options { read_write: true, ..Default::default() };
+/// let db = Arc::new(BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap());
+/// server::serve_replication(TcpListener::bind("127.0.0.1:7379").unwrap(), db).unwrap();
+/// ```
+pub fn serve_replication(listener: TcpListener, db: Arc<BitcaskHandler>) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept()?;
+        let db = Arc::clone(&db);
+        thread::spawn(move || {
+            // The follower connects again if anything goes wrong
+            let _ = ship(stream, &db);
+        });
+    }
+}
+
+/// Ships data files to a follower until the connection breaks.
+fn ship(stream: TcpStream, db: &BitcaskHandler) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let config = config::standard().with_limit::<MAX_FOLLOW_SIZE>();
+    let Follow { file_id, offset } = decode_from_std_read(&mut BufReader::new(stream.try_clone()?), config)?;
+    let mut writer = BufWriter::new(stream);
+    // The follower has the data files up to this file id and offset
+    let (mut file_id, mut offset) = (usize::try_from(file_id)?, usize::try_from(offset)?);
+    let mut shipped_ids: Option<Vec<u64>> = None;
+    let mut last_shipment = Instant::now();
+
+    loop {
+        // Records committed after this are shipped by the next round
+        let log_end = db.group_commit.log_end();
+        let data_files = db.engine().data_files()?;
+        let mut ids = Vec::with_capacity(data_files.len());
+        let mut has_shipped = false;
+        let mut is_complete = true;
+        for (file_path, size) in data_files {
+            let Some(id) = file_path
+                .file_name()
+                .and_then(|s| s.to_str())
+                .and_then(WorkingFile::parse_file_id)
+            else {
+                continue;
+            };
+            ids.push(id.try_into()?);
+            if id < file_id {
+                continue;
+            }
+            if id > file_id {
+                (file_id, offset) = (id, 0);
+            }
+            if size < offset {
+                bail!("The follower has more of data file {id} than the leader");
+            }
+            if size == offset {
+                continue;
+            }
+            match ship_file(&mut writer, &file_path, id, offset, size) {
+                Ok(()) => {}
+                // Removed by a merge since it was listed, the merged files are listed next time
+                Err(e) if e.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == ErrorKind::NotFound) => {
+                    is_complete = false;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+            offset = size;
+            has_shipped = true;
+        }
+        let is_follower_ahead = match ids.last() {
+            Some(newest_id) => u64::try_from(file_id)? > *newest_id,
+            None => (file_id, offset) != (0, 0),
+        };
+        if is_complete && is_follower_ahead {
+            // Rather than having the follower remove its data files, it might be the only copy left
+            bail!("The follower has data files newer than the leader");
+        }
+
+        if is_complete && shipped_ids.as_ref() != Some(&ids) {
+            encode_into_std_write(Shipment::DataFiles { ids: ids.clone() }, &mut writer, config::standard())?;
+            shipped_ids = Some(ids);
+            has_shipped = true;
+        }
+        if !has_shipped && last_shipment.elapsed() >= HEARTBEAT_INTERVAL {
+            encode_into_std_write(Shipment::Heartbeat, &mut writer, config::standard())?;
+            has_shipped = true;
+        }
+        if has_shipped {
+            writer.flush()?;
+            last_shipment = Instant::now();
+        } else {
+            let until_heartbeat = HEARTBEAT_INTERVAL.saturating_sub(last_shipment.elapsed());
+            db.group_commit.wait_log_end_past(log_end, Some(until_heartbeat));
+        }
+    }
+}
+
+/// Sends the bytes of a data file from `offset` up to `size`, in chunks.
+fn ship_file(writer: &mut impl Write, file_path: &Path, id: usize, offset: usize, size: usize) -> Result<()> {
+    let mut file = File::open(file_path)?;
+    file.seek(SeekFrom::Start(offset.try_into()?))?;
+    let mut chunk_offset = offset;
+    while chunk_offset < size {
+        let mut bytes = vec![0; CHUNK_SIZE.min(size - chunk_offset)];
+        file.read_exact(&mut bytes)?;
+        let length = bytes.len();
+        let chunk = Shipment::Chunk {
+            file_id: id.try_into()?,
+            offset: chunk_offset.try_into()?,
+            bytes,
+        };
+        encode_into_std_write(chunk, &mut *writer, config::standard())?;
+        chunk_offset += length;
+    }
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::sync::{Mutex, MutexGuard};
+use anyhow::{Result, bail};
+
+use crate::{BitcaskError, BitcaskHandler};
+
+/// Iterator over the key-value pairs of a [`KeyValueStore`].
+pub type StoreIter<'a> = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + 'a>;
+
+/// Operations shared by an embedded datastore and a remote one, so code can be written once for both.
+///
+/// Implemented by [`BitcaskHandler`], [`crate::client::Client`] and [`MemoryStore`], which lets tests run
+/// without touching disk. Errors match the handler's: a missing key fails with [`BitcaskError::KeyNotFound`].
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use bitcask::{BitcaskHandler, KeyValueStore, Options, client::Client};
+///
+/// fn record_visit(store: &impl KeyValueStore, user: &[u8]) -> anyhow::Result<()> {
+///     store.put(&[b"visit:", user].concat(), b"1")
+/// }
+///
+/// let options = Options { read_write: true, ..Default::default() };
+/// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), Some(options)).unwrap();
+/// record_visit(&db, b"ada").unwrap();
+/// let remote = Client::connect("127.0.0.1:6379", None).unwrap();
+/// record_visit(&remote, b"ada").unwrap();
+/// ```
+pub trait KeyValueStore {
+    /// See [`BitcaskHandler::get`].
+    fn get(&self, key: &[u8]) -> Result<Vec<u8>>;
+
+    /// See [`BitcaskHandler::put`].
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()>;
+
+    /// See [`BitcaskHandler::delete`].
+    fn delete(&self, key: &[u8]) -> Result<()>;
+
+    /// See [`BitcaskHandler::list_keys`].
+    fn list_keys(&self) -> Result<Vec<Vec<u8>>>;
+
+    /// See [`BitcaskHandler::iter`].
+    fn iter(&self) -> Result<StoreIter<'_>>;
+
+    /// See [`BitcaskHandler::merge`].
+    fn merge(&self) -> Result<()>;
+
+    /// See [`BitcaskHandler::sync`].
+    fn sync(&self) -> Result<()>;
+}
+
+impl KeyValueStore for BitcaskHandler {
+    fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
+        BitcaskHandler::get(self, key)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        BitcaskHandler::put(self, key, value)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        BitcaskHandler::delete(self, key)
+    }
+
+    fn list_keys(&self) -> Result<Vec<Vec<u8>>> {
+        BitcaskHandler::list_keys(self)
+    }
+
+    fn iter(&self) -> Result<StoreIter<'_>> {
+        Ok(Box::new(BitcaskHandler::iter(self)?))
+    }
+
+    fn merge(&self) -> Result<()> {
+        BitcaskHandler::merge(self)
+    }
+
+    fn sync(&self) -> Result<()> {
+        BitcaskHandler::sync(self)
+    }
+}
+
+/// [`KeyValueStore`] keeping its pairs in memory, for tests of code written against the trait.
+///
+/// Keys are iterated in order. `merge` and `sync` have nothing to do.
+///
+/// # Example
+///
+/// ```
+/// use bitcask::{KeyValueStore, MemoryStore};
+///
+/// let store = MemoryStore::new();
+/// store.put(b"user:1", b"Ada").unwrap();
+/// assert_eq!(store.get(b"user:1").unwrap(), b"Ada");
+/// assert!(store.get(b"user:2").is_err());
+/// ```
+#[derive(Default)]
+pub struct MemoryStore {
+    pairs: Mutex<imbl::OrdMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock_pairs(&self) -> MutexGuard<'_, imbl::OrdMap<Vec<u8>, Vec<u8>>> {
+        self.pairs
+            .lock()
+            .expect("Memory store lock poisoned by a panicking thread")
+    }
+}
+
+impl KeyValueStore for MemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
+        self.lock_pairs()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| BitcaskError::KeyNotFound.into())
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.lock_pairs().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        if self.lock_pairs().remove(key).is_none() {
+            bail!(BitcaskError::KeyNotFound);
+        }
+        Ok(())
+    }
+
+    fn list_keys(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self.lock_pairs().keys().cloned().collect())
+    }
+
+    fn iter(&self) -> Result<StoreIter<'_>> {
+        // Iterates over a snapshot, like the handler, cloning the map is O(1)
+        let snapshot = self.lock_pairs().clone();
+        Ok(Box::new(snapshot.into_iter().map(Ok)))
+    }
+
+    fn merge(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{Result, bail};
+
+use crate::{BitcaskError, BitcaskHandler, WriteBatch, engine::DirEntry};
+
+/// Read-modify-write transaction created by [`BitcaskHandler::transaction`].
+///
+/// Reads go to the datastore and record the version they saw (`None` for missing keys),
+/// writes are buffered in a [`WriteBatch`] and are visible to later reads of the same transaction.
+pub struct Transaction<'a> {
+    handler: &'a BitcaskHandler,
+    read_set: HashMap<Vec<u8>, Option<u64>>,
+    pending_writes: HashMap<Vec<u8>, Option<Vec<u8>>>,
+    batch: WriteBatch,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(handler: &'a BitcaskHandler) -> Self {
+        Self {
+            handler,
+            read_set: HashMap::new(),
+            pending_writes: HashMap::new(),
+            batch: WriteBatch::new(),
+        }
+    }
+
+    /// Returns the value of `key`, or `None` if it doesn't exist.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(pending_value) = self.pending_writes.get(key) {
+            return Ok(pending_value.clone());
+        }
+        let found = self.handler.engine().get_with_dir_entry(key)?;
+        let (value, dir_entry) = found.unzip();
+        // Keep the first version seen, a key changing between two reads conflicts anyway
+        self.read_set
+            .entry(key.to_vec())
+            .or_insert(dir_entry.as_ref().map(DirEntry::version));
+        Ok(value)
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.batch.put(key, value);
+        self.pending_writes.insert(key.to_vec(), Some(value.to_vec()));
+    }
+
+    pub fn put_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) {
+        self.batch.put_with_ttl(key, value, ttl);
+        self.pending_writes.insert(key.to_vec(), Some(value.to_vec()));
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        self.batch.delete(key);
+        self.pending_writes.insert(key.to_vec(), None);
+    }
+
+    pub(crate) fn commit(self) -> Result<()> {
+        // Validation and write happen under the same lock, nothing can sneak in between
+        self.handler.write(|engine| {
+            // Versions rather than whole entries are compared, a merge moves entries without changing them
+            for (key, seen) in &self.read_set {
+                if engine.version(key) != *seen {
+                    bail!(BitcaskError::Conflict { key: key.clone() });
+                }
+            }
+            engine.write_batch(self.batch)
+        })
+    }
+}
@@ -0,0 +1,150 @@
+//! Conversion of datastores written by the first version of the format, whose data files have no header.
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, Seek},
+    path::Path,
+};
+use anyhow::{Context, Result, bail};
+use bincode::{Decode, config, decode_from_std_read};
+
+use crate::{BitcaskError, BitcaskHandler, Options, engine::Bitcask, files::WorkingFile};
+
+// Inside the datastore directory, so that files are moved rather than copied
+const STAGING_DIR: &str = "upgrade";
+const LEGACY_DIR: &str = "legacy";
+
+/// Entry of the first version of the format, data files were a bare sequence of them.
+#[derive(Decode)]
+struct LegacyEntry {
+    crc_checksum: u32,
+    timestamp: u64,
+    key: Vec<u8>,
+    value: Vec<u8>,
+    is_deleted: bool,
+}
+
+impl LegacyEntry {
+    fn is_valid(&self) -> bool {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.timestamp.to_le_bytes());
+        hasher.update(&self.key);
+        hasher.update(&self.value);
+        hasher.finalize() == self.crc_checksum
+    }
+}
+
+/// Rewrites a datastore written by the first version of the format, which current versions refuse to open.
+///
+/// The entries are replayed in the order they were written into data files of the current format, timestamps
+/// are those of the upgrade. The old data files are kept in a `legacy` directory inside `directory`, it can be
+/// removed once the upgraded datastore is checked.
+///
+/// # Arguments
+///
+/// * `directory` - Directory of the datastore, no other process must have it open.
+/// * `options` - Options of the upgraded datastore, e.g. `mac_secret` to sign the rewritten entries.
+///
+/// # Returns
+///
+/// The number of entries replayed, deletes included.
+///
+/// # Errors
+///
+/// Fails with [`BitcaskError::Locked`] if another process has the directory open for writing, with
+/// [`BitcaskError::Corrupted`] if an old entry doesn't match its checksum, or if the datastore is already in the
+/// current format. Until the old data files are moved away the datastore is left as it was.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+/// use bitcask::{BitcaskHandler, upgrade};
+///
+/// upgrade::upgrade(Path::new("/tmp/bitcask"), None).unwrap();
+/// let db = BitcaskHandler::open(Path::new("/tmp/bitcask"), None).unwrap();
+/// ```
+pub fn upgrade(directory: &Path, options: Option<Options>) -> Result<usize> {
+    let _lock = Bitcask::try_acquire_write_lock(directory)?;
+    let legacy_dir = directory.join(LEGACY_DIR);
+    if legacy_dir.exists() {
+        bail!(
+            "{} already exists, an earlier upgrade was done or interrupted while moving files",
+            legacy_dir.display()
+        );
+    }
+    let data_files = WorkingFile::list_data_files(directory)?;
+    if data_files.is_empty() {
+        bail!("{} holds no data files to upgrade", directory.display());
+    }
+    for file_path in &data_files {
+        let file_name = file_name(file_path);
+        // Old files fail the check, except empty ones
+        if WorkingFile::read_header(&mut File::open(file_path)?, &file_name).unwrap_or(false) {
+            bail!("{file_name} is already in the current format, there's nothing to upgrade");
+        }
+    }
+
+    // A staging directory left by an interrupted upgrade only holds copies
+    let staging_dir = directory.join(STAGING_DIR);
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    fs::create_dir(&staging_dir)?;
+    let options = Options {
+        read_write: true,
+        ..options.unwrap_or_default()
+    };
+    let db = BitcaskHandler::open(&staging_dir, Some(options))?;
+    let mut replayed = 0;
+    for file_path in &data_files {
+        replayed += replay(&db, file_path)?;
+    }
+    db.close()?;
+    drop(db);
+
+    fs::create_dir(&legacy_dir)?;
+    for file_path in &data_files {
+        fs::rename(file_path, legacy_dir.join(file_name(file_path)))?;
+    }
+    for file_path in WorkingFile::list_data_files(&staging_dir)? {
+        fs::rename(&file_path, directory.join(file_name(&file_path)))?;
+    }
+    fs::remove_dir_all(&staging_dir)?;
+    Ok(replayed)
+}
+
+/// Writes the entries of an old data file to `db`, returns how many there were.
+fn replay(db: &BitcaskHandler, file_path: &Path) -> Result<usize> {
+    let file_name = file_name(file_path);
+    let mut reader = BufReader::new(File::open(file_path)?);
+    let mut replayed = 0;
+    loop {
+        let entry_pos: usize = reader.stream_position()?.try_into()?;
+        let entry: LegacyEntry = match decode_from_std_read(&mut reader, config::standard()) {
+            Ok(entry) => entry,
+            // The end of the file, or an entry torn by a crash
+            Err(e) if e.to_string().contains("UnexpectedEof") => return Ok(replayed),
+            Err(e) => return Err(e).with_context(|| format!("Couldn't read {file_name} at offset {entry_pos}")),
+        };
+        if !entry.is_valid() {
+            bail!(BitcaskError::Corrupted {
+                file_name,
+                offset: entry_pos
+            });
+        }
+        if !entry.is_deleted {
+            db.put(&entry.key, &entry.value)?;
+        } else if db.contains_key(&entry.key) {
+            db.delete(&entry.key)?;
+        }
+        replayed += 1;
+    }
+}
+
+fn file_name(file_path: &Path) -> String {
+    file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
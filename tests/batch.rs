@@ -0,0 +1,65 @@
+use std::fs::OpenOptions;
+
+use bitcask::{BitcaskError, BitcaskHandler, WriteBatch};
+
+use common::{bitcask_error, first_data_file, flip_bit_in, read_write, temp_dir};
+
+mod common;
+
+#[test]
+fn batch_is_applied_entirely() {
+    let dir = temp_dir("batch");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    db.put(b"c", b"3").unwrap();
+    let mut batch = WriteBatch::new();
+    batch.put(b"a", b"1").put(b"b", b"2").delete(b"c");
+    db.write_batch(batch).unwrap();
+    drop(db);
+
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert_eq!(db.get(b"a").unwrap(), b"1");
+    assert_eq!(db.get(b"b").unwrap(), b"2");
+    assert!(matches!(bitcask_error(db.get(b"c")), BitcaskError::KeyNotFound));
+}
+
+#[test]
+fn batch_torn_at_the_end_of_a_file_is_dropped() {
+    let dir = temp_dir("torn-batch");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    db.put(b"kept", b"1").unwrap();
+    let mut batch = WriteBatch::new();
+    batch.put(b"a", b"2").put(b"b", b"3");
+    db.write_batch(batch).unwrap();
+    drop(db);
+
+    // Crashed before the end of the batch reached the disk
+    let file = OpenOptions::new().write(true).open(first_data_file(&dir)).unwrap();
+    file.set_len(file.metadata().unwrap().len() - 3).unwrap();
+    drop(file);
+
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert_eq!(db.get(b"kept").unwrap(), b"1");
+    assert!(db.get(b"a").is_err());
+    assert!(db.get(b"b").is_err());
+    db.put(b"c", b"4").unwrap();
+    drop(db);
+
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert_eq!(db.get(b"kept").unwrap(), b"1");
+    assert_eq!(db.get(b"c").unwrap(), b"4");
+}
+
+#[test]
+fn corrupted_batch_followed_by_writes_is_rejected() {
+    let dir = temp_dir("corrupted-batch");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    let mut batch = WriteBatch::new();
+    batch.put(b"a", b"value of a").put(b"b", b"value of b");
+    db.write_batch(batch).unwrap();
+    db.put(b"c", b"value of c").unwrap();
+    drop(db);
+
+    flip_bit_in(&first_data_file(&dir), b"value of b");
+    let error = bitcask_error(BitcaskHandler::open(&dir, read_write()));
+    assert!(matches!(error, BitcaskError::Corrupted { .. }), "{error}");
+}
@@ -0,0 +1,135 @@
+use std::{
+    ops::Bound,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bitcask::{BitcaskError, BitcaskHandler, Change, Changes, LogPosition, WriteBatch};
+
+use common::{bitcask_error, read_write, temp_dir};
+
+mod common;
+
+/// Changes committed so far, without waiting for more.
+fn drain(changes: &mut Changes) -> Vec<Change> {
+    let mut drained = Vec::new();
+    while let Some(change) = changes.try_next().unwrap() {
+        drained.push(change);
+    }
+    drained
+}
+
+fn summary(changes: &[Change]) -> Vec<(&[u8], &[u8], bool)> {
+    changes
+        .iter()
+        .map(|change| (change.key.as_slice(), change.value.as_slice(), change.is_deleted))
+        .collect()
+}
+
+#[test]
+fn changes_come_in_commit_order() {
+    let db = BitcaskHandler::open(&temp_dir("changes-order"), read_write()).unwrap();
+    db.put(b"a", b"1").unwrap();
+    let mut batch = WriteBatch::new();
+    batch.put(b"user:1", b"2").put(b"user:2", b"3").delete(b"a");
+    db.write_batch(batch).unwrap();
+    assert_eq!(db.delete_prefix(b"user:").unwrap(), 2);
+    db.put(b"b", b"4").unwrap();
+
+    let changes = drain(&mut db.changes(LogPosition::default()));
+    assert_eq!(
+        summary(&changes),
+        [
+            (b"a".as_slice(), b"1".as_slice(), false),
+            (b"user:1", b"2", false),
+            (b"user:2", b"3", false),
+            (b"a", b"", true),
+            (b"user:", b"", true),
+            (b"b", b"4", false),
+        ]
+    );
+    assert!(changes.windows(2).all(|pair| pair[0].version < pair[1].version));
+    assert!(changes.windows(2).all(|pair| pair[0].position < pair[1].position));
+    assert_eq!(changes[4].range_end, Some(Bound::Excluded(b"user;".to_vec())));
+    assert!(changes.iter().filter(|change| change.key != b"user:").all(|change| change.range_end.is_none()));
+}
+
+#[test]
+fn reading_resumes_after_the_last_position() {
+    let db = BitcaskHandler::open(&temp_dir("changes-resume"), read_write()).unwrap();
+    for key in [b"a", b"b", b"c"] {
+        db.put(key, b"1").unwrap();
+    }
+    let mut changes = db.changes(LogPosition::default());
+    assert_eq!(changes.position(), LogPosition::default());
+    changes.try_next().unwrap().unwrap();
+    let second = changes.try_next().unwrap().unwrap();
+    assert_eq!(changes.position(), second.position);
+
+    db.put(b"d", b"1").unwrap();
+    let rest = drain(&mut db.changes(changes.position()));
+    let keys: Vec<&[u8]> = rest.iter().map(|change| change.key.as_slice()).collect();
+    assert_eq!(keys, [b"c", b"d"]);
+
+    // Subscribers only get the changes committed after they subscribed
+    let mut subscriber = db.subscribe();
+    assert!(subscriber.try_next().unwrap().is_none());
+    db.put(b"e", b"1").unwrap();
+    assert_eq!(subscriber.try_next().unwrap().unwrap().key, b"e");
+}
+
+#[test]
+fn positions_removed_by_a_merge_are_compacted() {
+    let db = BitcaskHandler::open(&temp_dir("changes-compacted"), read_write()).unwrap();
+    db.put(b"a", b"1").unwrap();
+    db.put(b"a", b"2").unwrap();
+    let position = drain(&mut db.changes(LogPosition::default()))[0].position;
+    db.merge().unwrap();
+
+    let error = bitcask_error(db.changes(position).try_next());
+    assert!(matches!(error, BitcaskError::Compacted { position: compacted } if compacted == position));
+}
+
+#[test]
+fn values_rewritten_by_a_merge_are_returned_once() {
+    let db = BitcaskHandler::open(&temp_dir("changes-dedup"), read_write()).unwrap();
+    db.put(b"a", b"1").unwrap();
+    db.put(b"b", b"2").unwrap();
+    db.put(b"a", b"3").unwrap();
+    // Opens the first data file before the merge removes it, the merged copies of its values follow it
+    let mut changes = db.changes(LogPosition::default());
+    db.merge().unwrap();
+    db.put(b"c", b"4").unwrap();
+
+    let changes = drain(&mut changes);
+    assert_eq!(
+        summary(&changes),
+        [
+            (b"a".as_slice(), b"1".as_slice(), false),
+            (b"b", b"2", false),
+            (b"a", b"3", false),
+            (b"c", b"4", false),
+        ]
+    );
+    assert!(changes.windows(2).all(|pair| pair[0].version < pair[1].version));
+}
+
+#[test]
+fn next_timeout_wakes_up_on_commit() {
+    let db = BitcaskHandler::open(&temp_dir("changes-wait"), read_write()).unwrap();
+    let mut changes = db.subscribe();
+    let started = Instant::now();
+    assert!(changes.next_timeout(Duration::from_millis(50)).unwrap().is_none());
+    assert!(started.elapsed() >= Duration::from_millis(50));
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            thread::sleep(Duration::from_millis(50));
+            db.put(b"a", b"1").unwrap();
+        });
+        let started = Instant::now();
+        let change = changes.next_timeout(Duration::from_secs(30)).unwrap().unwrap();
+        assert_eq!(change.key, b"a");
+        assert!(started.elapsed() < Duration::from_secs(10));
+    });
+}
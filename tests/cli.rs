@@ -0,0 +1,121 @@
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+use bitcask::BitcaskHandler;
+
+use common::{first_data_file, flip_bit_in, read_write, temp_dir};
+
+mod common;
+
+/// Runs the `bitcask` binary on the datastore in `dir`.
+fn bitcask(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bitcask"))
+        .env_remove("BITCASK_MAC_SECRET")
+        .arg("--dir")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn commands_round_trip_and_missing_keys_exit_with_3() {
+    let dir = temp_dir("cli-commands");
+    assert!(bitcask(&dir, &["put", "user:1", "Ada"]).status.success());
+    assert!(bitcask(&dir, &["--hex", "put", "75736572", "00ff"]).status.success());
+    let output = bitcask(&dir, &["get", "user:1"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "Ada");
+    assert_eq!(stdout(&bitcask(&dir, &["--hex", "get", "75736572"])), "00ff\n");
+    assert_eq!(stdout(&bitcask(&dir, &["keys", "--prefix", "user:"])), "user:1\n");
+
+    assert!(bitcask(&dir, &["delete", "user:1"]).status.success());
+    let output = bitcask(&dir, &["get", "user:1"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("not found"), "{}", stderr(&output));
+    assert_eq!(bitcask(&dir, &["delete", "user:1"]).status.code(), Some(3));
+
+    // Usage errors are reported by clap
+    assert_eq!(bitcask(&dir, &["get"]).status.code(), Some(2));
+    assert_eq!(bitcask(&dir, &["--hex", "get", "abc"]).status.code(), Some(1));
+}
+
+#[test]
+fn verify_reports_corrupted_entries_with_4() {
+    let dir = temp_dir("cli-verify");
+    // Both in the same data file: each run of the binary starts a new one, and a damaged last entry passes
+    // for a write torn by a crash
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    db.put(b"a", b"value of a").unwrap();
+    db.put(b"b", b"value of b").unwrap();
+    drop(db);
+    let output = bitcask(&dir, &["verify"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "OK: 2 live entries (22 bytes) verified\n");
+
+    flip_bit_in(&first_data_file(&dir), b"value of a");
+    let output = bitcask(&dir, &["verify"]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stderr(&output).contains("Corrupted entry"), "{}", stderr(&output));
+    assert!(stdout(&output).is_empty());
+}
+
+#[test]
+fn entries_signed_with_another_secret_exit_with_5() {
+    let dir = temp_dir("cli-tampered");
+    bitcask(&dir, &["--mac-secret", "secret", "put", "a", "1"]);
+    assert!(bitcask(&dir, &["--mac-secret", "secret", "verify"]).status.success());
+    let output = bitcask(&dir, &["--mac-secret", "another secret", "verify"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stderr(&output).contains("Tampered entry"), "{}", stderr(&output));
+}
+
+#[test]
+fn writes_while_another_process_writes_exit_with_7() {
+    let dir = temp_dir("cli-locked");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    db.put(b"a", b"1").unwrap();
+    db.sync().unwrap();
+    let output = bitcask(&dir, &["put", "a", "2"]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(stderr(&output).contains("already open for writing"), "{}", stderr(&output));
+    // Reading doesn't need the lock
+    assert_eq!(stdout(&bitcask(&dir, &["get", "a"])), "1");
+    drop(db);
+    assert!(bitcask(&dir, &["put", "a", "2"]).status.success());
+}
+
+#[test]
+fn shell_scans_pairs_by_prefix() {
+    let dir = temp_dir("cli-shell");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    for (key, value) in [("user:2", "b"), ("user:1", "a"), ("order:1", "c")] {
+        db.put(key.as_bytes(), value.as_bytes()).unwrap();
+    }
+    drop(db);
+
+    let mut shell = Command::new(env!("CARGO_BIN_EXE_bitcask"))
+        .arg("--dir")
+        .arg(&dir)
+        .args(["--ordered", "shell", "--read-only"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    shell.stdin.take().unwrap().write_all(b"scan user:\nscan user: 1\nscan missing\nexit\n").unwrap();
+    let output = shell.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let expected = "\"user:1\" => \"a\"\n\"user:2\" => \"b\"\n(2 entries)\n\"user:1\" => \"a\"\n(1 entries)\n(0 entries)\n";
+    assert!(stdout(&output).ends_with(expected), "{}", stdout(&output));
+}
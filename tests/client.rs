@@ -0,0 +1,82 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use bitcask::{
+    BitcaskError, BitcaskHandler,
+    client::{Client, ClientOptions, ServerError},
+    server,
+};
+
+use common::{bitcask_error, read_write, serve, temp_dir};
+
+mod common;
+
+fn start(name: &str) -> (Arc<BitcaskHandler>, Client) {
+    let db = Arc::new(BitcaskHandler::open(&temp_dir(name), read_write()).unwrap());
+    let address = serve(&db, server::serve_redis);
+    (db, Client::connect(address, None).unwrap())
+}
+
+#[test]
+fn calls_round_trip_through_the_server() {
+    let (db, client) = start("client-calls");
+    client.put(b"a", b"1").unwrap();
+    client.put(b"b", b"\x00\r\n").unwrap();
+    assert_eq!(db.get(b"a").unwrap(), b"1");
+    assert_eq!(client.get(b"b").unwrap(), b"\x00\r\n");
+    assert_eq!(client.get_many(&[b"a".as_slice(), b"missing"]).unwrap(), [Some(b"1".to_vec()), None]);
+
+    assert!(matches!(bitcask_error(client.get(b"missing")), BitcaskError::KeyNotFound));
+    client.delete(b"a").unwrap();
+    assert!(matches!(bitcask_error(client.delete(b"a")), BitcaskError::KeyNotFound));
+
+    let mut keys = client.list_keys().unwrap();
+    keys.sort();
+    assert_eq!(keys, [b"b".to_vec()]);
+    client.sync().unwrap();
+    client.merge().unwrap();
+    assert_eq!(client.get(b"b").unwrap(), b"\x00\r\n");
+}
+
+#[test]
+fn iter_pages_through_every_pair() {
+    let (db, client) = start("client-iter");
+    let expected: BTreeMap<Vec<u8>, Vec<u8>> = (0..1050)
+        .map(|i| (format!("key:{i}").into_bytes(), format!("value:{i}").into_bytes()))
+        .collect();
+    for (key, value) in &expected {
+        db.put(key, value).unwrap();
+    }
+    let pairs: BTreeMap<Vec<u8>, Vec<u8>> = client.iter().unwrap().map(Result::unwrap).collect();
+    assert_eq!(pairs, expected);
+
+    // Interleaved scans each keep their own cursor
+    let (first, second) = (client.iter().unwrap(), client.iter().unwrap());
+    let pairs: Vec<_> = first.zip(second).map(|(a, b)| (a.unwrap(), b.unwrap())).collect();
+    assert_eq!(pairs.len(), expected.len());
+
+    // Keys deleted while iterating are skipped, only the rest of the page already fetched is left
+    let mut iter = client.iter().unwrap();
+    iter.next().unwrap().unwrap();
+    for key in expected.keys() {
+        db.delete(key).unwrap();
+    }
+    assert_eq!(iter.count(), 99);
+}
+
+#[test]
+fn error_replies_are_server_errors() {
+    // Opened read-only, the server refuses writes
+    let db = Arc::new(BitcaskHandler::open(&temp_dir("client-errors"), None).unwrap());
+    let options = ClientOptions {
+        max_idle_connections: 1,
+        ..Default::default()
+    };
+    let client = Client::connect(serve(&db, server::serve_redis), Some(options)).unwrap();
+    let error = client.put(b"a", b"1").unwrap_err();
+    let server_error = error.downcast_ref::<ServerError>().unwrap();
+    assert_eq!(server_error.code, "ERR");
+    assert!(!server_error.message.is_empty());
+    assert!(error.to_string().contains(&server_error.message), "{error}");
+    // The connection is still usable afterwards
+    assert!(matches!(bitcask_error(client.get(b"a")), BitcaskError::KeyNotFound));
+}
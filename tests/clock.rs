@@ -0,0 +1,86 @@
+use std::{sync::Arc, thread, time::Duration};
+
+use bitcask::{BitcaskHandler, Clock, HybridLogicalClock, ManualClock, Options, SystemClock};
+
+use common::temp_dir;
+
+mod common;
+
+fn with_clock(clock: Arc<dyn Clock>) -> Option<Options> {
+    Some(Options {
+        read_write: true,
+        clock,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn hybrid_logical_clock_strictly_increases() {
+    let clock = Arc::new(HybridLogicalClock::new());
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let clock = Arc::clone(&clock);
+            thread::spawn(move || (0..10_000).map(|_| clock.now()).collect::<Vec<_>>())
+        })
+        .collect();
+    let mut readings: Vec<u64> = Vec::new();
+    for thread in threads {
+        let thread_readings = thread.join().unwrap();
+        assert!(thread_readings.windows(2).all(|pair| pair[0] < pair[1]));
+        readings.extend(thread_readings);
+    }
+    // No reading is handed out twice across threads
+    readings.sort();
+    assert!(readings.windows(2).all(|pair| pair[0] < pair[1]));
+
+    // Expiry checks don't move it
+    let last = readings.last().copied().unwrap();
+    for _ in 0..10_000 {
+        assert!(clock.current() >= last);
+    }
+    let next = clock.now();
+    assert!(next <= (last + 1).max(SystemClock.now()));
+}
+
+#[test]
+fn hybrid_logical_clock_counts_up_from_observed_timestamps() {
+    let clock = HybridLogicalClock::new();
+    let future = SystemClock.now() + 3_600_000;
+    clock.observe(future);
+    assert_eq!(clock.current(), future);
+    assert_eq!(clock.now(), future + 1);
+    assert_eq!(clock.now(), future + 2);
+    // Observing an older timestamp doesn't move it back
+    clock.observe(future - 1_000);
+    assert_eq!(clock.now(), future + 3);
+}
+
+#[test]
+fn entries_are_stamped_and_expire_with_the_configured_clock() {
+    let dir = temp_dir("manual-clock");
+    let clock = Arc::new(ManualClock::new(1_000_000));
+    let db = BitcaskHandler::open(&dir, with_clock(clock.clone())).unwrap();
+    db.put(b"a", b"1").unwrap();
+    db.put_with_ttl(b"b", b"2", Duration::from_secs(1)).unwrap();
+    assert_eq!(db.get_with_meta(b"a").unwrap().timestamp, 1_000_000);
+
+    clock.advance(Duration::from_millis(999));
+    assert_eq!(db.get(b"b").unwrap(), b"2");
+    clock.advance(Duration::from_millis(1));
+    assert!(db.get(b"b").is_err());
+    assert_eq!(db.get(b"a").unwrap(), b"1");
+    drop(db);
+
+    // The default clock doesn't stamp new entries before the ones on disk, even with a wall clock behind them
+    let future = SystemClock.now() + 3_600_000;
+    let clock = Arc::new(ManualClock::new(future));
+    let db = BitcaskHandler::open(&dir, with_clock(clock)).unwrap();
+    db.put(b"c", b"3").unwrap();
+    drop(db);
+    let db = BitcaskHandler::open(&dir, with_clock(Arc::new(HybridLogicalClock::new()))).unwrap();
+    db.put(b"d", b"4").unwrap();
+    db.put(b"e", b"5").unwrap();
+    let timestamp = |key: &[u8]| db.get_with_meta(key).unwrap().timestamp;
+    assert!(timestamp(b"c") < timestamp(b"d"));
+    assert!(timestamp(b"d") < timestamp(b"e"));
+}
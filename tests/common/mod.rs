@@ -0,0 +1,60 @@
+// Each test binary uses its own subset of the helpers
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    thread,
+};
+
+use anyhow::Result;
+use bitcask::{BitcaskError, BitcaskHandler, Options};
+
+/// Empty directory for a test, unique to the test binary running it.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("bitcask-test-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn read_write() -> Option<Options> {
+    Some(Options {
+        read_write: true,
+        ..Default::default()
+    })
+}
+
+pub fn first_data_file(dir: &Path) -> PathBuf {
+    dir.join("working_file_0")
+}
+
+/// Flips a bit of the first occurrence of `pattern` in the file, as a bad disk would.
+pub fn flip_bit_in(file_path: &Path, pattern: &[u8]) {
+    let mut bytes = fs::read(file_path).unwrap();
+    let offset = bytes
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+        .expect("Pattern not found in the data file");
+    bytes[offset] ^= 1;
+    fs::write(file_path, bytes).unwrap();
+}
+
+pub fn bitcask_error<T>(result: Result<T>) -> BitcaskError {
+    match result {
+        Ok(_) => panic!("Expected an error"),
+        Err(e) => e.downcast().expect("Expected a BitcaskError"),
+    }
+}
+
+/// Serves the datastore with one of the `server` functions on a free local port, returns its address.
+pub fn serve(db: &Arc<BitcaskHandler>, serve: fn(TcpListener, Arc<BitcaskHandler>) -> Result<()>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let db = Arc::clone(db);
+    thread::spawn(move || serve(listener, db));
+    address
+}
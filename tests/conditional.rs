@@ -0,0 +1,78 @@
+use std::{sync::Arc, thread};
+
+use bitcask::BitcaskHandler;
+
+use common::{read_write, temp_dir};
+
+mod common;
+
+#[test]
+fn compare_and_swap_only_writes_over_the_expected_value() {
+    let db = BitcaskHandler::open(&temp_dir("cas"), read_write()).unwrap();
+    // Creating requires the key to be missing
+    assert!(db.compare_and_swap(b"leader", None, Some(b"node-1")).unwrap());
+    assert!(!db.compare_and_swap(b"leader", None, Some(b"node-2")).unwrap());
+
+    assert!(!db.compare_and_swap(b"leader", Some(b"node-2"), Some(b"node-3")).unwrap());
+    assert_eq!(db.get(b"leader").unwrap(), b"node-1");
+    assert!(db.compare_and_swap(b"leader", Some(b"node-1"), Some(b"node-2")).unwrap());
+    assert_eq!(db.get(b"leader").unwrap(), b"node-2");
+
+    // Deleting
+    assert!(!db.compare_and_swap(b"leader", Some(b"node-1"), None).unwrap());
+    assert!(db.compare_and_swap(b"leader", Some(b"node-2"), None).unwrap());
+    assert!(db.get(b"leader").is_err());
+    assert!(!db.compare_and_swap(b"leader", Some(b"node-2"), None).unwrap());
+}
+
+#[test]
+fn concurrent_compare_and_swaps_never_lose_an_increment() {
+    let db = Arc::new(BitcaskHandler::open(&temp_dir("cas-counter"), read_write()).unwrap());
+    db.put(b"counter", b"0").unwrap();
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    loop {
+                        let current = db.get(b"counter").unwrap();
+                        let next: u64 = String::from_utf8_lossy(&current).parse::<u64>().unwrap() + 1;
+                        if db.compare_and_swap(b"counter", Some(&current), Some(next.to_string().as_bytes())).unwrap() {
+                            break;
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(db.get(b"counter").unwrap(), b"200");
+}
+
+#[test]
+fn put_if_absent_and_delete_if_version() {
+    let dir = temp_dir("conditional");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert!(db.put_if_absent(b"a", b"1").unwrap());
+    assert!(!db.put_if_absent(b"a", b"2").unwrap());
+    assert_eq!(db.get(b"a").unwrap(), b"1");
+
+    let version = db.version(b"a").unwrap();
+    assert_eq!(db.get_with_meta(b"a").unwrap().version, version);
+    db.put(b"a", b"3").unwrap();
+    let new_version = db.version(b"a").unwrap();
+    assert!(new_version > version);
+    assert!(!db.delete_if_version(b"a", version).unwrap());
+    assert_eq!(db.get(b"a").unwrap(), b"3");
+    assert!(!db.delete_if_version(b"missing", version).unwrap());
+
+    // Versions survive reopening
+    drop(db);
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert_eq!(db.version(b"a"), Some(new_version));
+    assert!(db.delete_if_version(b"a", new_version).unwrap());
+    assert_eq!(db.version(b"a"), None);
+    assert!(db.put_if_absent(b"a", b"4").unwrap());
+}
@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use bitcask::{BitcaskHandler, IndexKind, Options};
+
+use common::{read_write, temp_dir};
+
+mod common;
+
+fn ordered() -> Option<Options> {
+    Some(Options {
+        read_write: true,
+        index: IndexKind::Ordered,
+        ..Default::default()
+    })
+}
+
+fn sorted_keys(db: &BitcaskHandler) -> Vec<String> {
+    let mut keys: Vec<String> = db.keys().map(|key| String::from_utf8(key).unwrap()).collect();
+    keys.sort();
+    keys
+}
+
+fn fill(db: &BitcaskHandler) {
+    for key in ["log:2022", "log:2023:01", "log:2023:12", "log:2024", "tenant:1:a", "tenant:1:b", "tenant:2:a"] {
+        db.put(key.as_bytes(), b"1").unwrap();
+    }
+}
+
+fn check_range_and_prefix_deletes(dir: &Path, options: Option<Options>) {
+    let db = BitcaskHandler::open(dir, options).unwrap();
+    fill(&db);
+    assert_eq!(db.delete_range("log:2023".."log:2024").unwrap(), 2);
+    assert_eq!(db.delete_prefix(b"tenant:1:").unwrap(), 2);
+    assert_eq!(sorted_keys(&db), ["log:2022", "log:2024", "tenant:2:a"]);
+    // Nothing left to delete
+    assert_eq!(db.delete_prefix(b"tenant:1:").unwrap(), 0);
+    assert_eq!(db.delete_range("log:2023".."log:2024").unwrap(), 0);
+    // Keys written after the tombstone aren't covered by it
+    db.put(b"tenant:1:c", b"2").unwrap();
+}
+
+#[test]
+fn range_tombstones_survive_reopening_and_merging() {
+    for (name, options) in [("delete-range-hash", read_write()), ("delete-range-ordered", ordered())] {
+        let dir = temp_dir(name);
+        check_range_and_prefix_deletes(&dir, options);
+        let expected = ["log:2022", "log:2024", "tenant:1:c", "tenant:2:a"];
+
+        let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+        assert_eq!(sorted_keys(&db), expected);
+        assert!(db.get(b"log:2023:01").is_err());
+        assert_eq!(db.get(b"tenant:1:c").unwrap(), b"2");
+
+        db.merge().unwrap();
+        drop(db);
+        let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+        assert_eq!(sorted_keys(&db), expected);
+        assert!(db.get(b"tenant:1:a").is_err());
+    }
+}
+
+#[test]
+fn empty_and_reversed_ranges_delete_nothing() {
+    for (name, options) in [("reversed-hash", read_write()), ("reversed-ordered", ordered())] {
+        let db = BitcaskHandler::open(&temp_dir(name), options).unwrap();
+        fill(&db);
+        let size = db.stats().unwrap().data_size;
+        assert_eq!(db.delete_range("log:2024".."log:2022").unwrap(), 0);
+        assert_eq!(db.delete_range("log:2023".."log:2023").unwrap(), 0);
+        assert_eq!(db.delete_prefix(b"missing:").unwrap(), 0);
+        assert_eq!(db.len(), 7);
+        // No tombstone was written
+        assert_eq!(db.stats().unwrap().data_size, size);
+    }
+}
+
+#[test]
+fn prefix_of_max_bytes_has_no_upper_bound() {
+    let db = BitcaskHandler::open(&temp_dir("delete-prefix-max"), ordered()).unwrap();
+    db.put(&[0xff], b"1").unwrap();
+    db.put(&[0xff, 0xff, 0], b"2").unwrap();
+    db.put(&[0xfe], b"3").unwrap();
+    assert_eq!(db.delete_prefix(&[0xff]).unwrap(), 2);
+    assert_eq!(db.keys().collect::<Vec<_>>(), [vec![0xfe]]);
+}
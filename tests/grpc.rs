@@ -0,0 +1,100 @@
+#![cfg(feature = "grpc")]
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use bitcask::{
+    BitcaskHandler, IndexKind, Options,
+    grpc::{
+        BatchWriteRequest, BitcaskClient, DeleteRequest, GetRequest, PutRequest, ScanRequest, WriteOperation,
+        write_operation::Operation,
+    },
+    server,
+};
+use tonic::Code;
+
+use common::{read_write, serve, temp_dir};
+
+mod common;
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap()
+}
+
+fn put(key: &str, value: &str) -> PutRequest {
+    PutRequest {
+        key: key.as_bytes().to_vec(),
+        value: value.as_bytes().to_vec(),
+        ttl_millis: None,
+    }
+}
+
+async fn scan(client: &mut BitcaskClient<tonic::transport::Channel>, prefix: &str, limit: u64) -> Vec<(String, String)> {
+    let request = ScanRequest {
+        prefix: prefix.as_bytes().to_vec(),
+        limit,
+    };
+    let mut stream = client.scan(request).await.unwrap().into_inner();
+    let mut pairs = Vec::new();
+    while let Some(pair) = stream.message().await.unwrap() {
+        pairs.push((String::from_utf8(pair.key).unwrap(), String::from_utf8(pair.value).unwrap()));
+    }
+    pairs
+}
+
+#[test]
+fn get_put_delete_and_batch_write() {
+    let db = Arc::new(BitcaskHandler::open(&temp_dir("grpc-crud"), read_write()).unwrap());
+    let address = format!("http://{}", serve(&db, server::serve_grpc));
+    runtime().block_on(async {
+        let mut client = BitcaskClient::connect(address).await.unwrap();
+        let missing = client.get(GetRequest { key: b"a".to_vec() }).await.unwrap_err();
+        assert_eq!(missing.code(), Code::NotFound);
+
+        client.put(put("a", "1")).await.unwrap();
+        let response = client.get(GetRequest { key: b"a".to_vec() }).await.unwrap().into_inner();
+        assert_eq!(response.value, b"1");
+        assert_eq!(Some(response.version), db.version(b"a"));
+
+        let operations = [Operation::Put(put("b", "2")), Operation::Delete(DeleteRequest { key: b"a".to_vec() })];
+        let request = BatchWriteRequest {
+            operations: operations.into_iter().map(|operation| WriteOperation { operation: Some(operation) }).collect(),
+        };
+        client.batch_write(request).await.unwrap();
+        assert!(db.get(b"a").is_err());
+        assert_eq!(db.get(b"b").unwrap(), b"2");
+    });
+}
+
+#[test]
+fn scans_stream_pairs_by_prefix_with_either_index() {
+    for (name, index) in [("grpc-scan-hash", IndexKind::Hash), ("grpc-scan-ordered", IndexKind::Ordered)] {
+        let options = Options {
+            read_write: true,
+            index,
+            ..Default::default()
+        };
+        let db = Arc::new(BitcaskHandler::open(&temp_dir(name), Some(options)).unwrap());
+        for i in 0..200 {
+            db.put(format!("user:{i:03}").as_bytes(), i.to_string().as_bytes()).unwrap();
+        }
+        db.put(b"order:1", b"x").unwrap();
+        let address = format!("http://{}", serve(&db, server::serve_grpc));
+
+        runtime().block_on(async {
+            let mut client = BitcaskClient::connect(address).await.unwrap();
+            let users: BTreeMap<String, String> = scan(&mut client, "user:", 0).await.into_iter().collect();
+            assert_eq!(users.len(), 200);
+            assert_eq!(users["user:042"], "42");
+            assert_eq!(scan(&mut client, "", 0).await.len(), 201);
+            assert_eq!(scan(&mut client, "order:", 0).await, [("order:1".to_string(), "x".to_string())]);
+            assert!(scan(&mut client, "missing:", 0).await.is_empty());
+
+            let page = scan(&mut client, "user:", 10).await;
+            assert_eq!(page.len(), 10);
+            if index == IndexKind::Ordered {
+                assert_eq!(page[0].0, "user:000");
+                assert_eq!(page[9].0, "user:009");
+            }
+        });
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use bitcask::{
+    BitcaskHandler,
+    server::{self, HttpOptions},
+};
+
+use common::{read_write, temp_dir};
+
+mod common;
+
+struct HttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Sends a single request on its own connection and reads the response until the server closes it.
+fn request(address: &str, method: &str, path: &str, headers: &[(&str, &str)], body: &[u8]) -> HttpResponse {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut head = format!("{method} {path} HTTP/1.1\r\nHost: {address}\r\nConnection: close\r\n");
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+    stream.write_all(head.as_bytes()).unwrap();
+    // The server may answer before reading a body it refuses
+    let _ = stream.write_all(body);
+
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    let head_end = response.windows(4).position(|window| window == b"\r\n\r\n").unwrap();
+    let head = String::from_utf8(response[..head_end].to_vec()).unwrap();
+    let mut lines = head.split("\r\n");
+    let status = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+    let headers = lines
+        .filter_map(|line| line.split_once(": "))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    HttpResponse {
+        status,
+        headers,
+        body: response[head_end + 4..].to_vec(),
+    }
+}
+
+fn start(name: &str, options: Option<HttpOptions>) -> (Arc<BitcaskHandler>, String) {
+    let db = Arc::new(BitcaskHandler::open(&temp_dir(name), read_write()).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let served = Arc::clone(&db);
+    thread::spawn(move || server::serve_http_with_options(listener, served, options));
+    (db, address)
+}
+
+#[test]
+fn put_get_and_delete_keys() {
+    let (db, address) = start("http-crud", None);
+    assert_eq!(request(&address, "GET", "/keys/a%2Fb", &[], b"").status, 404);
+    assert_eq!(request(&address, "PUT", "/keys/a%2Fb", &[], b"\x00\x01value").status, 204);
+    assert_eq!(db.get(b"a/b").unwrap(), b"\x00\x01value");
+
+    let response = request(&address, "GET", "/keys/a%2Fb", &[], b"");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"\x00\x01value");
+    request(&address, "PUT", "/keys/c", &[], b"1");
+    let listing = request(&address, "GET", "/keys?prefix=a", &[], b"");
+    assert_eq!(listing.body, b"a%2Fb\n");
+
+    assert_eq!(request(&address, "DELETE", "/keys/a%2Fb", &[], b"").status, 204);
+    assert_eq!(request(&address, "DELETE", "/keys/a%2Fb", &[], b"").status, 404);
+    assert_eq!(request(&address, "POST", "/keys/c", &[], b"").status, 405);
+}
+
+#[test]
+fn etags_make_reads_and_writes_conditional() {
+    let (db, address) = start("http-etag", None);
+    request(&address, "PUT", "/keys/a", &[], b"1");
+    let etag = request(&address, "GET", "/keys/a", &[], b"").header("ETag").unwrap().to_string();
+
+    // Unchanged value
+    let response = request(&address, "GET", "/keys/a", &[("If-None-Match", &etag)], b"");
+    assert_eq!(response.status, 304);
+    assert!(response.body.is_empty());
+    let response = request(&address, "GET", "/keys/a", &[("If-None-Match", "\"0-0\"")], b"");
+    assert_eq!(response.status, 200);
+
+    // Writes over the version that was read
+    assert_eq!(request(&address, "PUT", "/keys/a", &[("If-Match", &etag)], b"2").status, 204);
+    assert_eq!(request(&address, "PUT", "/keys/a", &[("If-Match", &etag)], b"3").status, 412);
+    assert_eq!(request(&address, "DELETE", "/keys/a", &[("If-Match", &etag)], b"").status, 412);
+    assert_eq!(db.get(b"a").unwrap(), b"2");
+    let new_etag = request(&address, "GET", "/keys/a", &[], b"").header("ETag").unwrap().to_string();
+    assert_ne!(new_etag, etag);
+    assert_eq!(request(&address, "GET", "/keys/a", &[("If-None-Match", &etag)], b"").status, 200);
+    let tags = format!("\"0-0\", {new_etag}");
+    assert_eq!(request(&address, "PUT", "/keys/a", &[("If-Match", &tags)], b"4").status, 204);
+
+    // Existence conditions
+    assert_eq!(request(&address, "PUT", "/keys/a", &[("If-None-Match", "*")], b"5").status, 412);
+    assert_eq!(request(&address, "PUT", "/keys/b", &[("If-None-Match", "*")], b"1").status, 204);
+    assert_eq!(request(&address, "PUT", "/keys/c", &[("If-Match", "*")], b"1").status, 412);
+    assert_eq!(request(&address, "DELETE", "/keys/b", &[("If-Match", "*")], b"").status, 204);
+    assert_eq!(db.get(b"a").unwrap(), b"4");
+    assert!(db.get(b"b").is_err());
+}
+
+#[test]
+fn bodies_over_the_limit_are_refused() {
+    let options = HttpOptions {
+        max_body_size: 1024,
+        workers: 2,
+    };
+    let (db, address) = start("http-limit", Some(options));
+    assert_eq!(request(&address, "PUT", "/keys/a", &[], &[1; 1024]).status, 204);
+    assert_eq!(request(&address, "PUT", "/keys/b", &[], &[1; 1025]).status, 413);
+    assert_eq!(db.get(b"a").unwrap().len(), 1024);
+    assert!(db.get(b"b").is_err());
+    // The server still answers afterwards
+    assert_eq!(request(&address, "GET", "/keys/a", &[], b"").status, 200);
+}
+
+#[test]
+fn a_small_pool_serves_concurrent_clients() {
+    let options = HttpOptions {
+        workers: 2,
+        ..Default::default()
+    };
+    let (db, address) = start("http-pool", Some(options));
+    let clients: Vec<_> = (0..8)
+        .map(|client| {
+            let address = address.clone();
+            thread::spawn(move || {
+                for i in 0..10 {
+                    let path = format!("/keys/{client}-{i}");
+                    assert_eq!(request(&address, "PUT", &path, &[], b"1").status, 204);
+                }
+            })
+        })
+        .collect();
+    for client in clients {
+        client.join().unwrap();
+    }
+    assert_eq!(db.len(), 80);
+}
@@ -0,0 +1,97 @@
+use std::{fs, path::Path};
+
+use bitcask::{BitcaskError, BitcaskHandler, Options};
+
+use common::{bitcask_error, first_data_file, flip_bit_in, read_write, temp_dir};
+
+mod common;
+
+#[test]
+fn corrupted_entry_fails_reads_and_reopening() {
+    let dir = temp_dir("corrupted-entry");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    db.put(b"a", b"value of a").unwrap();
+    db.put(b"b", b"value of b").unwrap();
+    db.sync().unwrap();
+
+    flip_bit_in(&first_data_file(&dir), b"value of a");
+    assert!(matches!(bitcask_error(db.get(b"a")), BitcaskError::Corrupted { .. }));
+    assert_eq!(db.get(b"b").unwrap(), b"value of b");
+    drop(db);
+
+    let error = bitcask_error(BitcaskHandler::open(&dir, read_write()));
+    assert!(matches!(error, BitcaskError::Corrupted { .. }), "{error}");
+}
+
+#[test]
+fn entries_signed_with_another_secret_are_tampered() {
+    let dir = temp_dir("tampered-entry");
+    let with_secret = |secret: &[u8]| {
+        Some(Options {
+            read_write: true,
+            mac_secret: Some(secret.to_vec()),
+            ..Default::default()
+        })
+    };
+    let db = BitcaskHandler::open(&dir, with_secret(b"secret")).unwrap();
+    db.put(b"a", b"1").unwrap();
+    db.put(b"b", b"2").unwrap();
+    drop(db);
+
+    let error = bitcask_error(BitcaskHandler::open(&dir, with_secret(b"another secret")));
+    assert!(matches!(error, BitcaskError::Tampered { .. }), "{error}");
+
+    let db = BitcaskHandler::open(&dir, with_secret(b"secret")).unwrap();
+    assert_eq!(db.get(b"a").unwrap(), b"1");
+}
+
+#[test]
+fn sequence_marks_are_signed_like_entries() {
+    let dir = temp_dir("signed-sequence-mark");
+    let with_secret = |secret: Option<&[u8]>| {
+        Some(Options {
+            read_write: true,
+            mac_secret: secret.map(<[u8]>::to_vec),
+            ..Default::default()
+        })
+    };
+    // Once merged, only the mark keeping the versions used so far is left
+    let merge_deleted_key = |dir: &Path, secret: Option<&[u8]>| {
+        let db = BitcaskHandler::open(dir, with_secret(secret)).unwrap();
+        db.put(b"a", b"1").unwrap();
+        let version = db.version(b"a").unwrap();
+        db.delete(b"a").unwrap();
+        db.merge().unwrap();
+        version
+    };
+    merge_deleted_key(&dir, None);
+    let error = bitcask_error(BitcaskHandler::open(&dir, with_secret(Some(b"secret"))));
+    assert!(matches!(error, BitcaskError::Tampered { .. }), "{error}");
+
+    let dir = temp_dir("signed-sequence-mark-accepted");
+    let version = merge_deleted_key(&dir, Some(b"secret"));
+    let db = BitcaskHandler::open(&dir, with_secret(Some(b"secret"))).unwrap();
+    db.put(b"b", b"2").unwrap();
+    assert!(db.version(b"b").unwrap() > version);
+}
+
+#[test]
+fn data_files_of_unknown_formats_are_rejected() {
+    let dir = temp_dir("unknown-format");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    db.put(b"a", b"1").unwrap();
+    drop(db);
+    let file_path = first_data_file(&dir);
+    let bytes = fs::read(&file_path).unwrap();
+
+    let mut newer = bytes.clone();
+    newer[4] += 1;
+    fs::write(&file_path, newer).unwrap();
+    let error = BitcaskHandler::open(&dir, read_write()).err().unwrap();
+    assert!(error.to_string().contains("format version"), "{error}");
+
+    // Without any header, as written before formats were versioned
+    fs::write(&file_path, &bytes[5..]).unwrap();
+    let error = BitcaskHandler::open(&dir, read_write()).err().unwrap();
+    assert!(error.to_string().contains("known format"), "{error}");
+}
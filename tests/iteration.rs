@@ -0,0 +1,93 @@
+use std::{sync::Arc, time::Duration};
+
+use bitcask::{BitcaskHandler, ManualClock, Options};
+
+use common::{read_write, temp_dir};
+
+mod common;
+
+#[test]
+fn entries_and_fold_visit_live_pairs_in_disk_order() {
+    let clock = Arc::new(ManualClock::new(1_000_000));
+    let options = Options {
+        read_write: true,
+        // A data file per couple of entries
+        max_data_size: 64,
+        clock: clock.clone(),
+        ..Default::default()
+    };
+    let db = BitcaskHandler::open(&temp_dir("entries"), Some(options)).unwrap();
+    db.put(b"a", b"1").unwrap();
+    db.put(b"b", b"2").unwrap();
+    db.put(b"c", b"3").unwrap();
+    db.put(b"a", b"4").unwrap();
+    db.put(b"d", b"5").unwrap();
+    db.delete(b"d").unwrap();
+    db.put_with_ttl(b"e", b"6", Duration::from_secs(1)).unwrap();
+    assert!(db.stats().unwrap().data_files > 2);
+    clock.advance(Duration::from_secs(1));
+
+    let entries = db.entries().unwrap();
+    db.put(b"f", b"7").unwrap();
+    let pairs: Vec<(Vec<u8>, Vec<u8>)> = entries.map(Result::unwrap).collect();
+    // The overwritten key comes where its last value is, keys written afterwards aren't visited
+    assert_eq!(
+        pairs,
+        [
+            (b"b".to_vec(), b"2".to_vec()),
+            (b"c".to_vec(), b"3".to_vec()),
+            (b"a".to_vec(), b"4".to_vec()),
+        ]
+    );
+
+    let order = db.fold(String::new(), |mut acc, key, value| {
+        acc.push_str(&String::from_utf8_lossy(key));
+        acc.push_str(&String::from_utf8_lossy(value));
+        acc
+    });
+    assert_eq!(order.unwrap(), "b2c3a4f7");
+}
+
+#[test]
+fn fold_of_an_empty_datastore_is_its_initial_value() {
+    let db = BitcaskHandler::open(&temp_dir("fold-empty"), read_write()).unwrap();
+    assert_eq!(db.fold(42, |acc, _, _| acc + 1).unwrap(), 42);
+    assert_eq!(db.entries().unwrap().count(), 0);
+}
+
+#[test]
+fn keys_and_iter_work_on_a_snapshot() {
+    let clock = Arc::new(ManualClock::new(1_000_000));
+    let options = Options {
+        read_write: true,
+        clock: clock.clone(),
+        ..Default::default()
+    };
+    let db = BitcaskHandler::open(&temp_dir("keys-iter"), Some(options)).unwrap();
+    for i in 0..100 {
+        db.put(format!("key:{i:03}").as_bytes(), i.to_string().as_bytes()).unwrap();
+    }
+    db.delete(b"key:000").unwrap();
+    db.put_with_ttl(b"key:001", b"1", Duration::from_secs(1)).unwrap();
+    clock.advance(Duration::from_secs(1));
+
+    let keys = db.keys();
+    let iter = db.iter().unwrap();
+    db.put(b"key:new", b"new").unwrap();
+    db.delete(b"key:002").unwrap();
+
+    let mut keys: Vec<Vec<u8>> = keys.collect();
+    keys.sort();
+    let expected: Vec<Vec<u8>> = (2..100).map(|i| format!("key:{i:03}").into_bytes()).collect();
+    assert_eq!(keys, expected);
+
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = iter.map(Result::unwrap).collect();
+    pairs.sort();
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = (2..100)
+        .map(|i| (format!("key:{i:03}").into_bytes(), i.to_string().into_bytes()))
+        .collect();
+    assert_eq!(pairs, expected);
+
+    // Live keys as of now
+    assert_eq!(db.keys().count(), 98);
+}
@@ -0,0 +1,48 @@
+use bitcask::{BitcaskHandler, Options};
+
+use common::{read_write, temp_dir};
+
+mod common;
+
+#[test]
+fn put_many_and_get_many_round_trip() {
+    let dir = temp_dir("many");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    db.put_many(&[("a", "1"), ("b", "2"), ("a", "3")]).unwrap();
+    // Duplicate keys are answered each time, in the order asked
+    let values = db.get_many(&[b"b".as_slice(), b"missing", b"a", b"b"]).unwrap();
+    assert_eq!(values, [Some(b"2".to_vec()), None, Some(b"3".to_vec()), Some(b"2".to_vec())]);
+    assert!(db.get_many::<&[u8]>(&[]).unwrap().is_empty());
+
+    // Each pair gets its own version, the last one wins
+    let version_b = db.version(b"b").unwrap();
+    let version_a = db.version(b"a").unwrap();
+    assert!(version_a > version_b);
+
+    drop(db);
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert_eq!(db.get(b"a").unwrap(), b"3");
+    assert_eq!(db.version(b"a"), Some(version_a));
+}
+
+#[test]
+fn get_many_reads_values_across_data_files() {
+    let options = Options {
+        read_write: true,
+        max_data_size: 128,
+        ..Default::default()
+    };
+    let db = BitcaskHandler::open(&temp_dir("many-files"), Some(options)).unwrap();
+    let pairs: Vec<(String, String)> = (0..50).map(|i| (format!("key:{i}"), format!("value:{i}"))).collect();
+    db.put_many(&pairs[..25]).unwrap();
+    for (key, value) in &pairs[25..] {
+        db.put(key.as_bytes(), value.as_bytes()).unwrap();
+    }
+    assert!(db.stats().unwrap().data_files > 2);
+
+    // Asked in reverse order, values come back in the order asked
+    let keys: Vec<&str> = pairs.iter().rev().map(|(key, _)| key.as_str()).collect();
+    let values = db.get_many(&keys).unwrap();
+    let expected: Vec<Option<Vec<u8>>> = pairs.iter().rev().map(|(_, value)| Some(value.clone().into_bytes())).collect();
+    assert_eq!(values, expected);
+}
@@ -0,0 +1,354 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    time::Duration,
+};
+
+use bitcask::{BitcaskHandler, ManualClock, Options, server};
+
+use common::{read_write, serve, temp_dir};
+
+mod common;
+
+/// Text protocol connection, requests are sent as given.
+struct TextConnection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl TextConnection {
+    fn open(address: &str) -> Self {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        Self {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    /// Sends a request and returns its reply, up to and including the line that ends it.
+    fn call(&mut self, request: &str) -> String {
+        self.writer.write_all(request.as_bytes()).unwrap();
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            assert_ne!(self.reader.read_line(&mut line).unwrap(), 0, "Connection closed after {reply:?}");
+            reply.push_str(&line);
+            if let Some(header) = line.strip_prefix("VALUE ") {
+                let length: usize = header.split(' ').nth(2).unwrap().trim().parse().unwrap();
+                let mut data = vec![0; length + 2];
+                self.reader.read_exact(&mut data).unwrap();
+                reply.push_str(&String::from_utf8(data).unwrap());
+            } else if !line.starts_with("STAT ") {
+                return reply;
+            }
+        }
+    }
+
+    fn is_closed(&mut self) -> bool {
+        let mut rest = Vec::new();
+        self.reader.read_to_end(&mut rest).unwrap() == 0
+    }
+}
+
+/// Binary protocol packet, requests and responses share the layout.
+#[derive(Debug, Default)]
+struct Packet {
+    opcode: u8,
+    key: Vec<u8>,
+    extras: Vec<u8>,
+    value: Vec<u8>,
+    /// Status of a response, unused in requests
+    status: u16,
+    opaque: u32,
+    cas: u64,
+}
+
+impl Packet {
+    fn request(opcode: u8, key: &[u8]) -> Self {
+        Self {
+            opcode,
+            key: key.to_vec(),
+            opaque: 0xdead_beef,
+            ..Default::default()
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let body_length = self.extras.len() + self.key.len() + self.value.len();
+        let mut bytes = vec![0x80, self.opcode];
+        bytes.extend((self.key.len() as u16).to_be_bytes());
+        bytes.extend([self.extras.len() as u8, 0, 0, 0]);
+        bytes.extend((body_length as u32).to_be_bytes());
+        bytes.extend(self.opaque.to_be_bytes());
+        bytes.extend(self.cas.to_be_bytes());
+        bytes.extend(&self.extras);
+        bytes.extend(&self.key);
+        bytes.extend(&self.value);
+        bytes
+    }
+
+    fn read_from(reader: &mut impl Read) -> Self {
+        let mut header = [0; 24];
+        reader.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 0x81);
+        let key_length = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let extras_length = header[4] as usize;
+        let body_length = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+        let mut body = vec![0; body_length];
+        reader.read_exact(&mut body).unwrap();
+        Self {
+            opcode: header[1],
+            status: u16::from_be_bytes([header[6], header[7]]),
+            opaque: u32::from_be_bytes(header[12..16].try_into().unwrap()),
+            cas: u64::from_be_bytes(header[16..24].try_into().unwrap()),
+            extras: body[..extras_length].to_vec(),
+            key: body[extras_length..extras_length + key_length].to_vec(),
+            value: body[extras_length + key_length..].to_vec(),
+        }
+    }
+}
+
+const OP_GET: u8 = 0x00;
+const OP_SET: u8 = 0x01;
+const OP_ADD: u8 = 0x02;
+const OP_DELETE: u8 = 0x04;
+const OP_GETQ: u8 = 0x09;
+const OP_NOOP: u8 = 0x0a;
+const OP_GETK: u8 = 0x0c;
+const OP_SETQ: u8 = 0x11;
+const OP_TOUCH: u8 = 0x1c;
+const OP_GAT: u8 = 0x1d;
+
+const STATUS_OK: u16 = 0x0000;
+const STATUS_KEY_NOT_FOUND: u16 = 0x0001;
+const STATUS_KEY_EXISTS: u16 = 0x0002;
+const STATUS_INVALID_ARGUMENTS: u16 = 0x0004;
+const STATUS_UNKNOWN_COMMAND: u16 = 0x0081;
+
+fn set_extras(flags: u32, exptime: u32) -> Vec<u8> {
+    [flags.to_be_bytes(), exptime.to_be_bytes()].concat()
+}
+
+fn start(name: &str) -> (Arc<BitcaskHandler>, Arc<ManualClock>, String) {
+    let clock = Arc::new(ManualClock::new(1_700_000_000_000));
+    let options = Options {
+        read_write: true,
+        clock: clock.clone(),
+        ..Default::default()
+    };
+    let db = Arc::new(BitcaskHandler::open(&temp_dir(name), Some(options)).unwrap());
+    let address = serve(&db, server::serve_memcached);
+    (db, clock, address)
+}
+
+/// CAS unique in the reply to `gets` of a single key.
+fn cas_of(reply: &str) -> String {
+    reply.lines().next().unwrap().split(' ').nth(4).unwrap().to_string()
+}
+
+#[test]
+fn text_set_get_and_delete() {
+    let (db, _, address) = start("memcached-text");
+    let mut connection = TextConnection::open(&address);
+    assert_eq!(connection.call("get a\r\n"), "END\r\n");
+    assert_eq!(connection.call("set a 42 0 5\r\nhello\r\n"), "STORED\r\n");
+    assert_eq!(connection.call("get a missing\r\n"), "VALUE a 42 5\r\nhello\r\nEND\r\n");
+    assert_eq!(db.get(b"a").unwrap(), b"hello");
+
+    assert_eq!(connection.call("add a 0 0 1\r\nx\r\n"), "NOT_STORED\r\n");
+    assert_eq!(connection.call("replace b 0 0 1\r\nx\r\n"), "NOT_STORED\r\n");
+    assert_eq!(connection.call("add b 0 0 1\r\nx\r\n"), "STORED\r\n");
+    assert_eq!(connection.call("replace b 7 0 1\r\ny\r\n"), "STORED\r\n");
+    assert_eq!(connection.call("get b\r\n"), "VALUE b 7 1\r\ny\r\nEND\r\n");
+
+    // Nothing is sent back with noreply
+    assert_eq!(connection.call("set c 0 0 1 noreply\r\nz\r\nget c\r\n"), "VALUE c 0 1\r\nz\r\nEND\r\n");
+
+    assert_eq!(connection.call("delete a\r\n"), "DELETED\r\n");
+    assert_eq!(connection.call("delete a\r\n"), "NOT_FOUND\r\n");
+    assert!(connection.call("version\r\n").starts_with("VERSION "));
+    let stats = connection.call("stats\r\n");
+    assert!(stats.contains("STAT curr_items 2\r\n"), "{stats}");
+    assert!(stats.ends_with("END\r\n"));
+    connection.writer.write_all(b"quit\r\n").unwrap();
+    assert!(connection.is_closed());
+}
+
+#[test]
+fn text_cas_and_touch() {
+    let (_db, clock, address) = start("memcached-cas");
+    let mut connection = TextConnection::open(&address);
+    connection.call("set a 0 0 1\r\n1\r\n");
+    let cas = cas_of(&connection.call("gets a\r\n"));
+    assert_eq!(connection.call(&format!("cas a 0 0 1 {cas}\r\n2\r\n")), "STORED\r\n");
+    // The value changed since
+    assert_eq!(connection.call(&format!("cas a 0 0 1 {cas}\r\n3\r\n")), "EXISTS\r\n");
+    assert_eq!(connection.call("cas missing 0 0 1 1\r\n3\r\n"), "NOT_FOUND\r\n");
+    assert_eq!(connection.call("get a\r\n"), "VALUE a 0 1\r\n2\r\nEND\r\n");
+
+    assert_eq!(connection.call("set b 0 10 1\r\n1\r\n"), "STORED\r\n");
+    let cas = cas_of(&connection.call("gets a\r\n"));
+    assert_eq!(connection.call("touch a 10\r\n"), "TOUCHED\r\n");
+    assert_eq!(connection.call("touch missing 10\r\n"), "NOT_FOUND\r\n");
+    // Touching rewrites the value, its CAS unique changes
+    assert_ne!(cas_of(&connection.call("gets a\r\n")), cas);
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(connection.call("get a b\r\n"), "END\r\n");
+
+    // A negative expiration time expires right away
+    assert_eq!(connection.call("set c 0 -1 1\r\n1\r\n"), "STORED\r\n");
+    assert_eq!(connection.call("get c\r\n"), "END\r\n");
+}
+
+#[test]
+fn malformed_text_requests() {
+    let (_db, _, address) = start("memcached-malformed");
+    let mut connection = TextConnection::open(&address);
+    assert_eq!(connection.call("bogus\r\n"), "ERROR\r\n");
+    assert_eq!(connection.call("\r\n"), "ERROR\r\n");
+    assert_eq!(connection.call("get\r\n"), "ERROR\r\n");
+    assert_eq!(connection.call("set a 0 0\r\n"), "CLIENT_ERROR bad command line format\r\n");
+    assert_eq!(connection.call("set a x 0 1\r\n"), "CLIENT_ERROR bad command line format\r\n");
+    assert_eq!(connection.call("touch a soon\r\n"), "CLIENT_ERROR invalid exptime argument\r\n");
+    let long_key = "k".repeat(251);
+    assert_eq!(connection.call(&format!("set {long_key} 0 0 1\r\n1\r\n")), "CLIENT_ERROR key too long\r\n");
+    // The data of a value too large is skipped, the connection goes on
+    let large = "x".repeat(1024 * 1024 + 1);
+    let reply = connection.call(&format!("set a 0 0 {}\r\n{large}\r\n", large.len()));
+    assert_eq!(reply, "SERVER_ERROR object too large for cache\r\n");
+    assert_eq!(connection.call("get a\r\n"), "END\r\n");
+
+    // Data that doesn't end where announced can't be resynced, the connection is closed
+    assert_eq!(connection.call("set a 0 0 1\r\nabc\r\n"), "CLIENT_ERROR bad data chunk\r\n");
+    assert!(connection.is_closed());
+
+    let mut connection = TextConnection::open(&address);
+    let reply = connection.call(&format!("get {}", "k".repeat(4096)));
+    assert_eq!(reply, "CLIENT_ERROR line too long\r\n");
+    assert!(connection.is_closed());
+}
+
+#[test]
+fn binary_commands() {
+    let (_db, clock, address) = start("memcached-binary");
+    let mut stream = TcpStream::connect(&address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut call = |request: Packet| {
+        stream.write_all(&request.encode()).unwrap();
+        Packet::read_from(&mut stream)
+    };
+
+    let response = call(Packet::request(OP_GET, b"a"));
+    assert_eq!(response.status, STATUS_KEY_NOT_FOUND);
+    assert_eq!(response.opaque, 0xdead_beef);
+
+    let mut set = Packet::request(OP_SET, b"a");
+    set.extras = set_extras(7, 0);
+    set.value = b"hello".to_vec();
+    let response = call(set);
+    assert_eq!(response.status, STATUS_OK);
+    let cas = response.cas;
+    assert_ne!(cas, 0);
+
+    let response = call(Packet::request(OP_GETK, b"a"));
+    assert_eq!((response.status, response.opcode), (STATUS_OK, OP_GETK));
+    assert_eq!(response.key, b"a");
+    assert_eq!(response.value, b"hello");
+    assert_eq!(response.extras, 7u32.to_be_bytes());
+    assert_eq!(response.cas, cas);
+    assert!(call(Packet::request(OP_GET, b"a")).key.is_empty());
+
+    let mut add = Packet::request(OP_ADD, b"a");
+    add.extras = set_extras(0, 0);
+    assert_eq!(call(add).status, STATUS_KEY_EXISTS);
+
+    // Compare-and-swap with the CAS unique of the value
+    let mut stale = Packet::request(OP_SET, b"a");
+    stale.extras = set_extras(0, 0);
+    stale.cas = cas + 100;
+    assert_eq!(call(stale).status, STATUS_KEY_EXISTS);
+    let mut swap = Packet::request(OP_SET, b"a");
+    swap.extras = set_extras(0, 0);
+    swap.value = b"world".to_vec();
+    swap.cas = cas;
+    assert_eq!(call(swap).status, STATUS_OK);
+
+    let mut touch = Packet::request(OP_TOUCH, b"a");
+    touch.extras = 10u32.to_be_bytes().to_vec();
+    assert_eq!(call(touch).status, STATUS_OK);
+    let mut gat = Packet::request(OP_GAT, b"a");
+    gat.extras = 20u32.to_be_bytes().to_vec();
+    let response = call(gat);
+    assert_eq!((response.status, response.value.as_slice()), (STATUS_OK, b"world".as_slice()));
+    clock.advance(Duration::from_secs(20));
+    assert_eq!(call(Packet::request(OP_GET, b"a")).status, STATUS_KEY_NOT_FOUND);
+
+    let mut delete = Packet::request(OP_DELETE, b"a");
+    assert_eq!(call(delete).status, STATUS_KEY_NOT_FOUND);
+    let mut set = Packet::request(OP_SET, b"b");
+    set.extras = set_extras(0, 0);
+    call(set);
+    delete = Packet::request(OP_DELETE, b"b");
+    assert_eq!(call(delete).status, STATUS_OK);
+}
+
+#[test]
+fn quiet_binary_commands_only_report_failures() {
+    let (db, _, address) = start("memcached-quiet");
+    let mut stream = TcpStream::connect(&address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+
+    // A pipeline of quiet commands closed by a noop: only the hit and the noop are answered
+    let mut set = Packet::request(OP_SETQ, b"a");
+    set.extras = set_extras(0, 0);
+    set.value = b"1".to_vec();
+    let mut pipeline = set.encode();
+    pipeline.extend(Packet::request(OP_GETQ, b"missing").encode());
+    pipeline.extend(Packet::request(OP_GETQ, b"a").encode());
+    pipeline.extend(Packet::request(OP_NOOP, b"").encode());
+    stream.write_all(&pipeline).unwrap();
+
+    let hit = Packet::read_from(&mut stream);
+    assert_eq!((hit.opcode, hit.status, hit.value.as_slice()), (OP_GETQ, STATUS_OK, b"1".as_slice()));
+    let noop = Packet::read_from(&mut stream);
+    assert_eq!((noop.opcode, noop.status), (OP_NOOP, STATUS_OK));
+    assert_eq!(db.get(b"a").unwrap(), b"1");
+}
+
+#[test]
+fn malformed_binary_requests() {
+    let (_db, _, address) = start("memcached-binary-malformed");
+    let mut stream = TcpStream::connect(&address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut call = |request: &[u8]| {
+        stream.write_all(request).unwrap();
+        Packet::read_from(&mut stream)
+    };
+
+    assert_eq!(call(&Packet::request(0x42, b"a").encode()).status, STATUS_UNKNOWN_COMMAND);
+    // A get doesn't take extras
+    let mut get = Packet::request(OP_GET, b"a");
+    get.extras = vec![0; 4];
+    assert_eq!(call(&get.encode()).status, STATUS_INVALID_ARGUMENTS);
+    // A set needs its flags and expiration time
+    assert_eq!(call(&Packet::request(OP_SET, b"a").encode()).status, STATUS_INVALID_ARGUMENTS);
+    // The key is longer than the body
+    let mut request = Packet::request(OP_GET, b"a").encode();
+    request[2..4].copy_from_slice(&10u16.to_be_bytes());
+    assert_eq!(call(&request).status, STATUS_INVALID_ARGUMENTS);
+    assert_eq!(call(&Packet::request(OP_GET, &[b'k'; 251]).encode()).status, STATUS_INVALID_ARGUMENTS);
+
+    // Still served afterwards
+    assert_eq!(call(&Packet::request(OP_NOOP, b"").encode()).status, STATUS_OK);
+}
+
+#[test]
+fn values_written_by_memcached_clients_are_readable_by_the_handler() {
+    let db = Arc::new(BitcaskHandler::open(&temp_dir("memcached-handler"), read_write()).unwrap());
+    db.put_with_flags(b"a", b"1", 5, None).unwrap();
+    let address = serve(&db, server::serve_memcached);
+    let mut connection = TextConnection::open(&address);
+    assert_eq!(connection.call("get a\r\n"), "VALUE a 5 1\r\n1\r\nEND\r\n");
+}
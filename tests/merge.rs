@@ -0,0 +1,66 @@
+use bitcask::BitcaskHandler;
+
+use common::{read_write, temp_dir};
+
+mod common;
+
+fn sorted_pairs(db: &BitcaskHandler) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut pairs: Vec<_> = db.iter().unwrap().map(Result::unwrap).collect();
+    pairs.sort();
+    pairs
+}
+
+fn versions(db: &BitcaskHandler, keys: &[&[u8]]) -> Vec<u64> {
+    keys.iter().map(|key| db.get_with_meta(key).unwrap().version).collect()
+}
+
+#[test]
+fn merge_and_reopen_preserve_data_and_versions() {
+    let dir = temp_dir("merge-reopen");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    db.put(b"a", b"1").unwrap();
+    db.put(b"b", b"2").unwrap();
+    db.put(b"c", b"3").unwrap();
+    db.put(b"a", b"11").unwrap();
+    db.delete(b"c").unwrap();
+    db.put(b"d", b"4").unwrap();
+    let last_version = db.get_with_meta(b"d").unwrap().version;
+    // The newest write is gone once merged, its version must not be handed out again
+    db.delete(b"d").unwrap();
+    let pairs = sorted_pairs(&db);
+    let kept_versions = versions(&db, &[b"a", b"b"]);
+
+    db.merge().unwrap();
+    assert_eq!(sorted_pairs(&db), pairs);
+    assert_eq!(versions(&db, &[b"a", b"b"]), kept_versions);
+    drop(db);
+
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert_eq!(sorted_pairs(&db), pairs);
+    assert_eq!(versions(&db, &[b"a", b"b"]), kept_versions);
+    db.put(b"e", b"5").unwrap();
+    assert!(db.get_with_meta(b"e").unwrap().version > last_version + 1);
+    drop(db);
+
+    // Writes after a merge land next to the merged files
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert_eq!(db.get(b"e").unwrap(), b"5");
+    assert_eq!(versions(&db, &[b"a", b"b"]), kept_versions);
+}
+
+#[test]
+fn versions_keep_increasing_after_merging_everything_away() {
+    let dir = temp_dir("merge-empty");
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    db.put(b"a", b"1").unwrap();
+    let deleted_version = db.get_with_meta(b"a").unwrap().version;
+    db.delete(b"a").unwrap();
+    db.merge().unwrap();
+    assert!(db.is_empty());
+    drop(db);
+
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert!(db.is_empty());
+    db.put(b"b", b"2").unwrap();
+    assert!(db.get_with_meta(b"b").unwrap().version > deleted_version + 1);
+}
@@ -0,0 +1,71 @@
+use std::fs;
+
+use bitcask::{BitcaskHandler, Options, WriteBatch};
+
+use common::{first_data_file, flip_bit_in, temp_dir};
+
+mod common;
+
+const PREALLOCATED_SIZE: usize = 64 * 1024; // 64 KB
+
+fn preallocated() -> Option<Options> {
+    Some(Options {
+        read_write: true,
+        preallocate: true,
+        max_data_size: PREALLOCATED_SIZE,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn torn_batch_in_a_preallocated_file_cuts_the_padding() {
+    let dir = temp_dir("preallocated-torn-batch");
+    let db = BitcaskHandler::open(&dir, preallocated()).unwrap();
+    db.put(b"kept", b"1").unwrap();
+    let mut batch = WriteBatch::new();
+    batch.put(b"a", b"value of a").put(b"b", b"value of b");
+    db.write_batch(batch).unwrap();
+    db.sync().unwrap();
+
+    // Copied while still open, the file is in the state a crash would leave it in
+    let crashed_dir = temp_dir("preallocated-torn-batch-crashed");
+    let file_path = first_data_file(&crashed_dir);
+    fs::copy(first_data_file(&dir), &file_path).unwrap();
+    drop(db);
+    assert_eq!(fs::metadata(&file_path).unwrap().len(), PREALLOCATED_SIZE as u64);
+    flip_bit_in(&file_path, b"value of b");
+
+    let db = BitcaskHandler::open(&crashed_dir, preallocated()).unwrap();
+    assert_eq!(db.get(b"kept").unwrap(), b"1");
+    assert!(db.get(b"a").is_err());
+    let cut_size = fs::metadata(&file_path).unwrap().len();
+    assert!(cut_size < PREALLOCATED_SIZE as u64);
+    db.put(b"c", b"2").unwrap();
+    drop(db);
+
+    let db = BitcaskHandler::open(&crashed_dir, preallocated()).unwrap();
+    assert_eq!(db.get(b"kept").unwrap(), b"1");
+    assert_eq!(db.get(b"c").unwrap(), b"2");
+    assert_eq!(fs::metadata(&file_path).unwrap().len(), cut_size);
+}
+
+#[test]
+fn zero_filled_working_file_is_emptied() {
+    let dir = temp_dir("zero-filled");
+    let db = BitcaskHandler::open(&dir, preallocated()).unwrap();
+    db.put(b"kept", b"1").unwrap();
+    drop(db);
+    // Preallocated by a crashed process before its header reached the disk
+    let zero_filled_path = dir.join("working_file_1");
+    fs::write(&zero_filled_path, vec![0; PREALLOCATED_SIZE]).unwrap();
+
+    let db = BitcaskHandler::open(&dir, preallocated()).unwrap();
+    assert_eq!(db.get(b"kept").unwrap(), b"1");
+    assert_eq!(fs::metadata(&zero_filled_path).unwrap().len(), 0);
+    db.put(b"c", b"2").unwrap();
+    drop(db);
+
+    let db = BitcaskHandler::open(&dir, preallocated()).unwrap();
+    assert_eq!(db.get(b"kept").unwrap(), b"1");
+    assert_eq!(db.get(b"c").unwrap(), b"2");
+}
@@ -0,0 +1,89 @@
+use std::{ops::Bound, path::Path};
+
+use bitcask::{BitcaskHandler, IndexKind, Options};
+
+use common::{read_write, temp_dir};
+
+mod common;
+
+fn ordered() -> Option<Options> {
+    Some(Options {
+        read_write: true,
+        index: IndexKind::Ordered,
+        ..Default::default()
+    })
+}
+
+fn open_with_users(dir: &Path) -> BitcaskHandler {
+    let db = BitcaskHandler::open(dir, ordered()).unwrap();
+    for name in ["user:3", "user:1", "order:1", "user:2", "zebra", "user:10"] {
+        db.put(name.as_bytes(), name.to_uppercase().as_bytes()).unwrap();
+    }
+    db
+}
+
+fn range_keys(db: &BitcaskHandler, range: (Bound<&str>, Bound<&str>)) -> Vec<String> {
+    db.range::<&str>(range)
+        .unwrap()
+        .map(|pair| String::from_utf8(pair.unwrap().0).unwrap())
+        .collect()
+}
+
+#[test]
+fn ordered_index_lists_keys_in_order() {
+    let dir = temp_dir("ordered");
+    let db = open_with_users(&dir);
+    let keys: Vec<Vec<u8>> = db.keys().collect();
+    let expected = ["order:1", "user:1", "user:10", "user:2", "user:3", "zebra"].map(|key| key.as_bytes().to_vec());
+    assert_eq!(keys, expected);
+    let reversed: Vec<Vec<u8>> = db.keys().rev().collect();
+    assert_eq!(reversed, expected.iter().rev().cloned().collect::<Vec<_>>());
+
+    // Rebuilt in order when reopened
+    drop(db);
+    let db = BitcaskHandler::open(&dir, ordered()).unwrap();
+    assert_eq!(db.keys().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn range_and_prefix_scan_in_key_order() {
+    let db = open_with_users(&temp_dir("range"));
+    use Bound::{Excluded, Included, Unbounded};
+    assert_eq!(range_keys(&db, (Included("user:1"), Excluded("user:3"))), ["user:1", "user:10", "user:2"]);
+    assert_eq!(range_keys(&db, (Excluded("user:1"), Included("user:3"))), ["user:10", "user:2", "user:3"]);
+    assert_eq!(range_keys(&db, (Included("user:3"), Unbounded)), ["user:3", "zebra"]);
+    assert_eq!(range_keys(&db, (Unbounded, Excluded("user:1"))), ["order:1"]);
+
+    let pairs: Vec<(Vec<u8>, Vec<u8>)> = db.prefix(b"user:").unwrap().map(Result::unwrap).collect();
+    assert_eq!(pairs.len(), 4);
+    assert_eq!(pairs[0], (b"user:1".to_vec(), b"USER:1".to_vec()));
+    let last_two: Vec<Vec<u8>> = db.prefix(b"user:").unwrap().rev().take(2).map(|pair| pair.unwrap().0).collect();
+    assert_eq!(last_two, [b"user:3".to_vec(), b"user:2".to_vec()]);
+
+    // Pagination resuming after the last key of a page
+    let first_page: Vec<Vec<u8>> = db.range::<&[u8]>(..).unwrap().take(2).map(|pair| pair.unwrap().0).collect();
+    let last_key = first_page.last().unwrap().clone();
+    let next_page = db.range((Excluded(last_key), Unbounded)).unwrap().next().unwrap().unwrap();
+    assert_eq!(next_page.0, b"user:10");
+}
+
+#[test]
+fn empty_and_reversed_ranges_are_empty() {
+    let db = open_with_users(&temp_dir("range-empty"));
+    use Bound::{Excluded, Included};
+    assert!(range_keys(&db, (Included("user:3"), Excluded("user:1"))).is_empty());
+    assert!(range_keys(&db, (Included("user:3"), Excluded("user:3"))).is_empty());
+    assert!(range_keys(&db, (Excluded("user:3"), Included("user:3"))).is_empty());
+    assert_eq!(range_keys(&db, (Included("user:3"), Included("user:3"))), ["user:3"]);
+    assert_eq!(db.prefix(b"missing:").unwrap().count(), 0);
+    assert_eq!(db.prefix(&[0xff]).unwrap().count(), 0);
+}
+
+#[test]
+fn range_scans_require_an_ordered_index() {
+    let db = BitcaskHandler::open(&temp_dir("range-hash"), read_write()).unwrap();
+    db.put(b"a", b"1").unwrap();
+    let error = db.range::<&[u8]>(..).err().unwrap();
+    assert!(error.to_string().contains("ordered index"), "{error}");
+    assert!(db.prefix(b"a").is_err());
+}
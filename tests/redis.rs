@@ -0,0 +1,237 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    time::Duration,
+};
+
+use bitcask::{BitcaskHandler, IndexKind, ManualClock, Options, server};
+
+use common::{read_write, serve, temp_dir};
+
+mod common;
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+use Reply::{Array, Bulk, Error, Integer, Simple};
+
+fn bulk(bytes: &[u8]) -> Reply {
+    Bulk(Some(bytes.to_vec()))
+}
+
+fn ok() -> Reply {
+    Simple("OK".to_string())
+}
+
+/// RESP2 connection sending commands as arrays of bulk strings.
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn open(address: &str) -> Self {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        Self {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    fn call(&mut self, args: &[&str]) -> Reply {
+        let mut command = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            command.extend(format!("${}\r\n{arg}\r\n", arg.len()).into_bytes());
+        }
+        self.writer.write_all(&command).unwrap();
+        self.read_reply()
+    }
+
+    fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        line.trim_end_matches("\r\n").to_string()
+    }
+
+    fn read_reply(&mut self) -> Reply {
+        let line = self.read_line();
+        let (kind, rest) = line.split_at(1);
+        match kind {
+            "+" => Simple(rest.to_string()),
+            "-" => Error(rest.to_string()),
+            ":" => Integer(rest.parse().unwrap()),
+            "$" if rest == "-1" => Bulk(None),
+            "$" => {
+                let mut data = vec![0; rest.parse::<usize>().unwrap() + 2];
+                self.reader.read_exact(&mut data).unwrap();
+                data.truncate(data.len() - 2);
+                Bulk(Some(data))
+            }
+            "*" => Array((0..rest.parse().unwrap()).map(|_| self.read_reply()).collect()),
+            _ => panic!("Unexpected reply {line:?}"),
+        }
+    }
+
+    /// Runs one `SCAN` call, returns the next cursor and the page of keys.
+    fn scan(&mut self, cursor: &str, options: &[&str]) -> (String, Vec<String>) {
+        let reply = self.call(&[&["SCAN", cursor], options].concat());
+        let Array(reply) = reply else {
+            panic!("Unexpected SCAN reply {reply:?}");
+        };
+        let [Bulk(Some(next_cursor)), Array(page)] = reply.as_slice() else {
+            panic!("Unexpected SCAN reply {reply:?}");
+        };
+        let page = page.iter().map(|key| match key {
+            Bulk(Some(key)) => String::from_utf8(key.clone()).unwrap(),
+            _ => panic!("Unexpected key {key:?}"),
+        });
+        (String::from_utf8(next_cursor.clone()).unwrap(), page.collect())
+    }
+
+    /// Runs `SCAN` from `cursor` until it comes back to 0, returns the keys in the order they came.
+    fn scan_from(&mut self, mut cursor: String, options: &[&str]) -> Vec<String> {
+        let mut keys = Vec::new();
+        while cursor != "0" {
+            let (next_cursor, page) = self.scan(&cursor, options);
+            keys.extend(page);
+            cursor = next_cursor;
+        }
+        keys
+    }
+}
+
+fn start(name: &str, options: Option<Options>) -> (Arc<BitcaskHandler>, Connection) {
+    let db = Arc::new(BitcaskHandler::open(&temp_dir(name), options).unwrap());
+    let address = serve(&db, server::serve_redis);
+    (db, Connection::open(&address))
+}
+
+#[test]
+fn get_set_and_del() {
+    let (_db, mut connection) = start("get-set", read_write());
+    assert_eq!(connection.call(&["PING"]), Simple("PONG".to_string()));
+    assert_eq!(connection.call(&["GET", "a"]), Bulk(None));
+    assert_eq!(connection.call(&["SET", "a", "1"]), ok());
+    assert_eq!(connection.call(&["GET", "a"]), bulk(b"1"));
+    assert_eq!(connection.call(&["DEL", "a", "missing"]), Integer(1));
+    assert_eq!(connection.call(&["GET", "a"]), Bulk(None));
+    assert!(matches!(connection.call(&["GET"]), Error(message) if message.contains("wrong number")));
+    assert!(matches!(connection.call(&["NOPE"]), Error(message) if message.contains("unknown command")));
+}
+
+#[test]
+fn set_conditions_and_expiry() {
+    let clock = Arc::new(ManualClock::new(1_000_000));
+    let options = Options {
+        read_write: true,
+        clock: clock.clone(),
+        ..Default::default()
+    };
+    let (_db, mut connection) = start("set-options", Some(options));
+
+    assert_eq!(connection.call(&["SET", "a", "1", "XX"]), Bulk(None));
+    assert_eq!(connection.call(&["SET", "a", "1", "NX"]), ok());
+    assert_eq!(connection.call(&["SET", "a", "2", "NX"]), Bulk(None));
+    assert_eq!(connection.call(&["SET", "a", "2", "XX"]), ok());
+    assert_eq!(connection.call(&["GET", "a"]), bulk(b"2"));
+
+    assert_eq!(connection.call(&["SET", "seconds", "1", "EX", "10"]), ok());
+    assert_eq!(connection.call(&["SET", "millis", "1", "PX", "500"]), ok());
+    assert!(matches!(connection.call(&["SET", "b", "1", "EX", "0"]), Error(message) if message.contains("expire time")));
+    assert!(matches!(connection.call(&["SET", "b", "1", "NX", "XX"]), Error(message) if message.contains("syntax")));
+
+    clock.advance(Duration::from_millis(500));
+    assert_eq!(connection.call(&["GET", "millis"]), Bulk(None));
+    assert_eq!(connection.call(&["GET", "seconds"]), bulk(b"1"));
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(connection.call(&["GET", "seconds"]), Bulk(None));
+    // An expired key can be set again with NX
+    assert_eq!(connection.call(&["SET", "seconds", "2", "NX"]), ok());
+}
+
+#[test]
+fn mget_mset_and_info() {
+    let (db, mut connection) = start("mget-mset", read_write());
+    assert_eq!(connection.call(&["MSET", "a", "1", "b", "2"]), ok());
+    assert_eq!(connection.call(&["MGET", "a", "missing", "b"]), Array(vec![bulk(b"1"), Bulk(None), bulk(b"2")]));
+    assert!(matches!(connection.call(&["MSET", "a"]), Error(message) if message.contains("wrong number")));
+    assert_eq!(connection.call(&["SET", "c", "3", "EX", "100"]), ok());
+    assert_eq!(connection.call(&["DBSIZE"]), Integer(3));
+
+    let Bulk(Some(info)) = connection.call(&["INFO"]) else {
+        panic!("INFO didn't reply with a bulk string");
+    };
+    let info = String::from_utf8(info).unwrap();
+    assert!(info.contains("db0:keys=3,expires=1\r\n"), "{info}");
+    assert!(info.contains("aof_last_bgrewrite_status:ok\r\n"), "{info}");
+    assert_eq!(db.get(b"b").unwrap(), b"2");
+}
+
+#[test]
+fn scan_returns_every_key_once_despite_writes() {
+    let options = Options {
+        read_write: true,
+        index: IndexKind::Ordered,
+        ..Default::default()
+    };
+    let (_db, mut connection) = start("scan", Some(options));
+    let keys: Vec<String> = (0..50).map(|i| format!("key:{i:02}")).collect();
+    for key in &keys {
+        connection.call(&["SET", key, "1"]);
+    }
+    connection.call(&["SET", "other", "1"]);
+
+    let (cursor, first_page) = connection.scan("0", &["COUNT", "10"]);
+    assert_eq!(first_page, keys[..10]);
+    // Deleting keys already returned doesn't make the scan skip the next ones
+    for key in &keys[..10] {
+        connection.call(&["DEL", key]);
+    }
+    connection.call(&["SET", "key:new", "1"]);
+    let mut expected = keys[10..].to_vec();
+    expected.push("other".to_string());
+    assert_eq!(connection.scan_from(cursor, &["COUNT", "10"]), expected);
+
+    let (cursor, mut matching) = connection.scan("0", &["MATCH", "key:[0-1]?", "COUNT", "7"]);
+    matching.extend(connection.scan_from(cursor, &["MATCH", "key:[0-1]?", "COUNT", "7"]));
+    let expected: Vec<String> = (10..20).map(|i| format!("key:{i}")).collect();
+    assert_eq!(matching, expected);
+    assert!(matches!(connection.call(&["SCAN", "12345"]), Error(message) if message.contains("invalid cursor")));
+}
+
+#[test]
+fn keys_matches_glob_patterns() {
+    let (_db, mut connection) = start("keys", read_write());
+    for key in ["hello", "hallo", "hxllo", "heeeello", "h*llo"] {
+        connection.call(&["SET", key, "1"]);
+    }
+    let mut keys = |pattern: &str| {
+        let Array(keys) = connection.call(&["KEYS", pattern]) else {
+            panic!("KEYS didn't reply with an array");
+        };
+        let mut keys: Vec<String> = keys
+            .into_iter()
+            .map(|key| match key {
+                Bulk(Some(key)) => String::from_utf8(key).unwrap(),
+                _ => panic!("Unexpected key {key:?}"),
+            })
+            .collect();
+        keys.sort();
+        keys
+    };
+    assert_eq!(keys("h?llo"), ["h*llo", "hallo", "hello", "hxllo"]);
+    assert_eq!(keys("h*llo"), ["h*llo", "hallo", "heeeello", "hello", "hxllo"]);
+    assert_eq!(keys("h[ae]llo"), ["hallo", "hello"]);
+    assert_eq!(keys("h[^e]llo"), ["h*llo", "hallo", "hxllo"]);
+    assert_eq!(keys("h[a-e]llo"), ["hallo", "hello"]);
+    assert_eq!(keys("h\\*llo"), ["h*llo"]);
+    assert_eq!(keys("*e*e*e*e*x"), Vec::<String>::new());
+}
@@ -0,0 +1,108 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bitcask::{BitcaskHandler, replication::Follower, server};
+
+use common::{read_write, temp_dir};
+
+mod common;
+
+const CONVERGENCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn start_leader(name: &str) -> (Arc<BitcaskHandler>, String) {
+    let db = Arc::new(BitcaskHandler::open(&temp_dir(name), read_write()).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let served = Arc::clone(&db);
+    thread::spawn(move || server::serve_replication(listener, served));
+    (db, address)
+}
+
+fn sorted_pairs(db: &BitcaskHandler) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut pairs: Vec<_> = db.iter().unwrap().map(Result::unwrap).collect();
+    pairs.sort();
+    pairs
+}
+
+fn assert_converges(leader: &BitcaskHandler, follower: &BitcaskHandler) {
+    let deadline = Instant::now() + CONVERGENCE_TIMEOUT;
+    while sorted_pairs(follower) != sorted_pairs(leader) {
+        assert!(Instant::now() < deadline, "The follower didn't catch up with the leader");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn follower_converges_with_writes_deletes_and_merges() {
+    let (leader, address) = start_leader("converge-leader");
+    leader.put(b"a", b"1").unwrap();
+    leader.put(b"b", b"2").unwrap();
+    leader.delete(b"a").unwrap();
+
+    let follower = Follower::start(address.as_str(), &temp_dir("converge-follower"), None).unwrap();
+    assert_converges(&leader, &follower.db());
+
+    // Tailing the working file
+    leader.put(b"c", b"3").unwrap();
+    leader.put(b"b", b"22").unwrap();
+    assert_converges(&leader, &follower.db());
+
+    // Merged files replace the ones the follower has
+    leader.delete(b"c").unwrap();
+    leader.merge().unwrap();
+    leader.put(b"d", b"4").unwrap();
+    assert_converges(&leader, &follower.db());
+    assert!(follower.db().get(b"a").is_err());
+    assert!(follower.db().get(b"c").is_err());
+    assert!(follower.last_error().is_none());
+}
+
+#[test]
+fn follower_rejects_writes_until_promoted() {
+    let (leader, address) = start_leader("promote-leader");
+    leader.put(b"a", b"1").unwrap();
+
+    let follower = Follower::start(address.as_str(), &temp_dir("promote-follower"), None).unwrap();
+    assert_converges(&leader, &follower.db());
+    assert!(follower.db().put(b"b", b"2").is_err());
+
+    let promoted = follower.promote().unwrap();
+    promoted.put(b"b", b"2").unwrap();
+    assert_eq!(promoted.get(b"a").unwrap(), b"1");
+    assert_eq!(promoted.get(b"b").unwrap(), b"2");
+    // Versions go on after the replicated ones
+    assert!(promoted.get_with_meta(b"b").unwrap().version > promoted.get_with_meta(b"a").unwrap().version);
+}
+
+#[test]
+fn oversized_shipments_are_refused_before_allocating() {
+    // A leader announcing a chunk of 1 TB
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_exact(&mut [0; 2]).unwrap();
+        // Chunk of file 0 at offset 0, then the length as a varint u64
+        let mut shipment = vec![0, 0, 0, 0xfd];
+        shipment.extend((1u64 << 40).to_le_bytes());
+        stream.write_all(&shipment).unwrap();
+        thread::sleep(CONVERGENCE_TIMEOUT);
+    });
+
+    let follower = Follower::start(address.as_str(), &temp_dir("oversized-follower"), None).unwrap();
+    let deadline = Instant::now() + CONVERGENCE_TIMEOUT;
+    let error = loop {
+        if let Some(error) = follower.last_error() {
+            break error;
+        }
+        assert!(Instant::now() < deadline, "The follower didn't refuse the shipment");
+        thread::sleep(Duration::from_millis(20));
+    };
+    assert!(error.contains("Limit"), "{error}");
+    assert!(follower.db().is_empty());
+}
@@ -0,0 +1,71 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use bitcask::{BitcaskError, BitcaskHandler, KeyValueStore, MemoryStore, client::Client, server};
+
+use common::{bitcask_error, read_write, serve, temp_dir};
+
+mod common;
+
+fn sorted_pairs(store: &dyn KeyValueStore) -> BTreeMap<Vec<u8>, Vec<u8>> {
+    store.iter().unwrap().map(Result::unwrap).collect()
+}
+
+/// Behavior every implementation of the trait must share, starting from an empty store.
+fn check_conformance(store: &dyn KeyValueStore) {
+    assert!(store.list_keys().unwrap().is_empty());
+    assert!(store.iter().unwrap().next().is_none());
+    assert!(matches!(bitcask_error(store.get(b"missing")), BitcaskError::KeyNotFound));
+    assert!(matches!(bitcask_error(store.delete(b"missing")), BitcaskError::KeyNotFound));
+
+    store.put(b"a", b"1").unwrap();
+    store.put(b"a", b"2").unwrap();
+    store.put(b"b", b"").unwrap();
+    store.put(b"\x00\xff", b"\r\n\x00").unwrap();
+    assert_eq!(store.get(b"a").unwrap(), b"2");
+    assert_eq!(store.get(b"b").unwrap(), b"");
+    assert_eq!(store.get(b"\x00\xff").unwrap(), b"\r\n\x00");
+
+    store.delete(b"b").unwrap();
+    assert!(matches!(bitcask_error(store.get(b"b")), BitcaskError::KeyNotFound));
+    assert!(matches!(bitcask_error(store.delete(b"b")), BitcaskError::KeyNotFound));
+
+    let mut keys = store.list_keys().unwrap();
+    keys.sort();
+    assert_eq!(keys, [b"\x00\xff".to_vec(), b"a".to_vec()]);
+    let expected = BTreeMap::from([(b"\x00\xff".to_vec(), b"\r\n\x00".to_vec()), (b"a".to_vec(), b"2".to_vec())]);
+    assert_eq!(sorted_pairs(store), expected);
+
+    // Keys written once iteration started aren't visited
+    let mut iter = store.iter().unwrap();
+    let first = iter.next().unwrap().unwrap();
+    store.put(b"c", b"3").unwrap();
+    let rest: Vec<_> = iter.map(Result::unwrap).collect();
+    assert_eq!(rest.len(), 1);
+    assert_ne!(rest[0].0, first.0);
+    assert!(!rest.iter().any(|(key, _)| key == b"c"));
+    store.delete(b"c").unwrap();
+
+    // Merging and syncing keep every pair
+    store.merge().unwrap();
+    store.sync().unwrap();
+    assert_eq!(sorted_pairs(store), expected);
+}
+
+#[test]
+fn memory_store_conforms() {
+    check_conformance(&MemoryStore::new());
+}
+
+#[test]
+fn handler_conforms() {
+    let dir = temp_dir("store-handler");
+    check_conformance(&BitcaskHandler::open(&dir, read_write()).unwrap());
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert_eq!(KeyValueStore::get(&db, b"a").unwrap(), b"2");
+}
+
+#[test]
+fn client_conforms() {
+    let db = Arc::new(BitcaskHandler::open(&temp_dir("store-client"), read_write()).unwrap());
+    check_conformance(&Client::connect(serve(&db, server::serve_redis), None).unwrap());
+}
@@ -0,0 +1,74 @@
+use bitcask::{BitcaskError, BitcaskHandler};
+
+use common::{bitcask_error, read_write, temp_dir};
+
+mod common;
+
+#[test]
+fn transaction_applies_its_writes_together() {
+    let db = BitcaskHandler::open(&temp_dir("commit"), read_write()).unwrap();
+    db.put(b"from", b"10").unwrap();
+    db.transaction(|tx| {
+        assert_eq!(tx.get(b"from")?, Some(b"10".to_vec()));
+        tx.put(b"from", b"0");
+        tx.put(b"to", b"10");
+        // Reads see the transaction's own writes
+        assert_eq!(tx.get(b"to")?, Some(b"10".to_vec()));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(db.get(b"from").unwrap(), b"0");
+    assert_eq!(db.get(b"to").unwrap(), b"10");
+}
+
+#[test]
+fn concurrent_write_of_a_read_key_conflicts() {
+    let db = BitcaskHandler::open(&temp_dir("conflict"), read_write()).unwrap();
+    db.put(b"n", b"1").unwrap();
+    let result = db.transaction(|tx| {
+        tx.get(b"n")?;
+        db.put(b"n", b"2")?;
+        tx.put(b"n", b"3");
+        Ok(())
+    });
+    assert!(matches!(bitcask_error(result), BitcaskError::Conflict { .. }));
+    assert_eq!(db.get(b"n").unwrap(), b"2");
+
+    // A key missing when read conflicts once it's created
+    let result = db.transaction(|tx| {
+        tx.get(b"m")?;
+        db.put(b"m", b"1")?;
+        tx.put(b"m", b"2");
+        Ok(())
+    });
+    assert!(matches!(bitcask_error(result), BitcaskError::Conflict { .. }));
+}
+
+#[test]
+fn merge_between_read_and_commit_doesnt_conflict() {
+    let db = BitcaskHandler::open(&temp_dir("merge"), read_write()).unwrap();
+    db.put(b"n", b"1").unwrap();
+    db.put(b"other", b"1").unwrap();
+    db.put(b"other", b"2").unwrap();
+    db.transaction(|tx| {
+        tx.get(b"n")?;
+        tx.get(b"missing")?;
+        // Moves the entry of `n` to a merged file without changing it
+        db.merge()?;
+        tx.put(b"n", b"2");
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(db.get(b"n").unwrap(), b"2");
+}
+
+#[test]
+fn failed_transaction_writes_nothing() {
+    let db = BitcaskHandler::open(&temp_dir("abort"), read_write()).unwrap();
+    let result: anyhow::Result<()> = db.transaction(|tx| {
+        tx.put(b"a", b"1");
+        anyhow::bail!("aborted")
+    });
+    assert!(result.is_err());
+    assert!(db.get(b"a").is_err());
+}
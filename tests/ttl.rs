@@ -0,0 +1,131 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use bitcask::{BitcaskHandler, ManualClock, Options};
+
+use common::temp_dir;
+
+mod common;
+
+const START: u64 = 1_700_000_000_000;
+
+fn open(dir: &Path, clock: &Arc<ManualClock>) -> BitcaskHandler {
+    open_with_expiry(dir, clock, None)
+}
+
+fn open_with_expiry(dir: &Path, clock: &Arc<ManualClock>, expiry_secs: Option<u64>) -> BitcaskHandler {
+    let options = Options {
+        read_write: true,
+        clock: clock.clone(),
+        expiry_secs,
+        ..Default::default()
+    };
+    BitcaskHandler::open(dir, Some(options)).unwrap()
+}
+
+fn sorted_keys(db: &BitcaskHandler) -> Vec<Vec<u8>> {
+    let mut keys = db.list_keys().unwrap();
+    keys.sort();
+    keys
+}
+
+#[test]
+fn expired_keys_are_hidden_from_get_and_list_keys() {
+    let clock = Arc::new(ManualClock::new(START));
+    let db = open(&temp_dir("ttl-get"), &clock);
+    db.put_with_ttl(b"short", b"1", Duration::from_secs(10)).unwrap();
+    db.put_with_ttl(b"long", b"2", Duration::from_secs(60)).unwrap();
+    db.put(b"forever", b"3").unwrap();
+
+    clock.advance(Duration::from_secs(9));
+    assert_eq!(db.get(b"short").unwrap(), b"1");
+    assert_eq!(sorted_keys(&db), [b"forever".to_vec(), b"long".to_vec(), b"short".to_vec()]);
+
+    clock.advance(Duration::from_secs(1));
+    assert!(db.get(b"short").is_err());
+    assert!(!db.contains_key(b"short"));
+    assert_eq!(sorted_keys(&db), [b"forever".to_vec(), b"long".to_vec()]);
+
+    // Writing again without a TTL makes the key permanent
+    db.put(b"long", b"4").unwrap();
+    clock.advance(Duration::from_secs(3600));
+    assert_eq!(db.get(b"long").unwrap(), b"4");
+    assert_eq!(db.get(b"forever").unwrap(), b"3");
+}
+
+#[test]
+fn ttls_survive_reopening() {
+    let dir = temp_dir("ttl-reopen");
+    let clock = Arc::new(ManualClock::new(START));
+    let db = open(&dir, &clock);
+    db.put_with_ttl(b"a", b"1", Duration::from_secs(10)).unwrap();
+    db.put_with_ttl(b"b", b"2", Duration::from_secs(20)).unwrap();
+    drop(db);
+
+    clock.advance(Duration::from_secs(15));
+    let db = open(&dir, &clock);
+    assert!(db.get(b"a").is_err());
+    assert_eq!(db.get(b"b").unwrap(), b"2");
+    drop(db);
+
+    clock.advance(Duration::from_secs(5));
+    let db = open(&dir, &clock);
+    assert!(db.get(b"b").is_err());
+    assert!(db.is_empty());
+}
+
+#[test]
+fn merge_drops_expired_entries() {
+    let dir = temp_dir("ttl-merge");
+    let clock = Arc::new(ManualClock::new(START));
+    let db = open(&dir, &clock);
+    db.put_with_ttl(b"expiring", &[0; 1024], Duration::from_secs(10)).unwrap();
+    db.put(b"kept", b"1").unwrap();
+    let size_before = db.stats().unwrap().data_size;
+
+    clock.advance(Duration::from_secs(10));
+    db.merge().unwrap();
+    assert!(db.stats().unwrap().data_size < size_before - 1024);
+    assert_eq!(db.len(), 1);
+    drop(db);
+
+    // Going back in time doesn't bring it back, its entry is gone
+    clock.set(START);
+    let db = open(&dir, &clock);
+    assert!(db.get(b"expiring").is_err());
+    assert_eq!(db.get(b"kept").unwrap(), b"1");
+}
+
+#[test]
+fn expiry_secs_hides_old_entries_on_top_of_their_ttl() {
+    let dir = temp_dir("expiry-secs");
+    let clock = Arc::new(ManualClock::new(START));
+    let db = open_with_expiry(&dir, &clock, Some(60));
+    db.put(b"old", b"1").unwrap();
+    db.put_with_ttl(b"short", b"2", Duration::from_secs(10)).unwrap();
+    clock.advance(Duration::from_secs(30));
+    db.put(b"new", b"3").unwrap();
+
+    clock.advance(Duration::from_secs(10));
+    assert!(db.get(b"short").is_err());
+    assert_eq!(db.get(b"old").unwrap(), b"1");
+
+    clock.advance(Duration::from_secs(20));
+    assert!(db.get(b"old").is_err());
+    assert_eq!(sorted_keys(&db), [b"new".to_vec()]);
+    drop(db);
+
+    // Age is counted from the write, reopening without the option shows the old entry again
+    let db = open(&dir, &clock);
+    assert_eq!(db.get(b"old").unwrap(), b"1");
+    drop(db);
+    let db = open_with_expiry(&dir, &clock, Some(60));
+    assert!(db.get(b"old").is_err());
+    assert_eq!(db.get(b"new").unwrap(), b"3");
+
+    // Once merged away, it's gone for good
+    db.merge().unwrap();
+    drop(db);
+    let db = open(&dir, &clock);
+    assert!(db.get(b"old").is_err());
+    assert_eq!(sorted_keys(&db), [b"new".to_vec()]);
+}
@@ -0,0 +1,89 @@
+use std::{fs, path::Path};
+
+use bincode::{Encode, config, encode_to_vec};
+use bitcask::{BitcaskError, BitcaskHandler, upgrade};
+
+use common::{bitcask_error, read_write, temp_dir};
+
+mod common;
+
+/// Entry as written by the first version of the format.
+#[derive(Encode)]
+struct LegacyEntry {
+    crc_checksum: u32,
+    timestamp: u64,
+    key: Vec<u8>,
+    value: Vec<u8>,
+    is_deleted: bool,
+}
+
+impl LegacyEntry {
+    fn new(key: &[u8], value: &[u8], is_deleted: bool) -> Self {
+        let timestamp = 1_700_000_000_000;
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&u64::to_le_bytes(timestamp));
+        hasher.update(key);
+        hasher.update(value);
+        Self {
+            crc_checksum: hasher.finalize(),
+            timestamp,
+            key: key.to_vec(),
+            value: value.to_vec(),
+            is_deleted,
+        }
+    }
+}
+
+fn write_legacy_file(dir: &Path, id: usize, entries: &[LegacyEntry]) {
+    let bytes: Vec<u8> = entries
+        .iter()
+        .flat_map(|entry| encode_to_vec(entry, config::standard()).unwrap())
+        .collect();
+    fs::write(dir.join(format!("working_file_{id}")), bytes).unwrap();
+}
+
+#[test]
+fn legacy_datastore_is_rewritten_in_the_current_format() {
+    let dir = temp_dir("legacy");
+    write_legacy_file(
+        &dir,
+        0,
+        &[
+            LegacyEntry::new(b"a", b"1", false),
+            LegacyEntry::new(b"b", b"2", false),
+            LegacyEntry::new(b"a", b"11", false),
+        ],
+    );
+    write_legacy_file(&dir, 1, &[LegacyEntry::new(b"b", b" ", true), LegacyEntry::new(b"c", b"3", false)]);
+    // Opened by the first version, the working file stayed empty
+    write_legacy_file(&dir, 2, &[]);
+
+    let error = BitcaskHandler::open(&dir, read_write()).err().unwrap();
+    assert!(error.to_string().contains("bitcask upgrade"), "{error}");
+
+    assert_eq!(upgrade::upgrade(&dir, None).unwrap(), 5);
+    let db = BitcaskHandler::open(&dir, read_write()).unwrap();
+    assert_eq!(db.get(b"a").unwrap(), b"11");
+    assert!(db.get(b"b").is_err());
+    assert_eq!(db.get(b"c").unwrap(), b"3");
+    assert_eq!(db.len(), 2);
+    assert!(dir.join("legacy").join("working_file_1").exists());
+    drop(db);
+
+    // Nothing left to upgrade
+    assert!(upgrade::upgrade(&dir, None).is_err());
+}
+
+#[test]
+fn corrupted_legacy_entry_stops_the_upgrade() {
+    let dir = temp_dir("legacy-corrupted");
+    let mut corrupted = LegacyEntry::new(b"b", b"2", false);
+    corrupted.crc_checksum ^= 1;
+    write_legacy_file(&dir, 0, &[LegacyEntry::new(b"a", b"1", false), corrupted]);
+
+    let error = bitcask_error(upgrade::upgrade(&dir, None));
+    assert!(matches!(error, BitcaskError::Corrupted { .. }), "{error}");
+    // Left as it was
+    assert!(dir.join("working_file_0").exists());
+    assert!(!dir.join("legacy").exists());
+}